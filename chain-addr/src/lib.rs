@@ -5,13 +5,15 @@
 //! * First byte contains the discrimination information (1 bit) and the kind of address (7 bits)
 //! * Remaining bytes contains a kind specific encoding describe after.
 //!
-//! 4 kinds of address are currently supported:
+//! 5 kinds of address are currently supported:
 //!
 //! * Single: Just a (spending) public key using the ED25519 algorithm
 //! * Group: Same as single, but with a added (staking/group) public key
 //!   using the ED25519 algorithm.
 //! * Account: A account public key using the ED25519 algorithm
 //! * Multisig: a multisig account public key
+//! * Delegation: Same as account, but with an added stake pool identifier
+//!   the account is delegated to when receiving funds
 //!
 //! Single key:
 //!     DISCRIMINATION_BIT || SINGLE_KIND_TYPE (7 bits) || SPENDING_KEY
@@ -25,6 +27,9 @@
 //! Multisig key:
 //!     DISCRIMINATION_BIT || MULTISIG_KING_TYPE (7 bits) || MULTISIG_MERKLE_ROOT_PUBLIC_KEY
 //!
+//! Delegation key:
+//!     DISCRIMINATION_BIT || DELEGATION_KIND_TYPE (7 bits) || ACCOUNT_KEY || STAKE_POOL_ID
+//!
 //! Address human format is bech32 encoded
 //!
 
@@ -66,12 +71,15 @@ pub enum Discrimination {
 /// * Group address : an ed25519 spending public key followed by a group public key used for staking
 /// * Account address : an ed25519 stake public key
 /// * Multisig address : a multisig public key
+/// * Delegation address : an ed25519 stake public key followed by the identifier
+///   of the stake pool the account gets delegated to
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Kind {
     Single(PublicKey<Ed25519>),
     Group(PublicKey<Ed25519>, PublicKey<Ed25519>),
     Account(PublicKey<Ed25519>),
     Multisig([u8; 32]),
+    Delegation(PublicKey<Ed25519>, [u8; 32]),
 }
 
 /// Kind Type of an address
@@ -81,6 +89,7 @@ pub enum KindType {
     Group,
     Account,
     Multisig,
+    Delegation,
 }
 
 /// Size of a Single address
@@ -95,12 +104,16 @@ pub const ADDR_SIZE_ACCOUNT: usize = 33;
 /// Size of an Multisig Account address
 pub const ADDR_SIZE_MULTISIG: usize = 33;
 
+/// Size of a Delegation address
+pub const ADDR_SIZE_DELEGATION: usize = 65;
+
 const ADDR_KIND_LOW_SENTINEL: u8 = 0x2; /* anything under or equal to this is invalid */
 pub const ADDR_KIND_SINGLE: u8 = 0x3;
 pub const ADDR_KIND_GROUP: u8 = 0x4;
 pub const ADDR_KIND_ACCOUNT: u8 = 0x5;
 pub const ADDR_KIND_MULTISIG: u8 = 0x6;
-const ADDR_KIND_SENTINEL: u8 = 0x7; /* anything above or equal to this is invalid */
+pub const ADDR_KIND_DELEGATION: u8 = 0x7;
+const ADDR_KIND_SENTINEL: u8 = 0x8; /* anything above or equal to this is invalid */
 
 impl KindType {
     pub fn to_value(&self) -> u8 {
@@ -109,6 +122,7 @@ impl KindType {
             KindType::Group => ADDR_KIND_GROUP,
             KindType::Account => ADDR_KIND_ACCOUNT,
             KindType::Multisig => ADDR_KIND_MULTISIG,
+            KindType::Delegation => ADDR_KIND_DELEGATION,
         }
     }
 }
@@ -190,6 +204,12 @@ impl Address {
                 hash.copy_from_slice(&bytes[1..33]);
                 Kind::Multisig(hash)
             }
+            ADDR_KIND_DELEGATION => {
+                let stake_key = PublicKey::from_binary(&bytes[1..33])?;
+                let mut pool_id = [0u8; 32];
+                pool_id.copy_from_slice(&bytes[33..]);
+                Kind::Delegation(stake_key, pool_id)
+            }
             _ => unreachable!(),
         };
         Ok(Address(discr, kind))
@@ -202,6 +222,7 @@ impl Address {
             Kind::Group(_, _) => ADDR_SIZE_GROUP,
             Kind::Account(_) => ADDR_SIZE_ACCOUNT,
             Kind::Multisig(_) => ADDR_SIZE_MULTISIG,
+            Kind::Delegation(_, _) => ADDR_SIZE_DELEGATION,
         }
     }
 
@@ -212,6 +233,7 @@ impl Address {
             Kind::Group(_, _) => KindType::Group,
             Kind::Account(_) => KindType::Account,
             Kind::Multisig(_) => KindType::Multisig,
+            Kind::Delegation(_, _) => KindType::Delegation,
         }
     }
 
@@ -245,6 +267,7 @@ impl Address {
             Kind::Group(ref pk, _) => Some(pk),
             Kind::Account(ref pk) => Some(pk),
             Kind::Multisig(_) => None,
+            Kind::Delegation(ref pk, _) => Some(pk),
         }
     }
 }
@@ -294,6 +317,12 @@ fn is_valid_data(bytes: &[u8]) -> Result<(Discrimination, KindType), Error> {
             }
             KindType::Multisig
         }
+        ADDR_KIND_DELEGATION => {
+            if bytes.len() != ADDR_SIZE_DELEGATION {
+                return Err(Error::InvalidAddress);
+            }
+            KindType::Delegation
+        }
         _ => return Err(Error::InvalidKind),
     };
     Ok((get_discrimination_value(bytes[0]), kty))
@@ -393,6 +422,10 @@ impl PropertySerialize for Address {
             }
             Kind::Account(stake_key) => codec.write_all(stake_key.as_ref())?,
             Kind::Multisig(hash) => codec.write_all(&hash[..])?,
+            Kind::Delegation(stake_key, pool_id) => {
+                codec.write_all(stake_key.as_ref())?;
+                codec.write_all(&pool_id[..])?;
+            }
         };
 
         Ok(())
@@ -451,6 +484,16 @@ impl property::Deserialize for Address {
                 codec.read_exact(&mut bytes)?;
                 Kind::Multisig(bytes)
             }
+            ADDR_KIND_DELEGATION => {
+                let mut bytes = [0u8; 32];
+                codec.read_exact(&mut bytes)?;
+                let stake_key = PublicKey::from_binary(&bytes[..]).map_err(|err| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, Box::new(err))
+                })?;
+                let mut pool_id = [0u8; 32];
+                codec.read_exact(&mut pool_id)?;
+                Kind::Delegation(stake_key, pool_id)
+            }
             _ => unreachable!(),
         };
         Ok(Address(discr, kind))
@@ -494,6 +537,12 @@ impl Readable for Address {
                 let bytes = <[u8; 32]>::read(buf)?;
                 Kind::Multisig(bytes)
             }
+            ADDR_KIND_DELEGATION => {
+                let bytes = <[u8; 32]>::read(buf)?;
+                let stake_key = PublicKey::from_binary(&bytes[..]).map_err(chain_crypto_err)?;
+                let pool_id = <[u8; 32]>::read(buf)?;
+                Kind::Delegation(stake_key, pool_id)
+            }
             n => return Err(ReadError::UnknownTag(n as u32)),
         };
         Ok(Address(discr, kind))
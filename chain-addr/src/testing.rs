@@ -14,11 +14,12 @@ impl Arbitrary for Discrimination {
 
 impl Arbitrary for KindType {
     fn arbitrary<G: Gen>(g: &mut G) -> Self {
-        match u8::arbitrary(g) % 4 {
+        match u8::arbitrary(g) % 5 {
             0 => KindType::Single,
             1 => KindType::Group,
             2 => KindType::Account,
             3 => KindType::Multisig,
+            4 => KindType::Delegation,
             _ => unreachable!(),
         }
     }
@@ -56,6 +57,7 @@ impl Arbitrary for Address {
                 let h = arbitrary_32bytes(g);
                 Kind::Multisig(h)
             }
+            KindType::Delegation => Kind::Delegation(arbitrary_public_key(g), arbitrary_32bytes(g)),
         };
         Address(discrimination, kind)
     }
@@ -63,7 +65,7 @@ impl Arbitrary for Address {
 
 impl Arbitrary for Kind {
     fn arbitrary<G: Gen>(g: &mut G) -> Self {
-        match u8::arbitrary(g) % 4 {
+        match u8::arbitrary(g) % 5 {
             0 => Kind::Single(arbitrary_public_key(g)),
             1 => Kind::Group(arbitrary_public_key(g), arbitrary_public_key(g)),
             2 => Kind::Account(arbitrary_public_key(g)),
@@ -71,6 +73,7 @@ impl Arbitrary for Kind {
                 let h = arbitrary_32bytes(g);
                 Kind::Multisig(h)
             }
+            4 => Kind::Delegation(arbitrary_public_key(g), arbitrary_32bytes(g)),
             _ => unreachable!(),
         }
     }
@@ -41,6 +41,8 @@ pub enum ReadError {
     StructureInvalid(String),
     /// Unknown enumeration tag
     UnknownTag(u32),
+    /// Declaring more participants than the limit
+    TooManyParticipants(usize, usize),
 }
 
 impl fmt::Display for ReadError {
@@ -59,6 +61,11 @@ impl fmt::Display for ReadError {
             ),
             ReadError::StructureInvalid(s) => write!(f, "Structure invalid: {}", s),
             ReadError::UnknownTag(t) => write!(f, "Unknown tag: {}", t),
+            ReadError::TooManyParticipants(n, limit) => write!(
+                f,
+                "Declaring {} participants above the limit of {}",
+                n, limit
+            ),
         }
     }
 }
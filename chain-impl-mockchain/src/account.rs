@@ -1,10 +1,12 @@
 use crate::accounting::account;
 use crate::key::{deserialize_public_key, serialize_public_key};
+use crate::stake::StakePoolId;
+use crate::value::Value;
 use chain_core::{
     mempack::{ReadBuf, ReadError, Readable},
     property,
 };
-use chain_crypto::{Ed25519, PublicKey};
+use chain_crypto::{AsymmetricPublicKey, Ed25519, PublicKey};
 
 pub use account::{LedgerError, SpendingCounter};
 
@@ -14,6 +16,13 @@ pub type AccountAlg = Ed25519;
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Identifier(PublicKey<AccountAlg>);
 
+impl Identifier {
+    /// Check whether this identifier designates the given public key
+    pub fn matches_public_key(&self, public_key: &PublicKey<AccountAlg>) -> bool {
+        &self.0 == public_key
+    }
+}
+
 impl From<PublicKey<AccountAlg>> for Identifier {
     fn from(pk: PublicKey<AccountAlg>) -> Self {
         Identifier(pk)
@@ -32,15 +41,32 @@ impl AsRef<PublicKey<AccountAlg>> for Identifier {
     }
 }
 
+/// Size in bytes of the public key currently accepted as an account identifier
+pub const IDENTIFIER_SIZE: usize = <AccountAlg as AsymmetricPublicKey>::PUBLIC_KEY_SIZE;
+
+/// The identifier is serialized prefixed by its length on one byte, so that
+/// future key types of a different size can be represented. For now only
+/// `IDENTIFIER_SIZE` is accepted on read.
 impl property::Serialize for Identifier {
     type Error = std::io::Error;
     fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
-        serialize_public_key(&self.0, writer)
+        use chain_core::packer::Codec;
+
+        let mut codec = Codec::new(writer);
+        codec.put_u8(IDENTIFIER_SIZE as u8)?;
+        serialize_public_key(&self.0, &mut codec)
     }
 }
 
 impl Readable for Identifier {
     fn read<'a>(reader: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let len = reader.get_u8()? as usize;
+        if len != IDENTIFIER_SIZE {
+            return Err(ReadError::StructureInvalid(format!(
+                "account identifier size invalid, expected {} got {}",
+                IDENTIFIER_SIZE, len
+            )));
+        }
         deserialize_public_key(reader).map(Identifier)
     }
 }
@@ -48,6 +74,23 @@ impl Readable for Identifier {
 /// The public ledger of all accounts associated with their current state
 pub type Ledger = account::Ledger<Identifier, ()>;
 
+/// Whether an account is spent from with a single key or with the
+/// signatures required by a multisig declaration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountKind {
+    Single,
+    Multisig,
+}
+
+/// The state of an account, as presented to its owner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountState {
+    pub kind: AccountKind,
+    pub value: Value,
+    pub counter: SpendingCounter,
+    pub delegation: Option<StakePoolId>,
+}
+
 impl std::fmt::Display for Identifier {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         self.0.fmt(f)
@@ -57,8 +100,9 @@ impl std::fmt::Display for Identifier {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chain_core::property::Serialize as _;
     use chain_crypto::{Ed25519, KeyPair};
-    use quickcheck::{Arbitrary, Gen};
+    use quickcheck::{Arbitrary, Gen, TestResult};
 
     impl Arbitrary for Identifier {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -66,4 +110,33 @@ mod test {
             Identifier::from(kp.into_keys().1)
         }
     }
+
+    quickcheck! {
+        fn identifier_serialization_bijection(id: Identifier) -> TestResult {
+            property::testing::serialization_bijection_r(id)
+        }
+
+        fn identifier_matches_only_its_public_key(id: Identifier, other: Identifier) -> TestResult {
+            if id == other {
+                return TestResult::discard();
+            }
+            TestResult::from_bool(
+                id.matches_public_key(id.as_ref()) && !id.matches_public_key(other.as_ref()),
+            )
+        }
+
+        fn identifier_unexpected_size_is_rejected(id: Identifier, len: u8) -> TestResult {
+            if len as usize == IDENTIFIER_SIZE {
+                return TestResult::discard();
+            }
+            let mut bytes = id.serialize_as_vec().unwrap();
+            bytes[0] = len;
+            let mut buf = ReadBuf::from(&bytes);
+            match Identifier::read(&mut buf) {
+                Err(ReadError::StructureInvalid(_)) => TestResult::passed(),
+                Err(error) => TestResult::error(format!("unexpected error: {:?}", error)),
+                Ok(_) => TestResult::error("identifier with invalid size accepted"),
+            }
+        }
+    }
 }
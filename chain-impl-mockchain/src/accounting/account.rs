@@ -4,6 +4,7 @@
 //! which contains a non negative value representing your balance with the
 //! identifier of this account as key.
 
+use crate::date::{BlockDate, Epoch};
 use crate::stake::StakePoolId;
 use crate::value::*;
 use imhamt::{Hamt, HamtIter, InsertError, UpdateError};
@@ -16,6 +17,8 @@ custom_error! {
         NonExistent = "Account does not exist",
         AlreadyExists = "Account already exists",
         NeedTotalWithdrawal = "Operation counter reached its maximum and next operation must be full withdrawal",
+        SpendingCounterExhausted = "Spending counter reached its maximum value",
+        LockShortened { until: Epoch, requested: Epoch } = "Account is locked until epoch {until}, the lock cannot be shortened to epoch {requested}",
         NonZero = "Removed account is not empty",
         ValueError{ source: ValueError } = "Value calculation failed",
 }
@@ -37,11 +40,15 @@ impl From<InsertError> for LedgerError {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AccountState<Extra> {
     pub counter: SpendingCounter,
     pub delegation: Option<StakePoolId>,
     pub value: Value,
+    /// The account cannot be spent from before this epoch
+    pub locked_until: Option<Epoch>,
+    /// The date of the last change of the balance, if recorded
+    pub last_change: Option<BlockDate>,
     pub extra: Extra,
 }
 
@@ -52,6 +59,8 @@ impl<Extra> AccountState<Extra> {
             counter: SpendingCounter(0),
             delegation: None,
             value: v,
+            locked_until: None,
+            last_change: None,
             extra: e,
         }
     }
@@ -73,6 +82,11 @@ impl<Extra> AccountState<Extra> {
     pub fn get_counter(&self) -> u32 {
         self.counter.into()
     }
+
+    /// Check if the account cannot be spent from at the given epoch
+    pub fn is_locked_at(&self, epoch: Epoch) -> bool {
+        self.locked_until.map_or(false, |until| epoch < until)
+    }
 }
 
 impl<Extra: Clone> AccountState<Extra> {
@@ -91,25 +105,27 @@ impl<Extra: Clone> AccountState<Extra> {
     /// Note that this *also* increment the counter, as this function would be usually call
     /// for spending.
     ///
-    /// If the counter is also reaching the extremely rare of max, we only authorise
-    /// a total withdrawal of fund otherwise the fund will be stuck forever in limbo.
-    pub fn sub(&self, v: Value) -> Result<Option<Self>, LedgerError> {
+    /// The counter never goes past `max`: the spending bringing it to `max` must be a
+    /// full withdrawal, and the emptied account is kept with its counter at `max` rather
+    /// than removed, so none of its previous transactions can be replayed. Any spending
+    /// after that is refused.
+    pub fn sub(&self, v: Value, max: SpendingCounter) -> Result<Option<Self>, LedgerError> {
         let new_value = (self.value - v)?;
-        match self.counter.increment() {
-            None => {
-                if new_value == Value::zero() {
-                    Ok(None)
-                } else {
-                    Err(LedgerError::NeedTotalWithdrawal)
-                }
-            }
-            Some(new_counter) => Ok(Some(Self {
-                counter: new_counter,
-                delegation: self.delegation.clone(),
-                value: new_value,
-                extra: self.extra.clone(),
-            })),
+        if self.counter.0 >= max.0 {
+            return Err(LedgerError::SpendingCounterExhausted);
+        }
+        let new_counter = self.counter.checked_increment()?;
+        if new_counter == max && new_value != Value::zero() {
+            return Err(LedgerError::NeedTotalWithdrawal);
         }
+        Ok(Some(Self {
+            counter: new_counter,
+            delegation: self.delegation.clone(),
+            value: new_value,
+            locked_until: self.locked_until,
+            last_change: self.last_change,
+            extra: self.extra.clone(),
+        }))
     }
 
     /// Set delegation
@@ -118,6 +134,20 @@ impl<Extra: Clone> AccountState<Extra> {
         st.delegation = delegation;
         st
     }
+
+    /// Set the epoch until which the account is locked
+    pub fn set_lock(&self, locked_until: Option<Epoch>) -> Self {
+        let mut st = self.clone();
+        st.locked_until = locked_until;
+        st
+    }
+
+    /// Set the date of the last change of the balance
+    pub fn set_last_change(&self, date: BlockDate) -> Self {
+        let mut st = self.clone();
+        st.last_change = Some(date);
+        st
+    }
 }
 
 /// Spending counter associated to an account.
@@ -138,11 +168,24 @@ impl SpendingCounter {
         self.0.checked_add(1).map(SpendingCounter)
     }
 
+    /// Return the next counter value, or an error if the counter
+    /// would overflow instead of wrapping back to zero.
+    pub fn checked_increment(&self) -> Result<Self, LedgerError> {
+        self.increment()
+            .ok_or(LedgerError::SpendingCounterExhausted)
+    }
+
     pub fn to_bytes(&self) -> [u8; 4] {
         self.0.to_le_bytes()
     }
 }
 
+impl std::fmt::Display for SpendingCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl From<u32> for SpendingCounter {
     fn from(v: u32) -> Self {
         SpendingCounter(v)
@@ -202,6 +245,44 @@ impl<ID: Clone + Eq + Hash, Extra: Clone> Ledger<ID, Extra> {
             .map_err(|e| e.into())
     }
 
+    /// Lock an account of this ledger until the given epoch
+    ///
+    /// A lock can only be extended: an epoch earlier than the one the
+    /// account is already locked until is refused.
+    pub fn set_lock(&self, identifier: &ID, locked_until: Epoch) -> Result<Self, LedgerError> {
+        self.0
+            .update(identifier, |st| match st.locked_until {
+                Some(until) if locked_until < until => Err(LedgerError::LockShortened {
+                    until,
+                    requested: locked_until,
+                }),
+                _ => Ok(Some(st.set_lock(Some(locked_until)))),
+            })
+            .map(Ledger)
+            .map_err(|e| e.into())
+    }
+
+    /// Record the date at which the balance of an account changed
+    pub fn set_last_change(&self, identifier: &ID, date: BlockDate) -> Result<Self, LedgerError> {
+        self.0
+            .update(identifier, |st| Ok(Some(st.set_last_change(date))))
+            .map(Ledger)
+            .map_err(|e| e.into())
+    }
+
+    /// Set the state of an account back to the given one, or remove the
+    /// account if there is none
+    pub(crate) fn restore(&self, identifier: &ID, state: Option<AccountState<Extra>>) -> Self {
+        let hamt = self.0.remove(identifier).unwrap_or_else(|_| self.0.clone());
+        match state {
+            None => Ledger(hamt),
+            Some(state) => Ledger(
+                hamt.insert(identifier.clone(), state)
+                    .expect("account was removed"),
+            ),
+        }
+    }
+
     /// check if an account already exist
     #[inline]
     pub fn exists(&self, identifier: &ID) -> bool {
@@ -244,10 +325,14 @@ impl<ID: Clone + Eq + Hash, Extra: Clone> Ledger<ID, Extra> {
     /// Subtract value to an existing account.
     ///
     /// If the account doesn't exist, or that the value would become negative, errors out.
+    ///
+    /// The spending counter of the account is not incremented past `max_counter`,
+    /// see `AccountState::sub`.
     pub fn remove_value(
         &self,
         identifier: &ID,
         value: Value,
+        max_counter: SpendingCounter,
     ) -> Result<(Self, SpendingCounter), LedgerError> {
         // ideally we don't need 2 calls to do this
         let counter = self
@@ -255,7 +340,7 @@ impl<ID: Clone + Eq + Hash, Extra: Clone> Ledger<ID, Extra> {
             .lookup(identifier)
             .map_or(Err(LedgerError::NonExistent), |st| Ok(st.counter))?;
         self.0
-            .update(identifier, |st| st.sub(value))
+            .update(identifier, |st| st.sub(value, max_counter))
             .map(|ledger| (Ledger(ledger), counter))
             .map_err(|e| e.into())
     }
@@ -280,3 +365,69 @@ impl<ID: Clone + Eq + Hash, Extra: Clone> std::iter::FromIterator<(ID, AccountSt
         Ledger(Hamt::from_iter(iter))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn spending_counter_increment_near_max() {
+        let counter = SpendingCounter::from(std::u32::MAX - 1);
+        let counter = counter.checked_increment().unwrap();
+        assert_eq!(u32::from(counter), std::u32::MAX);
+        assert_eq!(
+            counter.checked_increment(),
+            Err(LedgerError::SpendingCounterExhausted)
+        );
+    }
+
+    fn ledger_with_counter(counter: u32) -> Ledger<u32, ()> {
+        let ledger: Ledger<u32, ()> = Ledger::new().add_account(&0, Value(100), ()).unwrap();
+        Ledger(
+            ledger
+                .0
+                .update(&0, |st| {
+                    let mut st = st.clone();
+                    st.counter = SpendingCounter::from(counter);
+                    Ok::<_, LedgerError>(Some(st))
+                })
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    pub fn last_spending_before_max_counter_must_be_total_withdrawal() {
+        let max = SpendingCounter::from(10);
+        let ledger = ledger_with_counter(9);
+
+        assert_eq!(
+            ledger.remove_value(&0, Value(1), max).map(|_| ()),
+            Err(LedgerError::NeedTotalWithdrawal)
+        );
+
+        // the emptied account is kept so its counter is not reset to zero
+        let (ledger, counter) = ledger.remove_value(&0, Value(100), max).unwrap();
+        assert_eq!(u32::from(counter), 9);
+        let state = ledger.get_state(&0).unwrap();
+        assert_eq!(state.get_counter(), 10);
+        assert_eq!(state.value(), Value::zero());
+    }
+
+    #[test]
+    pub fn spending_from_exhausted_counter_is_refused() {
+        for (counter, max) in &[(10, 10), (std::u32::MAX, std::u32::MAX)] {
+            let ledger = ledger_with_counter(*counter);
+            let max = SpendingCounter::from(*max);
+
+            for value in &[Value(1), Value(100)] {
+                assert_eq!(
+                    ledger.remove_value(&0, *value, max).map(|_| ()),
+                    Err(LedgerError::SpendingCounterExhausted)
+                );
+            }
+            let state = ledger.get_state(&0).unwrap();
+            assert_eq!(state.get_counter(), *counter);
+            assert_eq!(state.value(), Value(100));
+        }
+    }
+}
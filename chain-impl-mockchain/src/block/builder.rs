@@ -3,7 +3,8 @@
 
 use crate::block::{
     BftProof, Block, BlockContentHash, BlockContents, BlockDate, BlockId, BlockVersion,
-    ChainLength, Common, Fragment, GenesisPraosProof, Header, KESSignature, Proof,
+    ChainLength, Common, Fragment, GenesisPraosHeaderToSign, GenesisPraosProof, Header,
+    KESSignature, Proof,
 };
 use crate::key::make_signature;
 use crate::leadership;
@@ -142,11 +143,11 @@ impl BlockBuilder {
         assert_ne!(self.common.chain_length, ChainLength(0));
         self.finalize_common(BlockVersion::KesVrfproof);
 
+        let to_sign = GenesisPraosHeaderToSign::new(&self.common, node_id, &vrf_proof);
         let genesis_praos_proof = GenesisPraosProof {
             node_id: node_id.clone(),
             vrf_proof: vrf_proof,
-            // ! SECURITY FIXME ! : also include id and vrf proof.
-            kes_proof: KESSignature(make_signature(kes_signing_key, &self.common)),
+            kes_proof: KESSignature(make_signature(kes_signing_key, &to_sign)),
         };
         self.make_block(Proof::GenesisPraos(genesis_praos_proof))
     }
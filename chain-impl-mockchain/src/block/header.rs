@@ -9,7 +9,7 @@ use crate::key::{
 use crate::leadership::{bft, genesis};
 use crate::stake::StakePoolId;
 use chain_core::{
-    mempack::{ReadBuf, ReadError, Readable},
+    mempack::{read_from_raw, ReadBuf, ReadError, Readable},
     property,
 };
 use chain_crypto::{
@@ -37,6 +37,16 @@ pub struct ChainLength(pub(crate) u32);
 /// FIXME SECURITY : we want to sign Common + everything in proof except the signature
 pub type HeaderToSign = Common;
 
+/// The data signed by the KES key of a genesis praos block: the common
+/// part of the header along with the node id and the VRF proof, so the
+/// signature cannot be replayed with a different leader.
+#[derive(Debug, Clone)]
+pub struct GenesisPraosHeaderToSign {
+    pub(crate) common: Common,
+    pub(crate) node_id: StakePoolId,
+    pub(crate) vrf_proof: genesis::Witness,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BftProof {
     pub(crate) leader_id: bft::LeaderId,
@@ -54,7 +64,7 @@ pub struct GenesisPraosProof {
 }
 
 #[derive(Debug, Clone)]
-pub struct KESSignature(pub(crate) Signature<HeaderToSign, SumEd25519_12>);
+pub struct KESSignature(pub(crate) Signature<GenesisPraosHeaderToSign, SumEd25519_12>);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Proof {
@@ -195,6 +205,39 @@ impl property::Serialize for Common {
     }
 }
 
+fn serialize_vrf_proof<W: std::io::Write>(
+    vrf_proof: &genesis::Witness,
+    mut writer: W,
+) -> Result<(), std::io::Error> {
+    let mut buf = [0; <Curve25519_2HashDH as VerifiableRandomFunction>::VERIFIED_RANDOM_SIZE];
+    vrf_proof.to_bytes(&mut buf);
+    writer.write_all(&buf)
+}
+
+impl GenesisPraosHeaderToSign {
+    pub(crate) fn new(
+        common: &Common,
+        node_id: &StakePoolId,
+        vrf_proof: &genesis::Witness,
+    ) -> Self {
+        GenesisPraosHeaderToSign {
+            common: common.clone(),
+            node_id: node_id.clone(),
+            vrf_proof: vrf_proof.clone(),
+        }
+    }
+}
+
+impl property::Serialize for GenesisPraosHeaderToSign {
+    type Error = std::io::Error;
+
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        self.common.serialize(&mut writer)?;
+        self.node_id.serialize(&mut writer)?;
+        serialize_vrf_proof(&self.vrf_proof, writer)
+    }
+}
+
 impl property::Serialize for Header {
     type Error = std::io::Error;
 
@@ -209,12 +252,7 @@ impl property::Serialize for Header {
             }
             Proof::GenesisPraos(genesis_praos_proof) => {
                 genesis_praos_proof.node_id.serialize(&mut writer)?;
-                {
-                    let mut buf =
-                        [0; <Curve25519_2HashDH as VerifiableRandomFunction>::VERIFIED_RANDOM_SIZE];
-                    genesis_praos_proof.vrf_proof.to_bytes(&mut buf);
-                    writer.write_all(&buf)?;
-                }
+                serialize_vrf_proof(&genesis_praos_proof.vrf_proof, &mut writer)?;
                 serialize_signature(&genesis_praos_proof.kes_proof.0, writer)?;
             }
         }
@@ -284,6 +322,20 @@ impl Readable for Header {
     }
 }
 
+/// Read a header on its own, independently of the block contents, as it
+/// is done when the headers are fetched first during synchronisation.
+///
+/// The whole input is expected to be the serialized header.
+impl property::Deserialize for Header {
+    type Error = std::io::Error;
+
+    fn deserialize<R: std::io::BufRead>(mut reader: R) -> Result<Self, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        read_from_raw(&bytes)
+    }
+}
+
 impl property::Header for Header {
     type Id = HeaderHash;
     type Date = BlockDate;
@@ -315,10 +367,46 @@ mod test {
     use lazy_static::lazy_static;
     use quickcheck::{Arbitrary, Gen, TestResult};
 
+    fn header_only_roundtrip(
+        mut common: Common,
+        version: BlockVersion,
+        proof: Proof,
+    ) -> TestResult {
+        use chain_core::property::{Deserialize, Serialize};
+
+        common.any_block_version = version.into();
+        let header = Header { common, proof };
+        let bytes = match header.serialize_as_vec() {
+            Err(error) => return TestResult::error(format!("serialization: {}", error)),
+            Ok(bytes) => bytes,
+        };
+        let decoded = match Header::deserialize(&bytes[..]) {
+            Err(error) => return TestResult::error(format!("deserialization: {}", error)),
+            Ok(decoded) => decoded,
+        };
+        TestResult::from_bool(
+            decoded.block_content_hash() == header.block_content_hash()
+                && decoded.common.block_content_size == header.common.block_content_size
+                && decoded == header,
+        )
+    }
+
     quickcheck! {
         fn header_serialization_bijection(b: Header) -> TestResult {
             property::testing::serialization_bijection_r(b)
         }
+
+        fn header_only_roundtrip_genesis(common: Common) -> TestResult {
+            header_only_roundtrip(common, BlockVersion::Genesis, Proof::None)
+        }
+
+        fn header_only_roundtrip_bft(common: Common, proof: BftProof) -> TestResult {
+            header_only_roundtrip(common, BlockVersion::Ed25519Signed, Proof::Bft(proof))
+        }
+
+        fn header_only_roundtrip_genesis_praos(common: Common, proof: GenesisPraosProof) -> TestResult {
+            header_only_roundtrip(common, BlockVersion::KesVrfproof, Proof::GenesisPraos(proof))
+        }
     }
 
     impl Arbitrary for AnyBlockVersion {
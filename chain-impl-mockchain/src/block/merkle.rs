@@ -0,0 +1,121 @@
+//! Merkle tree over the fragment ids of a block's contents
+//!
+//! The root of this tree is the block content hash, which allows a light
+//! client to check that a given fragment is part of a block without
+//! downloading the whole contents, only the fragment id and a proof.
+
+use crate::fragment::FragmentId;
+use crate::key::Hash;
+
+use super::header::BlockContentHash;
+
+const LEAF_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+
+/// Step from a hash being proven to its parent in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MerkleStep {
+    /// the sibling hash is on the left
+    Left(Hash),
+    /// the sibling hash is on the right
+    Right(Hash),
+    /// odd node out of its level, without sibling
+    Alone,
+}
+
+/// Proof that a fragment id is a leaf of the tree whose root is
+/// the block content hash.
+///
+/// The path goes from the leaf up to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub path: Vec<MerkleStep>,
+}
+
+/// Leaves and nodes are hashed with different prefixes, so that a leaf
+/// cannot be taken for a node and the other way around.
+fn hash_leaf(fragment_id: &FragmentId) -> Hash {
+    let mut bytes = Vec::with_capacity(1 + 32);
+    bytes.push(LEAF_TAG);
+    bytes.extend_from_slice(fragment_id.as_ref());
+    Hash::hash_bytes(&bytes)
+}
+
+fn hash_single(node: &Hash) -> Hash {
+    let mut bytes = Vec::with_capacity(1 + 32);
+    bytes.push(NODE_TAG);
+    bytes.extend_from_slice(node.as_ref());
+    Hash::hash_bytes(&bytes)
+}
+
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut bytes = Vec::with_capacity(1 + 2 * 32);
+    bytes.push(NODE_TAG);
+    bytes.extend_from_slice(left.as_ref());
+    bytes.extend_from_slice(right.as_ref());
+    Hash::hash_bytes(&bytes)
+}
+
+/// compute the next level of the tree, an odd node out is hashed alone
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            [single] => hash_single(single),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Compute the root of the tree of the given fragment ids
+///
+/// An empty tree has for root the hash of no bytes
+pub(super) fn root(fragment_ids: Vec<FragmentId>) -> BlockContentHash {
+    if fragment_ids.is_empty() {
+        return Hash::hash_bytes(&[]);
+    }
+    let mut level: Vec<Hash> = fragment_ids.iter().map(hash_leaf).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Create the proof that the fragment id at `index` belongs to the tree
+pub(super) fn proof(fragment_ids: Vec<FragmentId>, mut index: usize) -> MerkleProof {
+    let mut path = Vec::new();
+    let mut level: Vec<Hash> = fragment_ids.iter().map(hash_leaf).collect();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        let step = if sibling >= level.len() {
+            MerkleStep::Alone
+        } else if sibling < index {
+            MerkleStep::Left(level[sibling])
+        } else {
+            MerkleStep::Right(level[sibling])
+        };
+        path.push(step);
+        level = next_level(&level);
+        index /= 2;
+    }
+    MerkleProof { path }
+}
+
+/// Verify that the fragment of the given id is included in the block
+/// contents of the given content hash.
+pub fn verify_inclusion(
+    proof: &MerkleProof,
+    content_hash: &BlockContentHash,
+    fragment_id: &FragmentId,
+) -> bool {
+    let computed = proof
+        .path
+        .iter()
+        .fold(hash_leaf(fragment_id), |current, step| match step {
+            MerkleStep::Left(sibling) => hash_node(sibling, &current),
+            MerkleStep::Right(sibling) => hash_node(&current, sibling),
+            MerkleStep::Alone => hash_single(&current),
+        });
+    &computed == content_hash
+}
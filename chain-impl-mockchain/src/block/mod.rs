@@ -1,8 +1,7 @@
 //! Representation of the block in the mockchain.
-use crate::fragment::{Fragment, FragmentRaw};
-use crate::key::Hash;
+use crate::fragment::{ConfigParams, Fragment, FragmentId, FragmentRaw, Message};
 use chain_core::mempack::{read_from_raw, ReadBuf, ReadError, Readable};
-use chain_core::property::{self, Serialize};
+use chain_core::property::{self, Message as _, Serialize};
 
 use std::slice;
 
@@ -10,6 +9,7 @@ mod builder;
 //mod cstruct;
 mod header;
 mod headerraw;
+mod merkle;
 mod version;
 
 pub use self::version::{AnyBlockVersion, BlockVersion, ConsensusVersion};
@@ -18,9 +18,11 @@ pub use self::builder::BlockBuilder;
 
 pub use self::header::{
     BftProof, BftSignature, BlockContentHash, BlockContentSize, BlockId, ChainLength, Common,
-    GenesisPraosProof, Header, HeaderContentEvalContext, HeaderHash, KESSignature, Proof,
+    GenesisPraosHeaderToSign, GenesisPraosProof, Header, HeaderContentEvalContext, HeaderHash,
+    KESSignature, Proof,
 };
 pub use self::headerraw::HeaderRaw;
+pub use self::merkle::{verify_inclusion, MerkleProof, MerkleStep};
 pub use self::version::*;
 
 pub use crate::date::{BlockDate, BlockDateParseError, Epoch, SlotId};
@@ -60,19 +62,70 @@ impl BlockContents {
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a Fragment> {
         self.0.iter()
     }
+
+    /// Compute the content hash, the merkle root of the fragment ids,
+    /// and the serialized size of the contents.
     pub fn compute_hash_size(&self) -> (BlockContentHash, usize) {
-        let mut bytes = Vec::with_capacity(4096);
+        let mut size = 0;
+        let mut ids = Vec::with_capacity(self.0.len());
 
         for message in self.iter() {
-            message.to_raw().serialize(&mut bytes).unwrap();
+            let raw = message.to_raw();
+            size += raw.size_bytes_plus_size();
+            ids.push(raw.id());
         }
 
-        let hash = Hash::hash_bytes(&bytes);
-        (hash, bytes.len())
+        (merkle::root(ids), size)
+    }
+
+    /// Check that these contents hash to the given content hash, as
+    /// found in the header of the block carrying them.
+    pub fn verify_hash(&self, expected: &BlockContentHash) -> bool {
+        let (content_hash, _) = self.compute_hash_size();
+        &content_hash == expected
+    }
+
+    /// Create the proof that the fragment of the given id is part of
+    /// these contents, to be checked with `verify_inclusion` against
+    /// the block content hash.
+    ///
+    /// Returns `None` if no such fragment is present.
+    pub fn inclusion_proof(&self, fragment_id: &FragmentId) -> Option<MerkleProof> {
+        let ids: Vec<FragmentId> = self.iter().map(|message| message.id()).collect();
+        let index = ids.iter().position(|id| id == fragment_id)?;
+        Some(merkle::proof(ids, index))
+    }
+
+    /// Count the witnesses of the transaction and certificate fragments
+    /// of these contents, that is the number of signatures to verify
+    /// when validating them.
+    pub fn total_witness_count(&self) -> usize {
+        self.iter()
+            .map(|message| match message {
+                Fragment::Transaction(tx) => tx.witnesses.len(),
+                Fragment::Certificate(tx) => tx.witnesses.len(),
+                Fragment::Initial(_)
+                | Fragment::OldUtxoDeclaration(_)
+                | Fragment::UpdateProposal(_)
+                | Fragment::UpdateVote(_) => 0,
+            })
+            .sum()
     }
 }
 
 impl Block {
+    /// Compute the size of the block once serialized for the wire,
+    /// the header and the contents included.
+    pub fn serialized_size(&self) -> usize {
+        let header_size = 2 + self.header.to_raw().unwrap().as_ref().len();
+        let contents_size: usize = self
+            .contents
+            .iter()
+            .map(|message| message.to_raw().size_bytes_plus_size())
+            .sum();
+        header_size + contents_size
+    }
+
     pub fn is_consistent(&self) -> bool {
         let (content_hash, content_size) = self.contents.compute_hash_size();
 
@@ -81,6 +134,23 @@ impl Block {
     }
 }
 
+/// Compute the hash of the genesis block made of the given messages,
+/// which is the `block0_initial_hash` of the ledger created from them.
+pub fn compute_block0_hash(messages: &[Message]) -> HeaderHash {
+    let mut builder = BlockBuilder::new();
+    builder.messages(messages.iter().cloned());
+    builder.make_genesis_block().header.hash()
+}
+
+/// Compute the hash of the genesis block made of the initial message
+/// holding the given configuration, followed by the other given messages.
+pub fn genesis_hash_for(config: &ConfigParams, initial: &[Message]) -> HeaderHash {
+    let mut messages = Vec::with_capacity(initial.len() + 1);
+    messages.push(Fragment::Initial(config.clone()));
+    messages.extend(initial.iter().cloned());
+    compute_block0_hash(&messages)
+}
+
 impl property::Block for Block {
     type Id = BlockId;
     type Date = BlockDate;
@@ -208,6 +278,10 @@ impl property::HasHeader for Block {
 mod test {
 
     use super::*;
+    use crate::certificate::Certificate;
+    use crate::key::Hash;
+    use crate::transaction::{AuthenticatedTransaction, NoExtra};
+    use chain_addr::Address;
     use quickcheck::{Arbitrary, Gen, TestResult};
 
     quickcheck! {
@@ -222,6 +296,103 @@ mod test {
         fn block_serialization_bijection(b: Block) -> TestResult {
             property::testing::serialization_bijection(b)
         }
+
+        fn block_serialized_size_matches_serialization(b: Block) -> TestResult {
+            TestResult::from_bool(b.serialized_size() == b.serialize_as_vec().unwrap().len())
+        }
+
+        fn block0_hash_matches_genesis_block(fragments: (Fragment, Fragment)) -> bool {
+            let messages = vec![fragments.0, fragments.1];
+            let mut builder = BlockBuilder::new();
+            builder.messages(messages.clone());
+            let block0 = builder.make_genesis_block();
+            compute_block0_hash(&messages) == property::Block::id(&block0)
+        }
+
+        fn genesis_hash_for_matches_genesis_block(config: ConfigParams, fragments: (Fragment, Fragment)) -> bool {
+            let mut builder = BlockBuilder::new();
+            builder.message(Fragment::Initial(config.clone()));
+            builder.messages(vec![fragments.0.clone(), fragments.1.clone()]);
+            let block0 = builder.make_genesis_block();
+            genesis_hash_for(&config, &[fragments.0, fragments.1]) == property::Block::id(&block0)
+        }
+
+        fn verify_hash_detects_a_tampered_fragment(fragments: (Fragment, Fragment, Fragment)) -> TestResult {
+            let (f1, f2, f3) = fragments;
+            if f2.id() == f3.id() {
+                return TestResult::discard();
+            }
+            let contents = BlockContents::new(vec![f1.clone(), f2]);
+            let (content_hash, _) = contents.compute_hash_size();
+            let tampered = BlockContents::new(vec![f1, f3]);
+            TestResult::from_bool(
+                contents.verify_hash(&content_hash) && !tampered.verify_hash(&content_hash),
+            )
+        }
+
+        fn inclusion_proof_of_4_fragments_block(fragments: (Fragment, Fragment, Fragment, Fragment)) -> TestResult {
+            let (f1, f2, f3, f4) = fragments;
+            let contents = BlockContents::new(vec![f1, f2, f3, f4]);
+            let (content_hash, _) = contents.compute_hash_size();
+
+            for fragment in contents.iter() {
+                let proof = match contents.inclusion_proof(&fragment.id()) {
+                    None => return TestResult::error("no proof for an included fragment"),
+                    Some(proof) => proof,
+                };
+                if !verify_inclusion(&proof, &content_hash, &fragment.id()) {
+                    return TestResult::error("valid inclusion proof rejected");
+                }
+            }
+
+            let other_id = Hash::hash_bytes(b"not a fragment");
+            let proof = contents.inclusion_proof(&contents.0[2].id()).unwrap();
+            TestResult::from_bool(
+                contents.inclusion_proof(&other_id).is_none()
+                    && !verify_inclusion(&proof, &content_hash, &other_id),
+            )
+        }
+
+        fn inclusion_proof_of_3_fragments_block(fragments: (Fragment, Fragment, Fragment)) -> TestResult {
+            let (f1, f2, f3) = fragments;
+            let contents = BlockContents::new(vec![f1, f2, f3]);
+            let (content_hash, _) = contents.compute_hash_size();
+
+            for fragment in contents.iter() {
+                let proof = match contents.inclusion_proof(&fragment.id()) {
+                    None => return TestResult::error("no proof for an included fragment"),
+                    Some(proof) => proof,
+                };
+                if !verify_inclusion(&proof, &content_hash, &fragment.id()) {
+                    return TestResult::error("valid inclusion proof rejected");
+                }
+            }
+
+            // the odd fragment out is hashed on its own on the way up
+            let last_id = contents.0[2].id();
+            let mut proof = contents.inclusion_proof(&last_id).unwrap();
+            proof.path.retain(|step| step != &MerkleStep::Alone);
+            TestResult::from_bool(!verify_inclusion(&proof, &content_hash, &last_id))
+        }
+
+        fn single_fragment_root_is_not_the_fragment_id(fragment: Fragment) -> bool {
+            let contents = BlockContents::new(vec![fragment.clone()]);
+            let (content_hash, _) = contents.compute_hash_size();
+            content_hash != fragment.id()
+        }
+
+        fn total_witness_count_sums_transactions_and_certificates(
+            transactions: Vec<AuthenticatedTransaction<Address, NoExtra>>,
+            certificate: AuthenticatedTransaction<Address, Certificate>,
+            config: ConfigParams
+        ) -> bool {
+            let expected = transactions.iter().map(|tx| tx.witnesses.len()).sum::<usize>()
+                + certificate.witnesses.len();
+            let mut fragments = vec![Fragment::Initial(config)];
+            fragments.extend(transactions.into_iter().map(Fragment::Transaction));
+            fragments.push(Fragment::Certificate(certificate));
+            BlockContents::new(fragments).total_witness_count() == expected
+        }
     }
 
     impl Arbitrary for HeaderRaw {
@@ -1,9 +1,11 @@
+use crate::account;
+use crate::date::Epoch;
 use crate::key::EitherEd25519SecretKey;
 use crate::stake::{StakePoolId, StakePoolInfo};
 use crate::transaction::AccountIdentifier;
 use chain_core::mempack::{read_vec, ReadBuf, ReadError, Readable};
 use chain_core::property;
-use chain_crypto::{Ed25519, PublicKey, Verification};
+use chain_crypto::{Ed25519, PublicKey, Signature, Verification};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SignatureRaw(pub Vec<u8>);
@@ -48,6 +50,18 @@ impl Certificate {
                 let signature = v.make_certificate(secret_key);
                 self.signatures.push(signature);
             }
+            CertificateContent::AccountLock(v) => {
+                let signature = v.make_certificate(secret_key);
+                self.signatures.push(signature);
+            }
+            CertificateContent::StakePoolOwnershipTransfer(v) => {
+                let signature = v.make_certificate(secret_key);
+                self.signatures.push(signature);
+            }
+            CertificateContent::BulkDelegation(v) => {
+                let signature = v.make_certificate(secret_key);
+                self.signatures.push(signature);
+            }
         }
     }
 
@@ -56,6 +70,11 @@ impl Certificate {
             CertificateContent::StakeDelegation(v) => verify_certificate(v, &self.signatures),
             CertificateContent::StakePoolRegistration(v) => verify_certificate(v, &self.signatures),
             CertificateContent::StakePoolRetirement(v) => verify_certificate(v, &self.signatures),
+            CertificateContent::AccountLock(v) => verify_certificate(v, &self.signatures),
+            CertificateContent::StakePoolOwnershipTransfer(v) => {
+                verify_certificate(v, &self.signatures)
+            }
+            CertificateContent::BulkDelegation(v) => verify_certificate(v, &self.signatures),
         }
     }
 }
@@ -77,17 +96,49 @@ where
     Verification::Success
 }
 
+/// Count how many of the given accounts made one of the signatures
+/// of the certificate's content.
+pub(crate) fn count_signers<C>(
+    certificate: &C,
+    accounts: &[account::Identifier],
+    raw_signatures: &[SignatureRaw],
+) -> usize
+where
+    C: property::Serialize,
+{
+    use crate::key::verify_signature;
+    let signatures: Vec<Signature<C, Ed25519>> = raw_signatures
+        .iter()
+        .filter_map(|raw| Signature::from_binary(&raw.0).ok())
+        .collect();
+    accounts
+        .iter()
+        .filter(|account| {
+            let public_key: &PublicKey<Ed25519> = account.as_ref();
+            signatures.iter().any(|signature| {
+                verify_signature(signature, public_key, certificate) == Verification::Success
+            })
+        })
+        .count()
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum CertificateContent {
     StakeDelegation(StakeDelegation),
     StakePoolRegistration(StakePoolInfo),
     StakePoolRetirement(StakePoolRetirement),
+    AccountLock(AccountLock),
+    StakePoolOwnershipTransfer(StakePoolOwnershipTransfer),
+    BulkDelegation(BulkDelegation),
 }
 
 enum CertificateTag {
     StakeDelegation = 1,
     StakePoolRegistration = 2,
     StakePoolRetirement = 3,
+    AccountLock = 4,
+    StakePoolOwnershipTransfer = 5,
+    BulkDelegation = 6,
 }
 
 impl CertificateTag {
@@ -96,6 +147,9 @@ impl CertificateTag {
             1 => Some(CertificateTag::StakeDelegation),
             2 => Some(CertificateTag::StakePoolRegistration),
             3 => Some(CertificateTag::StakePoolRetirement),
+            4 => Some(CertificateTag::AccountLock),
+            5 => Some(CertificateTag::StakePoolOwnershipTransfer),
+            6 => Some(CertificateTag::BulkDelegation),
             _ => None,
         }
     }
@@ -119,6 +173,18 @@ impl property::Serialize for Certificate {
                 codec.put_u8(CertificateTag::StakePoolRetirement as u8)?;
                 s.serialize(&mut codec)
             }
+            CertificateContent::AccountLock(s) => {
+                codec.put_u8(CertificateTag::AccountLock as u8)?;
+                s.serialize(&mut codec)
+            }
+            CertificateContent::StakePoolOwnershipTransfer(s) => {
+                codec.put_u8(CertificateTag::StakePoolOwnershipTransfer as u8)?;
+                s.serialize(&mut codec)
+            }
+            CertificateContent::BulkDelegation(s) => {
+                codec.put_u8(CertificateTag::BulkDelegation as u8)?;
+                s.serialize(&mut codec)
+            }
         }?;
         codec.put_u8(self.signatures.len() as u8)?;
         for sig in &self.signatures {
@@ -141,8 +207,19 @@ impl Readable for Certificate {
             Some(CertificateTag::StakeDelegation) => {
                 CertificateContent::StakeDelegation(StakeDelegation::read(buf)?)
             }
+            Some(CertificateTag::AccountLock) => {
+                CertificateContent::AccountLock(AccountLock::read(buf)?)
+            }
+            Some(CertificateTag::StakePoolOwnershipTransfer) => {
+                CertificateContent::StakePoolOwnershipTransfer(StakePoolOwnershipTransfer::read(
+                    buf,
+                )?)
+            }
+            Some(CertificateTag::BulkDelegation) => {
+                CertificateContent::BulkDelegation(BulkDelegation::read(buf)?)
+            }
 
-            None => panic!("not a certificate"),
+            None => return Err(ReadError::UnknownTag(tag as u32)),
         };
         let len = buf.get_u8()?;
         let signatures = chain_core::mempack::read_vec(buf, len as usize)?;
@@ -256,6 +333,152 @@ impl Readable for StakePoolRetirement {
     }
 }
 
+/// Lock an account, preventing any spending from it until the given epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountLock {
+    pub account_id: AccountIdentifier,
+    pub until: Epoch,
+}
+
+impl AccountLock {
+    pub fn make_certificate(&self, account_private_key: &EitherEd25519SecretKey) -> SignatureRaw {
+        use crate::key::make_signature;
+        match account_private_key {
+            EitherEd25519SecretKey::Extended(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+            EitherEd25519SecretKey::Normal(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+        }
+    }
+}
+
+impl property::Serialize for AccountLock {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        use chain_core::packer::*;
+        use std::io::Write;
+        let mut codec = Codec::new(writer);
+        codec.write_all(self.account_id.as_ref())?;
+        codec.put_u32(self.until)?;
+        Ok(())
+    }
+}
+
+impl Readable for AccountLock {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let account_identifier = <[u8; 32]>::read(buf)?;
+        Ok(AccountLock {
+            account_id: account_identifier.into(),
+            until: buf.get_u32()?,
+        })
+    }
+}
+
+/// Replace the owners of a registered stake pool, signed by a majority
+/// of its current owners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StakePoolOwnershipTransfer {
+    pub pool_id: StakePoolId,
+    pub new_owners: Vec<account::Identifier>,
+}
+
+impl StakePoolOwnershipTransfer {
+    pub fn make_certificate(&self, owner_private_key: &EitherEd25519SecretKey) -> SignatureRaw {
+        use crate::key::make_signature;
+        match owner_private_key {
+            EitherEd25519SecretKey::Extended(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+            EitherEd25519SecretKey::Normal(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+        }
+    }
+}
+
+impl property::Serialize for StakePoolOwnershipTransfer {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        assert!(self.new_owners.len() < 256);
+
+        use chain_core::packer::*;
+        let mut codec = Codec::new(writer);
+        self.pool_id.serialize(&mut codec)?;
+        codec.put_u8(self.new_owners.len() as u8)?;
+        for o in &self.new_owners {
+            o.serialize(&mut codec)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for StakePoolOwnershipTransfer {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let pool_id = StakePoolId::read(buf)?;
+        let owner_nb = buf.get_u8()? as usize;
+        let mut new_owners = Vec::with_capacity(owner_nb);
+        for _ in 0..owner_nb {
+            new_owners.push(account::Identifier::read(buf)?);
+        }
+        Ok(StakePoolOwnershipTransfer {
+            pool_id,
+            new_owners,
+        })
+    }
+}
+
+/// Delegate several accounts at once, signed by every delegated account.
+/// The delegations are applied all together or not at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkDelegation {
+    pub delegations: Vec<(account::Identifier, StakePoolId)>,
+}
+
+impl BulkDelegation {
+    pub fn make_certificate(&self, account_private_key: &EitherEd25519SecretKey) -> SignatureRaw {
+        use crate::key::make_signature;
+        match account_private_key {
+            EitherEd25519SecretKey::Extended(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+            EitherEd25519SecretKey::Normal(sk) => {
+                SignatureRaw(make_signature(sk, &self).as_ref().to_vec())
+            }
+        }
+    }
+}
+
+impl property::Serialize for BulkDelegation {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        assert!(self.delegations.len() < 256);
+
+        use chain_core::packer::*;
+        let mut codec = Codec::new(writer);
+        codec.put_u8(self.delegations.len() as u8)?;
+        for (account_id, pool_id) in &self.delegations {
+            account_id.serialize(&mut codec)?;
+            pool_id.serialize(&mut codec)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for BulkDelegation {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let delegation_nb = buf.get_u8()? as usize;
+        let mut delegations = Vec::with_capacity(delegation_nb);
+        for _ in 0..delegation_nb {
+            let account_id = account::Identifier::read(buf)?;
+            let pool_id = StakePoolId::read(buf)?;
+            delegations.push((account_id, pool_id));
+        }
+        Ok(BulkDelegation { delegations })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -266,10 +489,13 @@ mod test {
 
     impl Arbitrary for Certificate {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            let content = match g.next_u32() % 3 {
+            let content = match g.next_u32() % 6 {
                 0 => CertificateContent::StakeDelegation(Arbitrary::arbitrary(g)),
                 1 => CertificateContent::StakePoolRegistration(Arbitrary::arbitrary(g)),
-                _ => CertificateContent::StakePoolRetirement(Arbitrary::arbitrary(g)),
+                2 => CertificateContent::StakePoolRetirement(Arbitrary::arbitrary(g)),
+                3 => CertificateContent::AccountLock(Arbitrary::arbitrary(g)),
+                4 => CertificateContent::StakePoolOwnershipTransfer(Arbitrary::arbitrary(g)),
+                _ => CertificateContent::BulkDelegation(Arbitrary::arbitrary(g)),
             };
             let signatures = Arbitrary::arbitrary(g);
             Certificate {
@@ -294,6 +520,15 @@ mod test {
         }
     }
 
+    impl Arbitrary for AccountLock {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            AccountLock {
+                account_id: Arbitrary::arbitrary(g),
+                until: Arbitrary::arbitrary(g),
+            }
+        }
+    }
+
     impl Arbitrary for StakePoolInfo {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             use rand_core::SeedableRng;
@@ -329,4 +564,26 @@ mod test {
             }
         }
     }
+
+    impl Arbitrary for StakePoolOwnershipTransfer {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            StakePoolOwnershipTransfer {
+                pool_id: Arbitrary::arbitrary(g),
+                new_owners: vec![Arbitrary::arbitrary(g)],
+            }
+        }
+    }
+
+    impl Arbitrary for BulkDelegation {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let delegation_nb = usize::arbitrary(g) % 8;
+            BulkDelegation {
+                delegations: std::iter::repeat_with(|| {
+                    (Arbitrary::arbitrary(g), Arbitrary::arbitrary(g))
+                })
+                .take(delegation_nb)
+                .collect(),
+            }
+        }
+    }
 }
@@ -1,6 +1,12 @@
+use crate::date::BlockDate;
+use crate::key::Hash;
 use crate::leadership::bft::LeaderId;
 use crate::milli::Milli;
-use crate::{block::ConsensusVersion, fee::LinearFee};
+use crate::value::Value;
+use crate::{
+    block::ConsensusVersion,
+    fee::{LinearFee, PerCertificateFee},
+};
 use chain_addr::Discrimination;
 use chain_core::mempack::{ReadBuf, ReadError, Readable};
 use chain_core::packer::Codec;
@@ -56,10 +62,26 @@ pub enum ConfigParam {
     LinearFee(LinearFee),
     ProposalExpiration(u32),
     KESUpdateSpeed(u32),
+    MaxSlotGap(u32),
+    AccountMinBalance(Value),
+    RequireSortedInputs(bool),
+    MaxOutputValue(Value),
+    RejectZeroAccountCredit(bool),
+    MaxTransactionInputs(u32),
+    MaxTransactionOutputs(u32),
+    MaxTransactionWitnesses(u32),
+    PerCertificateFees(PerCertificateFee),
+    BftFallbackTimeout(u32),
+    OldUtxoRedemptionDeadline(BlockDate),
+    MinUtxoValue(Value),
+    AddBlockedAddress(Hash),
+    RemoveBlockedAddress(Hash),
+    RejectSelfTransfers(bool),
+    MaxSpendingCounter(u32),
 }
 
 // Discriminants can NEVER be 1024 or higher
-#[derive(AsRefStr, Clone, Copy, Debug, EnumIter, EnumString, PartialEq)]
+#[derive(AsRefStr, Clone, Copy, Debug, EnumIter, EnumString, PartialEq, Eq)]
 pub enum Tag {
     #[strum(to_string = "discrimination")]
     Discrimination = 1,
@@ -89,9 +111,78 @@ pub enum Tag {
     ProposalExpiration = 15,
     #[strum(to_string = "kes-update-speed")]
     KESUpdateSpeed = 16,
+    #[strum(to_string = "max-slot-gap")]
+    MaxSlotGap = 17,
+    #[strum(to_string = "account-min-balance")]
+    AccountMinBalance = 18,
+    #[strum(to_string = "require-sorted-inputs")]
+    RequireSortedInputs = 19,
+    #[strum(to_string = "max-output-value")]
+    MaxOutputValue = 20,
+    #[strum(to_string = "reject-zero-account-credit")]
+    RejectZeroAccountCredit = 21,
+    #[strum(to_string = "max-transaction-inputs")]
+    MaxTransactionInputs = 22,
+    #[strum(to_string = "max-transaction-outputs")]
+    MaxTransactionOutputs = 23,
+    #[strum(to_string = "max-transaction-witnesses")]
+    MaxTransactionWitnesses = 24,
+    #[strum(to_string = "per-certificate-fees")]
+    PerCertificateFees = 25,
+    #[strum(to_string = "bft-fallback-timeout")]
+    BftFallbackTimeout = 26,
+    #[strum(to_string = "old-utxo-redemption-deadline")]
+    OldUtxoRedemptionDeadline = 27,
+    #[strum(to_string = "min-utxo-value")]
+    MinUtxoValue = 28,
+    #[strum(to_string = "add-blocked-address")]
+    AddBlockedAddress = 29,
+    #[strum(to_string = "remove-blocked-address")]
+    RemoveBlockedAddress = 30,
+    #[strum(to_string = "reject-self-transfers")]
+    RejectSelfTransfers = 31,
+    #[strum(to_string = "max-spending-counter")]
+    MaxSpendingCounter = 32,
 }
 
 impl Tag {
+    /// Whether a parameter with this tag may appear several times in the
+    /// same set of parameters; any other parameter must be unique.
+    pub fn is_repeatable(self) -> bool {
+        match self {
+            Tag::AddBftLeader
+            | Tag::RemoveBftLeader
+            | Tag::AddBlockedAddress
+            | Tag::RemoveBlockedAddress => true,
+            Tag::Discrimination
+            | Tag::Block0Date
+            | Tag::ConsensusVersion
+            | Tag::SlotsPerEpoch
+            | Tag::SlotDuration
+            | Tag::EpochStabilityDepth
+            | Tag::ConsensusGenesisPraosActiveSlotsCoeff
+            | Tag::MaxNumberOfTransactionsPerBlock
+            | Tag::BftSlotsRatio
+            | Tag::LinearFee
+            | Tag::ProposalExpiration
+            | Tag::KESUpdateSpeed
+            | Tag::MaxSlotGap
+            | Tag::AccountMinBalance
+            | Tag::RequireSortedInputs
+            | Tag::MaxOutputValue
+            | Tag::RejectZeroAccountCredit
+            | Tag::MaxTransactionInputs
+            | Tag::MaxTransactionOutputs
+            | Tag::MaxTransactionWitnesses
+            | Tag::PerCertificateFees
+            | Tag::BftFallbackTimeout
+            | Tag::OldUtxoRedemptionDeadline
+            | Tag::MinUtxoValue
+            | Tag::RejectSelfTransfers
+            | Tag::MaxSpendingCounter => false,
+        }
+    }
+
     pub fn from_u16(v: u16) -> Option<Self> {
         match v {
             1 => Some(Tag::Discrimination),
@@ -108,11 +199,33 @@ impl Tag {
             14 => Some(Tag::LinearFee),
             15 => Some(Tag::ProposalExpiration),
             16 => Some(Tag::KESUpdateSpeed),
+            17 => Some(Tag::MaxSlotGap),
+            18 => Some(Tag::AccountMinBalance),
+            19 => Some(Tag::RequireSortedInputs),
+            20 => Some(Tag::MaxOutputValue),
+            21 => Some(Tag::RejectZeroAccountCredit),
+            22 => Some(Tag::MaxTransactionInputs),
+            23 => Some(Tag::MaxTransactionOutputs),
+            24 => Some(Tag::MaxTransactionWitnesses),
+            25 => Some(Tag::PerCertificateFees),
+            26 => Some(Tag::BftFallbackTimeout),
+            27 => Some(Tag::OldUtxoRedemptionDeadline),
+            28 => Some(Tag::MinUtxoValue),
+            29 => Some(Tag::AddBlockedAddress),
+            30 => Some(Tag::RemoveBlockedAddress),
+            31 => Some(Tag::RejectSelfTransfers),
+            32 => Some(Tag::MaxSpendingCounter),
             _ => None,
         }
     }
 }
 
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
 impl<'a> From<&'a ConfigParam> for Tag {
     fn from(config_param: &'a ConfigParam) -> Self {
         match config_param {
@@ -132,6 +245,22 @@ impl<'a> From<&'a ConfigParam> for Tag {
             ConfigParam::LinearFee(_) => Tag::LinearFee,
             ConfigParam::ProposalExpiration(_) => Tag::ProposalExpiration,
             ConfigParam::KESUpdateSpeed(_) => Tag::KESUpdateSpeed,
+            ConfigParam::MaxSlotGap(_) => Tag::MaxSlotGap,
+            ConfigParam::AccountMinBalance(_) => Tag::AccountMinBalance,
+            ConfigParam::RequireSortedInputs(_) => Tag::RequireSortedInputs,
+            ConfigParam::MaxOutputValue(_) => Tag::MaxOutputValue,
+            ConfigParam::RejectZeroAccountCredit(_) => Tag::RejectZeroAccountCredit,
+            ConfigParam::MaxTransactionInputs(_) => Tag::MaxTransactionInputs,
+            ConfigParam::MaxTransactionOutputs(_) => Tag::MaxTransactionOutputs,
+            ConfigParam::MaxTransactionWitnesses(_) => Tag::MaxTransactionWitnesses,
+            ConfigParam::PerCertificateFees(_) => Tag::PerCertificateFees,
+            ConfigParam::BftFallbackTimeout(_) => Tag::BftFallbackTimeout,
+            ConfigParam::OldUtxoRedemptionDeadline(_) => Tag::OldUtxoRedemptionDeadline,
+            ConfigParam::MinUtxoValue(_) => Tag::MinUtxoValue,
+            ConfigParam::AddBlockedAddress(_) => Tag::AddBlockedAddress,
+            ConfigParam::RemoveBlockedAddress(_) => Tag::RemoveBlockedAddress,
+            ConfigParam::RejectSelfTransfers(_) => Tag::RejectSelfTransfers,
+            ConfigParam::MaxSpendingCounter(_) => Tag::MaxSpendingCounter,
         }
     }
 }
@@ -177,6 +306,52 @@ impl Readable for ConfigParam {
             Tag::KESUpdateSpeed => {
                 ConfigParamVariant::from_payload(bytes).map(ConfigParam::KESUpdateSpeed)
             }
+            Tag::MaxSlotGap => ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxSlotGap),
+            Tag::AccountMinBalance => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::AccountMinBalance)
+            }
+            Tag::RequireSortedInputs => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::RequireSortedInputs)
+            }
+            Tag::MaxOutputValue => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxOutputValue)
+            }
+            Tag::RejectZeroAccountCredit => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::RejectZeroAccountCredit)
+            }
+            Tag::MaxTransactionInputs => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxTransactionInputs)
+            }
+            Tag::MaxTransactionOutputs => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxTransactionOutputs)
+            }
+            Tag::MaxTransactionWitnesses => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxTransactionWitnesses)
+            }
+            Tag::PerCertificateFees => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::PerCertificateFees)
+            }
+            Tag::BftFallbackTimeout => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::BftFallbackTimeout)
+            }
+            Tag::OldUtxoRedemptionDeadline => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::OldUtxoRedemptionDeadline)
+            }
+            Tag::MinUtxoValue => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MinUtxoValue)
+            }
+            Tag::AddBlockedAddress => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::AddBlockedAddress)
+            }
+            Tag::RemoveBlockedAddress => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::RemoveBlockedAddress)
+            }
+            Tag::RejectSelfTransfers => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::RejectSelfTransfers)
+            }
+            Tag::MaxSpendingCounter => {
+                ConfigParamVariant::from_payload(bytes).map(ConfigParam::MaxSpendingCounter)
+            }
         }
         .map_err(Into::into)
     }
@@ -202,6 +377,22 @@ impl property::Serialize for ConfigParam {
             ConfigParam::LinearFee(data) => data.to_payload(),
             ConfigParam::ProposalExpiration(data) => data.to_payload(),
             ConfigParam::KESUpdateSpeed(data) => data.to_payload(),
+            ConfigParam::MaxSlotGap(data) => data.to_payload(),
+            ConfigParam::AccountMinBalance(data) => data.to_payload(),
+            ConfigParam::RequireSortedInputs(data) => data.to_payload(),
+            ConfigParam::MaxOutputValue(data) => data.to_payload(),
+            ConfigParam::RejectZeroAccountCredit(data) => data.to_payload(),
+            ConfigParam::MaxTransactionInputs(data) => data.to_payload(),
+            ConfigParam::MaxTransactionOutputs(data) => data.to_payload(),
+            ConfigParam::MaxTransactionWitnesses(data) => data.to_payload(),
+            ConfigParam::PerCertificateFees(data) => data.to_payload(),
+            ConfigParam::BftFallbackTimeout(data) => data.to_payload(),
+            ConfigParam::OldUtxoRedemptionDeadline(data) => data.to_payload(),
+            ConfigParam::MinUtxoValue(data) => data.to_payload(),
+            ConfigParam::AddBlockedAddress(data) => data.to_payload(),
+            ConfigParam::RemoveBlockedAddress(data) => data.to_payload(),
+            ConfigParam::RejectSelfTransfers(data) => data.to_payload(),
+            ConfigParam::MaxSpendingCounter(data) => data.to_payload(),
         };
         let taglen = TagLen::new(tag, bytes.len()).ok_or_else(|| {
             io::Error::new(
@@ -285,6 +476,21 @@ impl ConfigParamVariant for LeaderId {
     }
 }
 
+impl ConfigParamVariant for Hash {
+    fn to_payload(&self) -> Vec<u8> {
+        self.as_ref().to_vec()
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Error> {
+        let mut bytes = [0; 32];
+        if payload.len() != bytes.len() {
+            return Err(Error::SizeInvalid);
+        }
+        bytes.copy_from_slice(payload);
+        Ok(Hash::from_bytes(bytes))
+    }
+}
+
 impl ConfigParamVariant for bool {
     fn to_payload(&self) -> Vec<u8> {
         vec![if *self { 1 } else { 0 }]
@@ -355,6 +561,16 @@ impl ConfigParamVariant for Milli {
     }
 }
 
+impl ConfigParamVariant for Value {
+    fn to_payload(&self) -> Vec<u8> {
+        self.0.to_payload()
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Error> {
+        u64::from_payload(payload).map(Value)
+    }
+}
+
 impl ConfigParamVariant for LinearFee {
     fn to_payload(&self) -> Vec<u8> {
         let mut v = self.constant.to_payload();
@@ -375,6 +591,44 @@ impl ConfigParamVariant for LinearFee {
     }
 }
 
+impl ConfigParamVariant for PerCertificateFee {
+    fn to_payload(&self) -> Vec<u8> {
+        let mut v = self.stake_delegation.to_payload();
+        v.extend(self.stake_pool_registration.to_payload());
+        v.extend(self.stake_pool_retirement.to_payload());
+        v
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Error> {
+        if payload.len() != 3 * 8 {
+            return Err(Error::SizeInvalid);
+        }
+        Ok(PerCertificateFee {
+            stake_delegation: u64::from_payload(&payload[0..8])?,
+            stake_pool_registration: u64::from_payload(&payload[8..16])?,
+            stake_pool_retirement: u64::from_payload(&payload[16..24])?,
+        })
+    }
+}
+
+impl ConfigParamVariant for BlockDate {
+    fn to_payload(&self) -> Vec<u8> {
+        let mut v = self.epoch.to_payload();
+        v.extend(self.slot_id.to_payload());
+        v
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Error> {
+        if payload.len() != 2 * 4 {
+            return Err(Error::SizeInvalid);
+        }
+        Ok(BlockDate {
+            epoch: u32::from_payload(&payload[0..4])?,
+            slot_id: u32::from_payload(&payload[4..8])?,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct TagLen(u16);
 
@@ -430,7 +684,7 @@ mod test {
 
     impl Arbitrary for ConfigParam {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            match u8::arbitrary(g) % 12 {
+            match u8::arbitrary(g) % 28 {
                 0 => ConfigParam::Block0Date(Arbitrary::arbitrary(g)),
                 1 => ConfigParam::Discrimination(Arbitrary::arbitrary(g)),
                 2 => ConfigParam::ConsensusVersion(Arbitrary::arbitrary(g)),
@@ -443,6 +697,22 @@ mod test {
                 9 => ConfigParam::RemoveBftLeader(Arbitrary::arbitrary(g)),
                 10 => ConfigParam::LinearFee(Arbitrary::arbitrary(g)),
                 11 => ConfigParam::ProposalExpiration(Arbitrary::arbitrary(g)),
+                12 => ConfigParam::MaxSlotGap(Arbitrary::arbitrary(g)),
+                13 => ConfigParam::AccountMinBalance(Arbitrary::arbitrary(g)),
+                14 => ConfigParam::RequireSortedInputs(Arbitrary::arbitrary(g)),
+                15 => ConfigParam::MaxOutputValue(Arbitrary::arbitrary(g)),
+                16 => ConfigParam::RejectZeroAccountCredit(Arbitrary::arbitrary(g)),
+                17 => ConfigParam::MaxTransactionInputs(Arbitrary::arbitrary(g)),
+                18 => ConfigParam::MaxTransactionOutputs(Arbitrary::arbitrary(g)),
+                19 => ConfigParam::MaxTransactionWitnesses(Arbitrary::arbitrary(g)),
+                20 => ConfigParam::PerCertificateFees(Arbitrary::arbitrary(g)),
+                21 => ConfigParam::BftFallbackTimeout(Arbitrary::arbitrary(g)),
+                22 => ConfigParam::OldUtxoRedemptionDeadline(Arbitrary::arbitrary(g)),
+                23 => ConfigParam::MinUtxoValue(Arbitrary::arbitrary(g)),
+                24 => ConfigParam::AddBlockedAddress(Arbitrary::arbitrary(g)),
+                25 => ConfigParam::RemoveBlockedAddress(Arbitrary::arbitrary(g)),
+                26 => ConfigParam::RejectSelfTransfers(Arbitrary::arbitrary(g)),
+                27 => ConfigParam::MaxSpendingCounter(Arbitrary::arbitrary(g)),
                 _ => unreachable!(),
             }
         }
@@ -1,4 +1,4 @@
-use crate::certificate::Certificate;
+use crate::certificate::{Certificate, CertificateContent};
 use crate::transaction as tx;
 use crate::value::Value;
 use chain_addr::Address;
@@ -20,6 +20,82 @@ impl LinearFee {
             certificate,
         }
     }
+
+    /// Calculate the fees of many candidate transactions at once, in the
+    /// same order as the given transactions.
+    pub fn calculate_batch<Extra>(
+        &self,
+        txs: &[&tx::Transaction<Address, Extra>],
+    ) -> Vec<Option<Value>>
+    where
+        Self: FeeAlgorithm<tx::Transaction<Address, Extra>>,
+    {
+        txs.iter().map(|tx| self.calculate(*tx)).collect()
+    }
+
+    /// Calculate the fees of a transaction together with its witnesses.
+    ///
+    /// Every input is already charged for one single-signature witness;
+    /// heavier witnesses (old utxo, multisig with many signatures) are
+    /// additionally charged `COEFFICIENT` for each extra single-signature
+    /// witness worth of bytes they take.
+    pub fn calculate_with_witnesses<Extra>(
+        &self,
+        tx: &tx::Transaction<Address, Extra>,
+        witnesses: &[tx::Witness],
+    ) -> Option<Value>
+    where
+        Self: FeeAlgorithm<tx::Transaction<Address, Extra>>,
+    {
+        let mut extra = 0u64;
+        for witness in witnesses {
+            extra = extra.checked_add(witness_weight(witness)?.saturating_sub(1))?;
+        }
+        let fee = self
+            .calculate(tx)?
+            .0
+            .checked_add(self.coefficient.checked_mul(extra)?)?;
+        Some(Value(fee))
+    }
+}
+
+/// Serialized size of a witness holding a single signature
+const SINGLE_SIGNATURE_WITNESS_SIZE: u64 = 1 + 64;
+
+/// Size of a witness in number of single-signature witnesses, rounded up
+fn witness_weight(witness: &tx::Witness) -> Option<u64> {
+    use chain_core::property::Serialize as _;
+
+    let size = witness.serialize_as_vec().ok()?.len() as u64;
+    Some((size + SINGLE_SIGNATURE_WITNESS_SIZE - 1) / SINGLE_SIGNATURE_WITNESS_SIZE)
+}
+
+/// Surcharges added to the linear fee of a certificate transaction,
+/// depending on the kind of certificate. Other certificates have no
+/// surcharge.
+#[derive(PartialEq, Eq, PartialOrd, Debug, Clone, Copy)]
+pub struct PerCertificateFee {
+    pub stake_delegation: u64,
+    pub stake_pool_registration: u64,
+    pub stake_pool_retirement: u64,
+}
+
+impl PerCertificateFee {
+    pub fn new(
+        stake_delegation: u64,
+        stake_pool_registration: u64,
+        stake_pool_retirement: u64,
+    ) -> Self {
+        PerCertificateFee {
+            stake_delegation,
+            stake_pool_registration,
+            stake_pool_retirement,
+        }
+    }
+
+    pub fn zero() -> Self {
+        PerCertificateFee::new(0, 0, 0)
+    }
 }
 
 pub trait FeeAlgorithm<P> {
@@ -55,10 +131,54 @@ impl FeeAlgorithm<tx::Transaction<Address, Certificate>> for LinearFee {
     }
 }
 
+/// Only the surcharge of the certificate, to be added to the linear fee
+impl FeeAlgorithm<tx::Transaction<Address, Certificate>> for PerCertificateFee {
+    fn calculate(&self, tx: &tx::Transaction<Address, Certificate>) -> Option<Value> {
+        let surcharge = match tx.extra.content {
+            CertificateContent::StakeDelegation(_) => self.stake_delegation,
+            CertificateContent::BulkDelegation(ref bulk) => self
+                .stake_delegation
+                .checked_mul(bulk.delegations.len() as u64)?,
+            CertificateContent::StakePoolRegistration(_) => self.stake_pool_registration,
+            CertificateContent::StakePoolRetirement(_) => self.stake_pool_retirement,
+            CertificateContent::AccountLock(_)
+            | CertificateContent::StakePoolOwnershipTransfer(_) => 0,
+        };
+        Some(Value(surcharge))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use quickcheck::{Arbitrary, Gen};
+    use crate::multisig::{self, TreeIndex};
+    use chain_crypto::{Ed25519, KeyPair, Signature};
+    use quickcheck::{Arbitrary, Gen, TestResult};
+
+    quickcheck! {
+        fn batch_calculation_matches_individual(fee: LinearFee, txs: Vec<tx::Transaction<Address, tx::NoExtra>>) -> TestResult {
+            let refs: Vec<_> = txs.iter().collect();
+            let individual: Vec<_> = txs.iter().map(|tx| fee.calculate(tx)).collect();
+            TestResult::from_bool(fee.calculate_batch(&refs) == individual)
+        }
+
+        fn multisig_witness_costs_more_than_single_signature(
+            tx: tx::Transaction<Address, tx::NoExtra>,
+            signature: tx::Witness,
+            keys: (KeyPair<Ed25519>, KeyPair<Ed25519>),
+            signatures: (Signature<multisig::WitnessMultisigData, Ed25519>, Signature<multisig::WitnessMultisigData, Ed25519>)
+        ) -> TestResult {
+            let fee = LinearFee::new(10, 2, 0);
+            let mut builder = multisig::WitnessBuilder::new();
+            builder.append(TreeIndex::D1(multisig::Index::from_u8(0).unwrap()), keys.0.public_key().clone(), signatures.0);
+            builder.append(TreeIndex::D1(multisig::Index::from_u8(1).unwrap()), keys.1.public_key().clone(), signatures.1);
+            let multisig_witness = tx::Witness::Multisig(builder.finalize());
+
+            let single_fee = fee.calculate_with_witnesses(&tx, &[signature]);
+            let multisig_fee = fee.calculate_with_witnesses(&tx, &[multisig_witness]);
+            TestResult::from_bool(single_fee == fee.calculate(&tx) && multisig_fee > single_fee)
+        }
+    }
 
     impl Arbitrary for LinearFee {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -69,4 +189,14 @@ mod test {
             }
         }
     }
+
+    impl Arbitrary for PerCertificateFee {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            Self {
+                stake_delegation: Arbitrary::arbitrary(g),
+                stake_pool_registration: Arbitrary::arbitrary(g),
+                stake_pool_retirement: Arbitrary::arbitrary(g),
+            }
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use crate::config::ConfigParam;
+use crate::config::{ConfigParam, Tag};
 use chain_core::mempack::{ReadBuf, ReadError, Readable};
 use chain_core::property;
 
@@ -22,15 +22,79 @@ impl ConfigParams {
     pub fn iter(&self) -> std::slice::Iter<ConfigParam> {
         self.0.iter()
     }
+
+    /// Iterate over the parameters in canonical order: sorted by tag, the
+    /// parameters of a same tag keeping their relative order. This is the
+    /// order the parameters are serialized and applied in, so that a set
+    /// of parameters has the same effect before and after a round trip.
+    pub fn iter_canonical(&self) -> impl Iterator<Item = &ConfigParam> {
+        // the sort is stable, keeping the order of the repeated parameters
+        let mut configs: Vec<&ConfigParam> = self.0.iter().collect();
+        configs.sort_by_key(|config| Tag::from(*config) as u16);
+        configs.into_iter()
+    }
+
+    /// List all the parameters required to create a ledger from the
+    /// initial fragment that are not present in this set.
+    pub fn missing_required(&self) -> Vec<RequiredParam> {
+        RequiredParam::ALL
+            .iter()
+            .filter(|required| !self.iter().any(|param| required.is_fulfilled_by(param)))
+            .cloned()
+            .collect()
+    }
+
+    /// Check that the parameters are in canonical order: sorted by tag,
+    /// with only the repeatable parameters appearing more than once.
+    pub fn is_canonical(&self) -> bool {
+        self.0.windows(2).all(|pair| {
+            let previous = Tag::from(&pair[0]);
+            let next = Tag::from(&pair[1]);
+            (previous as u16) < (next as u16) || (previous == next && next.is_repeatable())
+        })
+    }
+}
+
+/// Parameters that must be present in the initial fragment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequiredParam {
+    Block0Date,
+    Discrimination,
+    SlotDuration,
+    SlotsPerEpoch,
+    KESUpdateSpeed,
+    BftLeader,
+}
+
+impl RequiredParam {
+    const ALL: [RequiredParam; 6] = [
+        RequiredParam::Block0Date,
+        RequiredParam::Discrimination,
+        RequiredParam::SlotDuration,
+        RequiredParam::SlotsPerEpoch,
+        RequiredParam::KESUpdateSpeed,
+        RequiredParam::BftLeader,
+    ];
+
+    fn is_fulfilled_by(self, param: &ConfigParam) -> bool {
+        match (self, param) {
+            (RequiredParam::Block0Date, ConfigParam::Block0Date(_))
+            | (RequiredParam::Discrimination, ConfigParam::Discrimination(_))
+            | (RequiredParam::SlotDuration, ConfigParam::SlotDuration(_))
+            | (RequiredParam::SlotsPerEpoch, ConfigParam::SlotsPerEpoch(_))
+            | (RequiredParam::KESUpdateSpeed, ConfigParam::KESUpdateSpeed(_))
+            | (RequiredParam::BftLeader, ConfigParam::AddBftLeader(_)) => true,
+            _ => false,
+        }
+    }
 }
 
 impl property::Serialize for ConfigParams {
     type Error = std::io::Error;
     fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
-        // FIXME: put params in canonical order (e.g. sorted by tag)?
         use chain_core::packer::*;
         Codec::new(&mut writer).put_u16(self.0.len() as u16)?;
-        for config in &self.0 {
+        for config in self.iter_canonical() {
             config.serialize(&mut writer)?
         }
         Ok(())
@@ -39,35 +103,120 @@ impl property::Serialize for ConfigParams {
 
 impl Readable for ConfigParams {
     fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
-        // FIXME: check canonical order?
         let len = buf.get_u16()?;
         let mut configs = vec![];
         for _ in 0..len {
             configs.push(ConfigParam::read(buf)?);
         }
-        Ok(ConfigParams(configs))
+        let params = ConfigParams(configs);
+        if !params.is_canonical() {
+            return Err(ReadError::StructureInvalid(
+                "config params are not in canonical order".to_string(),
+            ));
+        }
+        Ok(params)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::config::Block0Date;
+    use crate::leadership::bft::LeaderId;
+    use chain_addr::Discrimination;
+    use chain_core::mempack::read_from_raw;
+    use chain_core::property::Serialize as _;
+    use chain_crypto::{Ed25519, SecretKey};
     use quickcheck::{Arbitrary, Gen, TestResult};
 
     quickcheck! {
         fn initial_ents_serialization_bijection(b: ConfigParams) -> TestResult {
             property::testing::serialization_bijection_r(b)
         }
+
+        fn missing_required_lists_all_absent_params(leader: LeaderId) -> TestResult {
+            let mut params = ConfigParams::new();
+            params.push(ConfigParam::Block0Date(Block0Date(0)));
+            params.push(ConfigParam::SlotsPerEpoch(21600));
+            params.push(ConfigParam::AddBftLeader(leader));
+
+            TestResult::from_bool(
+                params.missing_required()
+                    == vec![
+                        RequiredParam::Discrimination,
+                        RequiredParam::SlotDuration,
+                        RequiredParam::KESUpdateSpeed,
+                    ],
+            )
+        }
+    }
+
+    #[test]
+    pub fn shuffled_params_are_read_back_in_canonical_order() {
+        let leaders: Vec<LeaderId> = std::iter::repeat_with(|| {
+            let sk: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+            LeaderId::from(sk.to_public())
+        })
+        .take(2)
+        .collect();
+        let mut shuffled = ConfigParams::new();
+        shuffled.push(ConfigParam::SlotsPerEpoch(21600));
+        shuffled.push(ConfigParam::AddBftLeader(leaders[0].clone()));
+        shuffled.push(ConfigParam::Block0Date(Block0Date(0)));
+        shuffled.push(ConfigParam::AddBftLeader(leaders[1].clone()));
+        shuffled.push(ConfigParam::Discrimination(Discrimination::Test));
+        assert!(!shuffled.is_canonical());
+
+        let bytes = shuffled.serialize_as_vec().unwrap();
+        let decoded: ConfigParams = read_from_raw(&bytes).unwrap();
+        assert!(decoded.is_canonical());
+        assert_eq!(
+            decoded.0,
+            vec![
+                ConfigParam::Discrimination(Discrimination::Test),
+                ConfigParam::Block0Date(Block0Date(0)),
+                ConfigParam::SlotsPerEpoch(21600),
+                ConfigParam::AddBftLeader(leaders[0].clone()),
+                ConfigParam::AddBftLeader(leaders[1].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn non_canonical_params_are_rejected_on_read() {
+        let write = |params: &[ConfigParam]| {
+            let mut bytes = (params.len() as u16).to_be_bytes().to_vec();
+            for param in params {
+                bytes.extend(param.serialize_as_vec().unwrap());
+            }
+            bytes
+        };
+
+        let unsorted = write(&[
+            ConfigParam::SlotsPerEpoch(21600),
+            ConfigParam::Block0Date(Block0Date(0)),
+        ]);
+        assert!(read_from_raw::<ConfigParams>(&unsorted).is_err());
+
+        let duplicated = write(&[
+            ConfigParam::SlotsPerEpoch(21600),
+            ConfigParam::SlotsPerEpoch(43200),
+        ]);
+        assert!(read_from_raw::<ConfigParams>(&duplicated).is_err());
     }
 
     impl Arbitrary for ConfigParams {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let size = u8::arbitrary(g) as usize;
-            ConfigParams(
-                std::iter::repeat_with(|| ConfigParam::arbitrary(g))
-                    .take(size)
-                    .collect(),
-            )
+            let mut configs: Vec<ConfigParam> = Vec::with_capacity(size);
+            for config in std::iter::repeat_with(|| ConfigParam::arbitrary(g)).take(size) {
+                let tag = Tag::from(&config);
+                if tag.is_repeatable() || !configs.iter().any(|c| Tag::from(c) == tag) {
+                    configs.push(config);
+                }
+            }
+            configs.sort_by_key(|config| Tag::from(config) as u16);
+            ConfigParams(configs)
         }
     }
 }
@@ -0,0 +1,37 @@
+use super::Fragment;
+use crate::transaction::{AuthenticatedTransaction, NoExtra};
+use chain_addr::Address;
+
+/// Transactions bundled together to be applied all-or-nothing: either
+/// every transaction of the group applies, or none of them does.
+///
+/// This allows for atomic exchanges between parties, where each one
+/// authenticates its own transaction.
+#[derive(Debug, Clone)]
+pub struct FragmentGroup {
+    fragments: Vec<Fragment>,
+}
+
+impl FragmentGroup {
+    pub fn new(transactions: Vec<AuthenticatedTransaction<Address, NoExtra>>) -> Self {
+        FragmentGroup {
+            fragments: transactions
+                .into_iter()
+                .map(Fragment::Transaction)
+                .collect(),
+        }
+    }
+
+    /// The fragments of the group, in the order they are applied
+    pub fn fragments(&self) -> &[Fragment] {
+        &self.fragments
+    }
+
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+}
@@ -1,12 +1,16 @@
 pub mod config;
+mod group;
 mod raw;
 
+use crate::block::HeaderContentEvalContext;
+use crate::ledger::{self, Ledger, LedgerParameters};
 use crate::legacy;
 use chain_addr::Address;
-use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::mempack::{read_from_raw, ReadBuf, ReadError, Readable};
 use chain_core::property;
 
-pub use config::ConfigParams;
+pub use config::{ConfigParams, RequiredParam};
+pub use group::FragmentGroup;
 pub use raw::{FragmentId, FragmentRaw};
 
 use crate::{
@@ -92,6 +96,20 @@ impl Fragment {
         let mut buf = ReadBuf::from(raw.as_ref());
         Fragment::read(&mut buf)
     }
+
+    /// Deserialize a fragment and check that it can be applied to the
+    /// ledger in the given context, leaving the ledger untouched.
+    pub fn read_and_validate(
+        bytes: &[u8],
+        ledger: &Ledger,
+        ledger_params: &LedgerParameters,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<Self, ledger::Error> {
+        let fragment: Fragment =
+            read_from_raw(bytes).map_err(|_| ledger::Error::FragmentUndecodable)?;
+        ledger.apply_fragment(ledger_params, &fragment, metadata)?;
+        Ok(fragment)
+    }
 }
 
 impl Readable for Fragment {
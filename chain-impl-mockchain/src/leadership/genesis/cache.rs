@@ -0,0 +1,157 @@
+use super::{ActiveSlotsCoeff, GenesisLeaderSelection, Nonce};
+use crate::block::BlockDate;
+use crate::date::{Epoch, SlotId};
+use crate::leadership::Error;
+use crate::ledger::Ledger;
+use crate::setting::Settings;
+use crate::stake::{PoolTable, StakeDistribution, StakePoolId, StakePoolInfo};
+use chain_crypto::{Curve25519_2HashDH, PublicKey, SecretKey};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+type EligibilityKey = (StakePoolId, PublicKey<Curve25519_2HashDH>, SlotId);
+
+/// Leader eligibility of stake pools for the slots of an epoch.
+///
+/// Eligibility is evaluated against the stake distribution and nonce frozen
+/// when entering the epoch, so the transactions applied during the epoch do
+/// not change the answers. The answers already evaluated are kept, so that
+/// checking the same slot, pool and VRF key again is cheap. They are shared
+/// by the copies of the ledger made during the epoch, which all have the
+/// same frozen distribution.
+#[derive(Clone)]
+pub struct LeaderEligibilityCache {
+    selection: Arc<GenesisLeaderSelection>,
+    eligibility: Arc<RwLock<HashMap<EligibilityKey, bool>>>,
+}
+
+// The cache is not part of the ledger state: two ledgers only differing
+// by what they have cached are the same.
+impl PartialEq for LeaderEligibilityCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl Eq for LeaderEligibilityCache {}
+
+/// The frozen leader selection of an epoch, in a flat form which can be
+/// recorded and restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderSelectionSnapshot {
+    pub epoch: Epoch,
+    pub epoch_nonce: Nonce,
+    pub active_slots_coeff: ActiveSlotsCoeff,
+    /// The registered pools, sorted by identifier
+    pub pools: Vec<StakePoolInfo>,
+    pub distribution: StakeDistribution,
+}
+
+impl LeaderEligibilityCache {
+    /// Freeze the stake distribution of the ledger for the given epoch
+    pub(crate) fn new(epoch: Epoch, ledger: &Ledger) -> Self {
+        Self::from_selection(GenesisLeaderSelection::new(epoch, ledger))
+    }
+
+    /// Cache for a ledger without any stake yet, no pool is ever elected
+    pub(crate) fn without_stake(epoch: Epoch, settings: &Settings) -> Self {
+        Self::from_selection(GenesisLeaderSelection {
+            epoch_nonce: settings.consensus_nonce.clone(),
+            nodes: PoolTable::new(),
+            distribution: StakeDistribution::empty(),
+            epoch,
+            active_slots_coeff: settings.active_slots_coeff,
+        })
+    }
+
+    /// Cache for the leader selection of the snapshot, with no answer
+    /// evaluated yet
+    pub fn from_snapshot(snapshot: LeaderSelectionSnapshot) -> Self {
+        let nodes = snapshot
+            .pools
+            .into_iter()
+            .fold(PoolTable::new(), |nodes, info| {
+                nodes
+                    .insert(info.to_id(), info)
+                    .expect("stake pools of a snapshot are unique")
+            });
+        Self::from_selection(GenesisLeaderSelection {
+            epoch_nonce: snapshot.epoch_nonce,
+            nodes,
+            distribution: snapshot.distribution,
+            epoch: snapshot.epoch,
+            active_slots_coeff: snapshot.active_slots_coeff,
+        })
+    }
+
+    /// Record the frozen leader selection, without the cached answers
+    pub fn snapshot(&self) -> LeaderSelectionSnapshot {
+        let mut pools: Vec<StakePoolInfo> = self
+            .selection
+            .nodes
+            .iter()
+            .map(|(_, info)| info.clone())
+            .collect();
+        pools.sort_by_key(|info| info.to_id());
+        LeaderSelectionSnapshot {
+            epoch: self.selection.epoch,
+            epoch_nonce: self.selection.epoch_nonce.clone(),
+            active_slots_coeff: self.selection.active_slots_coeff,
+            pools,
+            distribution: self.selection.distribution.clone(),
+        }
+    }
+
+    fn from_selection(selection: GenesisLeaderSelection) -> Self {
+        LeaderEligibilityCache {
+            selection: Arc::new(selection),
+            eligibility: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// The epoch the cached answers are valid for
+    pub fn epoch(&self) -> Epoch {
+        self.selection.epoch
+    }
+
+    /// Number of cached answers
+    pub fn len(&self) -> usize {
+        self.eligibility.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether the stake pool, given its VRF key, is elected leader
+    /// of the slot at the given date, evaluating it only the first time.
+    pub fn is_slot_leader(
+        &self,
+        pool_id: &StakePoolId,
+        vrf_key: &SecretKey<Curve25519_2HashDH>,
+        date: BlockDate,
+    ) -> Result<bool, Error> {
+        // a date out of the epoch is reported by the selection
+        if date.epoch != self.epoch() {
+            return self.is_slot_leader_uncached(pool_id, vrf_key, date);
+        }
+        let key = (pool_id.clone(), vrf_key.to_public(), date.slot_id);
+        if let Some(eligible) = self.eligibility.read().unwrap().get(&key) {
+            return Ok(*eligible);
+        }
+        let eligible = self.is_slot_leader_uncached(pool_id, vrf_key, date)?;
+        self.eligibility.write().unwrap().insert(key, eligible);
+        Ok(eligible)
+    }
+
+    /// Same as `is_slot_leader`, without using the cached answers
+    pub fn is_slot_leader_uncached(
+        &self,
+        pool_id: &StakePoolId,
+        vrf_key: &SecretKey<Curve25519_2HashDH>,
+        date: BlockDate,
+    ) -> Result<bool, Error> {
+        self.selection
+            .leader(pool_id, vrf_key, date)
+            .map(|witness| witness.is_some())
+    }
+}
@@ -1,7 +1,8 @@
+mod cache;
 mod vrfeval;
 
 use crate::{
-    block::{BlockDate, Header, Proof},
+    block::{BlockDate, GenesisPraosHeaderToSign, Header, Proof},
     date::Epoch,
     key::verify_signature,
     leadership::{Error, ErrorKind, Verification},
@@ -9,10 +10,14 @@ use crate::{
     stake::{self, StakeDistribution, StakePoolId},
     value::Value,
 };
+pub use cache::{LeaderEligibilityCache, LeaderSelectionSnapshot};
 use chain_crypto::Verification as SigningVerification;
 use chain_crypto::{Curve25519_2HashDH, PublicKey, SecretKey, SumEd25519_12};
 pub(crate) use vrfeval::witness_to_nonce;
-pub use vrfeval::{ActiveSlotsCoeff, ActiveSlotsCoeffError, Nonce, Witness, WitnessOutput};
+pub use vrfeval::{
+    is_slot_leader, vrf_threshold, ActiveSlotsCoeff, ActiveSlotsCoeffError, Nonce, Witness,
+    WitnessOutput,
+};
 use vrfeval::{PercentStake, VrfEvaluator};
 
 /// Praos Leader consisting of the KES public key and VRF public key
@@ -144,7 +149,11 @@ impl GenesisLeaderSelection {
                         let valid = verify_signature(
                             &genesis_praos_proof.kes_proof.0,
                             &pool_info.initial_key.kes_public_key,
-                            &block_header.common,
+                            &GenesisPraosHeaderToSign::new(
+                                &block_header.common,
+                                node_id,
+                                &genesis_praos_proof.vrf_proof,
+                            ),
                         );
 
                         if valid == SigningVerification::Failed {
@@ -293,4 +302,176 @@ mod tests {
         assert!((times_selected_big as f64 / times_selected_small as f64) > 0.98);
         assert!((times_selected_big as f64 / times_selected_small as f64) < 1.02);
     }
+
+    #[test]
+    pub fn genesis_praos_signature_binds_node_id() {
+        use crate::block::{BlockBuilder, ChainLength};
+        use std::convert::TryFrom;
+
+        let mut rng = rand_os::OsRng::new().unwrap();
+        let vrf_key: SecretKey<Curve25519_2HashDH> = SecretKey::generate(&mut rng);
+        let kes_key: SecretKey<SumEd25519_12> = SecretKey::generate(&mut rng);
+        let initial_key = GenesisPraosLeader {
+            vrf_public_key: vrf_key.to_public(),
+            kes_public_key: kes_key.to_public(),
+        };
+
+        // two pools sharing the same keys, so only the signed payload
+        // distinguishes them
+        let pool = StakePoolInfo {
+            serial: 1,
+            owners: vec![],
+            initial_key: initial_key.clone(),
+        };
+        let other_pool = StakePoolInfo {
+            serial: 2,
+            owners: vec![],
+            initial_key: initial_key,
+        };
+        let pool_id = pool.to_id();
+        let other_pool_id = other_pool.to_id();
+
+        let mut distribution = StakeDistribution::empty();
+        for id in &[&pool_id, &other_pool_id] {
+            distribution.to_pools.insert(
+                (*id).clone(),
+                PoolStakeDistribution {
+                    total_stake: Value(100),
+                },
+            );
+        }
+        let selection = GenesisLeaderSelection {
+            epoch_nonce: Nonce::zero(),
+            nodes: stake::PoolTable::new()
+                .insert(pool_id.clone(), pool)
+                .unwrap()
+                .insert(other_pool_id.clone(), other_pool)
+                .unwrap(),
+            distribution,
+            epoch: 0,
+            active_slots_coeff: ActiveSlotsCoeff::try_from(Milli::HALF).unwrap(),
+        };
+
+        let vrf_proof = vrf_evaluate_and_prove(&vrf_key, &[0, 1, 2, 3], &mut rng);
+        let mut builder = BlockBuilder::new();
+        builder.chain_length(ChainLength(1));
+        let block = builder.make_genesis_praos_block(&pool_id, &kes_key, vrf_proof);
+        let mut header = block.header;
+        match selection.verify(&header) {
+            Verification::Success => {}
+            Verification::Failure(error) => panic!("valid proof rejected: {}", error),
+        }
+
+        match header.proof {
+            Proof::GenesisPraos(ref mut proof) => proof.node_id = other_pool_id,
+            _ => unreachable!(),
+        }
+        match selection.verify(&header) {
+            Verification::Success => panic!("proof with a swapped node id accepted"),
+            Verification::Failure(_) => {}
+        }
+    }
+
+    #[test]
+    pub fn is_slot_leader_at_the_stake_boundaries() {
+        use std::convert::TryFrom;
+
+        let mut rng = rand_os::OsRng::new().unwrap();
+        let vrf_key: SecretKey<Curve25519_2HashDH> = SecretKey::generate(&mut rng);
+        let nonce = Nonce::zero();
+        let half = ActiveSlotsCoeff::try_from(Milli::HALF).unwrap();
+        let one = ActiveSlotsCoeff::try_from(Milli::ONE).unwrap();
+        assert!(ActiveSlotsCoeff::try_from(Milli::ZERO).is_err());
+
+        for slot_id in 0..100 {
+            let witness = vrf_evaluate_and_prove(&vrf_key, &[slot_id as u8], &mut rng);
+            assert!(!is_slot_leader(&witness, &nonce, slot_id, 0.0, half));
+            assert!(!is_slot_leader(&witness, &nonce, slot_id, 0.0, one));
+            assert!(is_slot_leader(&witness, &nonce, slot_id, 1.0, one));
+            for invalid in &[std::f64::NAN, -0.5, 1.5] {
+                assert!(!is_slot_leader(&witness, &nonce, slot_id, *invalid, one));
+            }
+        }
+        assert_eq!(vrf_threshold(one, std::f64::NAN), [0u8; 32]);
+    }
+
+    #[test]
+    pub fn is_slot_leader_agrees_with_the_evaluator() {
+        use super::vrfeval::{PercentStake, VrfEvaluator};
+        use std::convert::TryFrom;
+
+        let mut rng = rand_os::OsRng::new().unwrap();
+        let vrf_key: SecretKey<Curve25519_2HashDH> = SecretKey::generate(&mut rng);
+        let nonce = Nonce::zero();
+        let half = ActiveSlotsCoeff::try_from(Milli::HALF).unwrap();
+
+        for slot_id in 0..100 {
+            let evaluator = VrfEvaluator {
+                stake: PercentStake {
+                    stake: Value(1),
+                    total: Value(1),
+                },
+                nonce: &nonce,
+                slot_id,
+                active_slots_coeff: half,
+            };
+            if let Some(witness) = evaluator.evaluate(&vrf_key) {
+                assert!(is_slot_leader(&witness, &nonce, slot_id, 1.0, half));
+                assert!(!is_slot_leader(&witness, &nonce, slot_id, 0.0, half));
+            }
+        }
+    }
+
+    #[test]
+    pub fn vrf_threshold_is_stable() {
+        use std::convert::TryFrom;
+
+        let half = ActiveSlotsCoeff::try_from(Milli::HALF).unwrap();
+        let one = ActiveSlotsCoeff::try_from(Milli::ONE).unwrap();
+        let with_prefix = |prefix: [u8; 8]| {
+            let mut bound = [0u8; 32];
+            bound[0..8].copy_from_slice(&prefix);
+            bound
+        };
+
+        assert_eq!(vrf_threshold(half, 0.0), [0u8; 32]);
+        assert_eq!(vrf_threshold(one, 1.0), [0xff; 32]);
+        assert_eq!(
+            vrf_threshold(half, 1.0),
+            with_prefix([0x80, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            vrf_threshold(half, 0.5),
+            with_prefix([0x4a, 0xfb, 0x0c, 0xcc, 0x06, 0x21, 0x98, 0x00])
+        );
+    }
+
+    #[test]
+    pub fn is_slot_leader_agrees_with_vrf_threshold() {
+        use super::vrfeval::threshold_prefix;
+        use std::convert::TryFrom;
+
+        let mut rng = rand_os::OsRng::new().unwrap();
+        let vrf_key: SecretKey<Curve25519_2HashDH> = SecretKey::generate(&mut rng);
+        let nonce = Nonce::zero();
+        let coeffs = [
+            ActiveSlotsCoeff::try_from(Milli::HALF).unwrap(),
+            ActiveSlotsCoeff::try_from(Milli::ONE).unwrap(),
+        ];
+
+        for slot_id in 0..100 {
+            let witness = vrf_evaluate_and_prove(&vrf_key, &[slot_id as u8], &mut rng);
+            let mut output = [0u8; 32];
+            output[0..8]
+                .copy_from_slice(&threshold_prefix(&witness, &nonce, slot_id).to_be_bytes());
+            for coeff in coeffs.iter() {
+                for stake_fraction in &[0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0] {
+                    assert_eq!(
+                        is_slot_leader(&witness, &nonce, slot_id, *stake_fraction, *coeff),
+                        output < vrf_threshold(*coeff, *stake_fraction)
+                    );
+                }
+            }
+        }
+    }
 }
@@ -30,6 +30,18 @@ impl Nonce {
     }
 }
 
+impl AsRef<[u8]> for Nonce {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for Nonce {
+    fn from(nonce: [u8; 32]) -> Self {
+        Nonce(nonce)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ActiveSlotsCoeffError {
     InvalidValue(Milli),
@@ -173,6 +185,78 @@ impl<'a> VrfEvaluator<'a> {
     }
 }
 
+/// Check whether a VRF output wins the slot `slot_id` of the epoch with the
+/// nonce `epoch_nonce`, for a leader holding `stake_fraction` (between 0.0
+/// and 1.0) of the total stake.
+///
+/// The first 64 bits of `H(epoch_nonce || slot_id)` derived from the output,
+/// read as a big endian integer, are compared against the integer bound of
+/// [`vrf_threshold`], so that both always agree. A leader without stake
+/// never wins, and with an active slots coefficient of 1 a leader with all
+/// the stake always wins. A stake fraction out of range, or not a number,
+/// never wins either.
+///
+/// This does not verify the VRF proof of the output.
+pub fn is_slot_leader(
+    vrf_output: &Witness,
+    epoch_nonce: &Nonce,
+    slot_id: SlotId,
+    stake_fraction: f64,
+    active_slots_coeff: ActiveSlotsCoeff,
+) -> bool {
+    match threshold_bound(active_slots_coeff, stake_fraction) {
+        None => true,
+        Some(bound) => threshold_prefix(vrf_output, epoch_nonce, slot_id) < bound,
+    }
+}
+
+/// First 64 bits of the VRF threshold output, as a big endian integer
+pub(super) fn threshold_prefix(vrf_output: &Witness, epoch_nonce: &Nonce, slot_id: SlotId) -> u64 {
+    let input = Input::create(epoch_nonce, slot_id);
+    let r = vrf_verified_get_output::<Curve25519_2HashDH>(vrf_output);
+    let out = r.to_output(&input.0, DOMAIN_THRESHOLD);
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&out.as_ref()[0..8]);
+    u64::from_be_bytes(prefix)
+}
+
+/// Upper bound on the VRF threshold output for a leader holding
+/// `relative_stake` (between 0.0 and 1.0) of the total stake.
+///
+/// The bound is `ceil(2^64 * (1 - (1 - f)^relative_stake))` written as a big
+/// endian integer in the first 8 bytes, the remaining bytes being zero, where
+/// `f` is the active slots coefficient. A VRF output derived with the
+/// threshold domain wins the slot when its first 8 bytes, read as a big
+/// endian integer, are below the bound. The bound saturates to all bytes set
+/// when the leader always wins, and is zero for a `relative_stake` out of
+/// range or not a number.
+pub fn vrf_threshold(active_slots_coeff: ActiveSlotsCoeff, relative_stake: f64) -> [u8; 32] {
+    match threshold_bound(active_slots_coeff, relative_stake) {
+        None => [0xff; 32],
+        Some(bound) => {
+            let mut threshold = [0u8; 32];
+            threshold[0..8].copy_from_slice(&bound.to_be_bytes());
+            threshold
+        }
+    }
+}
+
+/// Bound on the first 64 bits of the VRF threshold output, or None when
+/// the leader always wins
+fn threshold_bound(active_slots_coeff: ActiveSlotsCoeff, stake_fraction: f64) -> Option<u64> {
+    const TWO_POW_64: f64 = 18446744073709551616.0;
+    // NaN is not in the range either
+    if !(0.0..=1.0).contains(&stake_fraction) {
+        return Some(0);
+    }
+    let scaled = (phi_fraction(active_slots_coeff, stake_fraction).0 * TWO_POW_64).ceil();
+    if scaled >= TWO_POW_64 {
+        None
+    } else {
+        Some(scaled as u64)
+    }
+}
+
 fn above_stake_threshold(
     threshold: Threshold,
     stake: &PercentStake,
@@ -184,8 +268,13 @@ fn above_stake_threshold(
 fn phi(active_slots_coeff: ActiveSlotsCoeff, rs: &PercentStake) -> Threshold {
     assert!(rs.stake <= rs.total);
     let t = (rs.stake.0 as f64) / (rs.total.0 as f64);
+    phi_fraction(active_slots_coeff, t)
+}
+
+fn phi_fraction(active_slots_coeff: ActiveSlotsCoeff, stake_fraction: f64) -> Threshold {
+    assert!(stake_fraction >= 0.0 && stake_fraction <= 1.0);
     let f: f64 = active_slots_coeff.into();
-    Threshold(1.0 - (1.0 - f).powf(t))
+    Threshold(1.0 - (1.0 - f).powf(stake_fraction))
 }
 
 const DOMAIN_NONCE: &'static [u8] = b"NONCE";
@@ -2,6 +2,7 @@ use crate::{
     block::{AnyBlockVersion, BlockDate, BlockVersion, ConsensusVersion, Header},
     date::Epoch,
     ledger::{Ledger, LedgerParameters},
+    setting::Settings,
     stake::StakePoolId,
 };
 use chain_crypto::{Curve25519_2HashDH, Ed25519, SecretKey, SumEd25519_12};
@@ -9,6 +10,9 @@ use chain_time::era::TimeEra;
 
 pub mod bft;
 pub mod genesis;
+mod reward;
+
+pub use reward::{distribute_rewards, PoolFee};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ErrorKind {
@@ -77,6 +81,10 @@ pub struct Leadership {
     era: TimeEra,
     // Consensus specific metadata required for verifying/evaluating leaders
     inner: LeadershipConsensus,
+    // BFT leader selection for the slots given to BFT leaders in a genesis praos epoch
+    bft_slots: Option<LeadershipConsensus>,
+    // Settings of the epoch, deciding which consensus elects each slot's leader
+    settings: Settings,
     // Ledger evaluation parameters fixed for a given epoch
     ledger_parameters: LedgerParameters,
 }
@@ -140,10 +148,18 @@ impl Leadership {
                 genesis::GenesisLeaderSelection::new(epoch, ledger),
             ),
         };
+        let bft_slots = match ledger.settings.consensus_version {
+            ConsensusVersion::Bft => None,
+            ConsensusVersion::GenesisPraos => {
+                bft::BftLeaderSelection::new(ledger).map(LeadershipConsensus::Bft)
+            }
+        };
         Leadership {
             epoch: epoch,
             era: ledger.era.clone(),
             inner,
+            bft_slots,
+            settings: ledger.settings.clone(),
             ledger_parameters: ledger.get_ledger_parameters(),
         }
     }
@@ -160,7 +176,7 @@ impl Leadership {
     ///
     /// If the slot index is not valid given the leadership, out of bound date
     pub fn date_at_slot(&self, slot_id: u32) -> BlockDate {
-        assert!(slot_id < self.era.slots_per_epoch());
+        assert!(slot_id < self.era.slots_per_epoch_at(chain_time::Epoch(self.epoch)));
         BlockDate {
             epoch: self.epoch(),
             slot_id: slot_id,
@@ -184,12 +200,28 @@ impl Leadership {
         &self.ledger_parameters
     }
 
+    /// Get the consensus electing the leader of the given date's slot
+    fn slot_consensus(&self, date: BlockDate) -> Result<&LeadershipConsensus, Error> {
+        match self.settings.slot_consensus_type(date.slot_id) {
+            ConsensusVersion::Bft => match (&self.inner, &self.bft_slots) {
+                (LeadershipConsensus::Bft(_), _) => Ok(&self.inner),
+                (_, Some(bft_slots)) => Ok(bft_slots),
+                (_, None) => Err(Error::new(ErrorKind::NoLeaderForThisSlot)),
+            },
+            ConsensusVersion::GenesisPraos => Ok(&self.inner),
+        }
+    }
+
     /// Verify whether this header has been produced by a leader that fits with the leadership
     ///
     pub fn verify(&self, block_header: &Header) -> Verification {
-        try_check!(self.inner.verify_version(block_header.block_version()));
+        let consensus = match self.slot_consensus(*block_header.block_date()) {
+            Ok(consensus) => consensus,
+            Err(error) => return Verification::Failure(error),
+        };
+        try_check!(consensus.verify_version(block_header.block_version()));
 
-        try_check!(self.inner.verify_leader(block_header));
+        try_check!(consensus.verify_leader(block_header));
         Verification::Success
     }
 
@@ -200,7 +232,7 @@ impl Leadership {
         leader: &'a Leader,
         date: BlockDate,
     ) -> Result<LeaderOutput, Error> {
-        self.inner.is_leader(leader, date)
+        self.slot_consensus(date)?.is_leader(leader, date)
     }
 }
 
@@ -276,3 +308,25 @@ impl std::error::Error for Error {
         self.cause.as_ref().map(std::ops::Deref::deref)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::ledger::{self, ConfigBuilder};
+
+    #[test]
+    pub fn default_genesis_praos_ledger_elects_praos_leaders() {
+        let config = ConfigBuilder::new()
+            .with_consensus_version(ConsensusVersion::GenesisPraos)
+            .build();
+        let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+        let leadership = Leadership::new(0, &ledger);
+
+        for slot_id in 0..100 {
+            match leadership.slot_consensus(BlockDate { epoch: 0, slot_id }) {
+                Ok(LeadershipConsensus::GenesisPraos(_)) => (),
+                _ => panic!("slot {} is not elected by genesis praos", slot_id),
+            }
+        }
+    }
+}
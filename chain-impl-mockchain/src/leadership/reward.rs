@@ -0,0 +1,123 @@
+use crate::account;
+use crate::milli::Milli;
+use crate::value::Value;
+
+/// Fee a stake pool takes from its reward before distributing the rest to
+/// its delegators: a fixed value first, then a ratio of what remains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolFee {
+    pub fixed: Value,
+    pub ratio: Milli,
+}
+
+impl PoolFee {
+    /// A fee only made of a fixed value.
+    pub fn fixed(fixed: Value) -> Self {
+        PoolFee {
+            fixed,
+            ratio: Milli::ZERO,
+        }
+    }
+
+    /// The part of the reward left once the fee is deducted. The ratio part
+    /// of the fee is rounded down, and capped to the whole remaining reward.
+    fn deduct_from(&self, reward: Value) -> Value {
+        let after_fixed = reward.0.saturating_sub(self.fixed.0);
+        let ratio = std::cmp::min(self.ratio, Milli::ONE).to_millis() as u128;
+        let taken = after_fixed as u128 * ratio / Milli::ONE.to_millis() as u128;
+        Value(after_fixed - taken as u64)
+    }
+}
+
+/// Distribute the reward of a stake pool across its delegators, proportionally
+/// to their stake, once the pool fee has been deducted.
+///
+/// The shares are computed with `Value::split_proportionally`, so the whole
+/// reward (minus the fee) is always distributed and the rounding remainder
+/// goes to the first delegator with some stake.
+///
+/// Delegators with no stake get nothing, and if the total stake is null
+/// nothing is distributed.
+pub fn distribute_rewards(
+    pool_reward: Value,
+    delegators: &[(account::Identifier, Value)],
+    pool_fee: PoolFee,
+) -> Vec<(account::Identifier, Value)> {
+    let to_distribute = pool_fee.deduct_from(pool_reward);
+    let stakes: Vec<u64> = delegators
+        .iter()
+        .map(|(_, stake)| stake.0)
+        .filter(|stake| *stake > 0)
+        .collect();
+
+    // only fails if there is no stake at all, in which case nobody is rewarded
+    let mut shares = to_distribute
+        .split_proportionally(&stakes)
+        .unwrap_or_default()
+        .into_iter();
+
+    delegators
+        .iter()
+        .map(|(id, stake)| {
+            let reward = if stake.0 > 0 {
+                shares.next().unwrap_or_else(Value::zero)
+            } else {
+                Value::zero()
+            };
+            (id.clone(), reward)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_crypto::{Ed25519, SecretKey};
+
+    fn identifier() -> account::Identifier {
+        let sk: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+        sk.to_public().into()
+    }
+
+    #[test]
+    fn reward_split_between_unequal_delegators() {
+        let alice = identifier();
+        let bob = identifier();
+        let delegators = vec![(alice.clone(), Value(100)), (bob.clone(), Value(200))];
+
+        let rewards = distribute_rewards(Value(1010), &delegators, PoolFee::fixed(Value(10)));
+        assert_eq!(
+            rewards,
+            vec![(alice.clone(), Value(334)), (bob.clone(), Value(666))]
+        );
+
+        let total: u64 = rewards.iter().map(|(_, v)| v.0).sum();
+        assert_eq!(total, 1000);
+
+        let rewards = distribute_rewards(Value(5), &delegators, PoolFee::fixed(Value(10)));
+        assert_eq!(rewards, vec![(alice, Value(0)), (bob, Value(0))]);
+    }
+
+    #[test]
+    fn reward_split_after_fixed_and_ratio_fee() {
+        let alice = identifier();
+        let bob = identifier();
+        let carol = identifier();
+        let delegators = vec![
+            (carol.clone(), Value(0)),
+            (alice.clone(), Value(100)),
+            (bob.clone(), Value(200)),
+        ];
+        let pool_fee = PoolFee {
+            fixed: Value(10),
+            ratio: Milli::from_millis(100),
+        };
+
+        // 10 of fixed fee, then 10% of the remaining 1001, rounded down
+        let rewards = distribute_rewards(Value(1011), &delegators, pool_fee);
+        assert_eq!(
+            rewards,
+            vec![(carol, Value(0)), (alice, Value(301)), (bob, Value(600)),]
+        );
+    }
+}
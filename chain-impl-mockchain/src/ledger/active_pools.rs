@@ -0,0 +1,79 @@
+//! Stake pools active at each epoch boundary
+//!
+//! The set of active stake pools is frozen when entering an epoch, so that
+//! the rewards and the leader selection of the epoch are not affected by
+//! the registrations and retirements happening during it.
+
+use crate::block::ChainLength;
+use crate::date::Epoch;
+use crate::stake::StakePoolId;
+use std::collections::{btree_map, BTreeMap};
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+/// The active stake pools of each epoch, along with the chain length at
+/// which they were captured
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct ActivePoolsHistory(Arc<BTreeMap<Epoch, (ChainLength, Vec<StakePoolId>)>>);
+
+impl ActivePoolsHistory {
+    pub fn new() -> Self {
+        ActivePoolsHistory(Arc::new(BTreeMap::new()))
+    }
+
+    pub fn get(&self, epoch: Epoch) -> Option<&[StakePoolId]> {
+        self.0.get(&epoch).map(|(_, pools)| pools.as_slice())
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<Epoch, (ChainLength, Vec<StakePoolId>)> {
+        self.0.iter()
+    }
+
+    /// Record the active stake pools of an epoch captured at the given
+    /// chain length.
+    ///
+    /// The epochs before the last one captured at least `stability_depth`
+    /// blocks ago are forgotten, as no rollback can reach them anymore.
+    pub fn record(
+        &self,
+        epoch: Epoch,
+        chain_length: ChainLength,
+        pools: Vec<StakePoolId>,
+        stability_depth: u32,
+    ) -> Self {
+        let mut history = (*self.0).clone();
+        history.insert(epoch, (chain_length, pools));
+        let stable = history
+            .iter()
+            .rev()
+            .find(|(_, (captured_at, _))| {
+                captured_at.0.saturating_add(stability_depth) <= chain_length.0
+            })
+            .map(|(stable_epoch, _)| *stable_epoch);
+        if let Some(stable_epoch) = stable {
+            history = history.split_off(&stable_epoch);
+        }
+        ActivePoolsHistory(Arc::new(history))
+    }
+
+    /// Forget the epochs captured after the given chain length
+    pub fn truncate(&self, chain_length: ChainLength) -> Self {
+        let history = self
+            .0
+            .iter()
+            .filter(|(_, (captured_at, _))| *captured_at <= chain_length)
+            .map(|(epoch, entry)| (*epoch, entry.clone()))
+            .collect();
+        ActivePoolsHistory(Arc::new(history))
+    }
+}
+
+impl FromIterator<(Epoch, ChainLength, Vec<StakePoolId>)> for ActivePoolsHistory {
+    fn from_iter<I: IntoIterator<Item = (Epoch, ChainLength, Vec<StakePoolId>)>>(iter: I) -> Self {
+        let history = iter
+            .into_iter()
+            .map(|(epoch, chain_length, pools)| (epoch, (chain_length, pools)))
+            .collect();
+        ActivePoolsHistory(Arc::new(history))
+    }
+}
@@ -0,0 +1,128 @@
+//! Index of the certificates affecting each account
+//!
+//! The index is opt-in, as it is only of use to indexers and grows with
+//! every certificate applied to the ledger.
+
+use crate::account;
+use crate::block::ChainLength;
+use crate::certificate::CertificateContent;
+use crate::date::BlockDate;
+use crate::stake::DelegationState;
+use crate::transaction::TransactionId;
+use imhamt::Hamt;
+use std::collections::hash_map::DefaultHasher;
+use std::sync::Arc;
+
+/// A certificate applied to the ledger, along with where it was applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateRef {
+    /// The chain length of the block the certificate was applied in
+    pub chain_length: ChainLength,
+    /// The date at which the certificate was applied
+    pub date: BlockDate,
+    /// The identifier of the transaction carrying the certificate
+    pub transaction_id: TransactionId,
+    pub content: CertificateContent,
+}
+
+/// The certificates affecting an account, as a persistent list starting
+/// with the last one applied, so that recording a certificate does not copy
+/// the ones recorded before it
+#[derive(PartialEq, Eq)]
+struct CertificateRefs {
+    last: Arc<CertificateRef>,
+    previous: Option<Arc<CertificateRefs>>,
+}
+
+impl CertificateRefs {
+    fn iter(&self) -> impl Iterator<Item = &CertificateRef> {
+        std::iter::successors(Some(self), |refs| refs.previous.as_ref().map(Arc::as_ref))
+            .map(|refs| refs.last.as_ref())
+    }
+}
+
+/// Certificates affecting each account, in the order they were applied
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct CertificateIndex(Hamt<DefaultHasher, account::Identifier, Arc<CertificateRefs>>);
+
+impl CertificateIndex {
+    pub fn new() -> Self {
+        CertificateIndex(Hamt::new())
+    }
+
+    pub fn get(&self, id: &account::Identifier) -> Vec<CertificateRef> {
+        let mut refs: Vec<_> = self
+            .0
+            .lookup(id)
+            .map(|refs| refs.iter().cloned().collect())
+            .unwrap_or_default();
+        refs.reverse();
+        refs
+    }
+
+    /// Record the certificate against every account it affects
+    pub fn add(&self, accounts: &[account::Identifier], certificate: CertificateRef) -> Self {
+        let last = Arc::new(certificate);
+        let mut hamt = self.0.clone();
+        for id in accounts {
+            let refs = Arc::new(CertificateRefs {
+                last: last.clone(),
+                previous: hamt.lookup(id).cloned(),
+            });
+            hamt = hamt
+                .insert_or_update(id.clone(), refs.clone(), |_| Ok::<_, ()>(Some(refs)))
+                .expect("updating certificate references cannot fail");
+        }
+        CertificateIndex(hamt)
+    }
+
+    /// Forget the certificates applied after the given chain length
+    pub fn truncate(&self, chain_length: ChainLength) -> Self {
+        CertificateIndex(
+            self.0
+                .iter()
+                .filter_map(|(id, refs)| {
+                    let mut refs = Some(refs);
+                    while let Some(r) = refs.filter(|r| r.last.chain_length > chain_length) {
+                        refs = r.previous.as_ref();
+                    }
+                    refs.map(|refs| (id.clone(), refs.clone()))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Get the accounts affected by the certificate, as of the delegation
+/// state before it is applied
+pub(crate) fn affected_accounts(
+    delegation: &DelegationState,
+    content: &CertificateContent,
+) -> Vec<account::Identifier> {
+    let mut accounts = match content {
+        CertificateContent::StakeDelegation(reg) => {
+            reg.stake_key_id.to_single_account().into_iter().collect()
+        }
+        CertificateContent::StakePoolRegistration(info) => info.owners.clone(),
+        CertificateContent::StakePoolRetirement(reg) => reg.pool_info.owners.clone(),
+        CertificateContent::AccountLock(lock) => {
+            lock.account_id.to_single_account().into_iter().collect()
+        }
+        CertificateContent::StakePoolOwnershipTransfer(transfer) => {
+            let mut owners = delegation
+                .pool_info(&transfer.pool_id)
+                .map(|info| info.owners.clone())
+                .unwrap_or_default();
+            owners.extend(transfer.new_owners.iter().cloned());
+            owners
+        }
+        CertificateContent::BulkDelegation(bulk) => bulk
+            .delegations
+            .iter()
+            .map(|(account_id, _)| account_id.clone())
+            .collect(),
+    };
+    accounts.sort();
+    accounts.dedup();
+    accounts
+}
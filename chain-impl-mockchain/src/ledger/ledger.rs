@@ -2,19 +2,32 @@
 //! current state and verify transactions.
 
 use crate::block::{
-    BlockDate, ChainLength, ConsensusVersion, HeaderContentEvalContext, HeaderHash,
+    Block, BlockDate, ChainLength, ConsensusVersion, Epoch, HeaderContentEvalContext, HeaderHash,
+};
+use crate::config::{self, ConfigParam, Tag};
+use crate::fee::{FeeAlgorithm, LinearFee, PerCertificateFee};
+use crate::fragment::{Fragment, FragmentGroup};
+use crate::key::SpendingSignature;
+use crate::leadership;
+use crate::leadership::genesis::{
+    ActiveSlotsCoeffError, LeaderEligibilityCache, LeaderSelectionSnapshot,
+};
+use crate::ledger::active_pools::ActivePoolsHistory;
+use crate::ledger::certificates::{self, CertificateIndex, CertificateRef};
+use crate::ledger::undo::UndoLog;
+use crate::stake::{
+    DelegationError, DelegationState, StakeDistribution, StakeDistributionCache, StakePoolId,
+    StakePoolInfo,
 };
-use crate::config::{self, ConfigParam};
-use crate::fee::{FeeAlgorithm, LinearFee};
-use crate::fragment::Fragment;
-use crate::leadership::genesis::ActiveSlotsCoeffError;
-use crate::stake::{DelegationError, DelegationState, StakeDistribution};
 use crate::transaction::*;
 use crate::value::*;
 use crate::{account, certificate, legacy, multisig, setting, stake, update, utxo};
 use chain_addr::{Address, Discrimination, Kind};
-use chain_core::property::{self, ChainLength as _, Message as _};
-use chain_time::{Epoch, SlotDuration, TimeEra, TimeFrame, Timeline};
+use chain_core::mempack::read_from_raw;
+use chain_core::property::{self, Block as _, ChainLength as _, Message as _};
+use chain_crypto::{Curve25519_2HashDH, Ed25519, PublicKey, SecretKey};
+use chain_time::era::{EpochPosition, EpochSlotOffset};
+use chain_time::{Slot, SlotDuration, TimeEra, TimeFrame, Timeline};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
@@ -31,12 +44,24 @@ pub struct LedgerStaticParameters {
 #[derive(Clone)]
 pub struct LedgerParameters {
     pub fees: LinearFee,
+    /// The maximum value of a single transaction output, unlimited if not set
+    pub max_output_value: Option<Value>,
+    /// Whether zero-valued outputs to accounts are rejected or ignored
+    pub reject_zero_account_credit: bool,
+    /// The minimum value of an output creating a utxo, account and
+    /// multisig outputs are not concerned
+    pub min_utxo_value: Value,
+    pub transaction_limits: TransactionLimits,
+    pub per_certificate_fees: PerCertificateFee,
 }
 
-//Limits for input/output transactions and witnesses
-const MAX_TRANSACTION_INPUTS_COUNT: usize = 256;
-const MAX_TRANSACTION_OUTPUTS_COUNT: usize = 254;
-const MAX_TRANSACTION_WITNESSES_COUNT: usize = 256;
+/// Limits for input/output transactions and witnesses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionLimits {
+    pub max_inputs: usize,
+    pub max_outputs: usize,
+    pub max_witnesses: usize,
+}
 
 /// Overall ledger structure.
 ///
@@ -54,11 +79,15 @@ pub struct Ledger {
     pub(crate) updates: update::UpdateState,
     pub(crate) multisig: multisig::Ledger,
     pub(crate) delegation: DelegationState,
+    pub(crate) stake_cache: StakeDistributionCache,
+    pub(crate) leader_cache: LeaderEligibilityCache,
     pub(crate) static_params: Arc<LedgerStaticParameters>,
     pub(crate) date: BlockDate,
     pub(crate) chain_length: ChainLength,
     pub(crate) era: TimeEra,
     pub(crate) pot: Value,
+    pub(crate) certificate_index: Option<CertificateIndex>,
+    pub(crate) active_pools: ActivePoolsHistory,
 }
 
 custom_error! {
@@ -68,14 +97,12 @@ custom_error! {
         TransactionHasInput = "Transaction should not have inputs in a block0",
         TransactionHasOutput = "Transaction should not have outputs in a block0",
         TransactionHasWitnesses = "Transaction should not have witnesses in a block0",
+        CertificateHasInput = "Certificate should not have inputs in a block0",
+        CertificateHasOutput = "Certificate should not have outputs in a block0",
+        CertificateHasWitnesses = "Certificate should not have witnesses in a block0",
         InitialMessageMissing = "The initial message is missing.",
         InitialMessageMany = "Only one initial message is required",
-        InitialMessageDuplicateBlock0Date = "Block0 Date is duplicated in the initial message",
-        InitialMessageDuplicateDiscrimination = "Address discrimination setting is duplicated in the initial fragment",
-        InitialMessageDuplicateConsensusVersion = "Consensus version is duplicated in the initial fragment",
-        InitialMessageDuplicateSlotDuration = "Slot Duration is duplicated in the initial fragment",
-        InitialMessageDuplicateEpochStabilityDepth = "Epoch stability depth is duplicated in the initial fragment",
-        InitialMessageDuplicatePraosActiveSlotsCoeff = "Praos active slot coefficient setting is duplicated in the initial fragment",
+        InitialMessageDuplicateParam { tag: Tag } = "Setting {tag} is duplicated in the initial fragment",
         InitialMessageNoDate = "Missing block0 date in the initial fragment",
         InitialMessageNoSlotDuration = "Missing slot duration in the initial fragment",
         InitialMessageNoSlotsPerEpoch = "Missing slots per epoch in the initial fragment",
@@ -87,6 +114,7 @@ custom_error! {
         UtxoTotalValueTooBig = "Total initial value is too big",
         HasUpdateProposal = "Update proposal fragments are not valid in the block0",
         HasUpdateVote = "Update vote fragments are not valid in the block0",
+        Undecodable = "The block0 bytes cannot be deserialized",
 }
 
 pub type OutputOldAddress = Output<legacy::OldAddress>;
@@ -97,16 +125,27 @@ custom_error! {
     pub Error
         Config { source: config::Error } = "Invalid settings",
         NotEnoughSignatures { actual: usize, expected: usize } = "Not enough signatures, expected {expected} signatures but received {actual}",
-        UtxoValueNotMatching { expected: Value, value: Value } = "The UTxO value ({expected}) in the transaction does not match the actually state value: {value}",
+        InputValueMismatch { expected: Value, value: Value } = "The input value ({expected}) in the transaction does not match the value available in the state: {value}",
         UtxoError { source: utxo::Error } = "Invalid UTxO",
         UtxoInvalidSignature { utxo: UtxoPointer, output: OutputAddress, witness: Witness } = "Transaction with invalid signature",
         OldUtxoInvalidSignature { utxo: UtxoPointer, output: OutputOldAddress, witness: Witness } = "Old Transaction with invalid signature",
         OldUtxoInvalidPublicKey { utxo: UtxoPointer, output: OutputOldAddress, witness: Witness } = "Old Transaction with invalid public key",
         AccountInvalidSignature { account: account::Identifier, witness: Witness } = "Account with invalid signature",
+        AccountSpendingCounterMismatch { expected: account::SpendingCounter, actual: account::SpendingCounter } = "Account witness signed with spending counter {actual} instead of {expected}",
+        InputsNotSorted = "Transaction inputs are not in canonical order",
+        NoOpTransaction = "Transaction only sends its inputs back to the addresses they are spent from",
+        InitialMessageNotInBlock0 = "Initial message is only valid in the block0",
+        OldUtxoDeclarationNotInBlock0 = "Old UTxO declaration is only valid in the block0",
+        FragmentUndecodable = "The fragment bytes cannot be deserialized",
+        AccountLocked { account: account::Identifier, until: Epoch } = "Account is locked until epoch {until}",
+        AccountLockSigIsInvalid { account: account::Identifier } = "Account lock is not signed by the locked account",
+        AccountBelowMinBalance { account: AccountIdentifier, remaining: Value, min: Value } = "Account would be left with {remaining} which is below the minimum balance of {min}",
         MultisigInvalidSignature { multisig: multisig::Identifier, witness: Witness } = "Multisig with invalid signature",
         TransactionHasTooManyInputs {expected: usize, actual: usize } = "Transaction has more than {expected} inputs ({actual})",
         TransactionHasTooManyOutputs {expected: usize, actual: usize } = "Transaction has more than {expected} outputs ({actual})",
         TransactionHasTooManyWitnesses {expected: usize, actual: usize } = "Transaction has more than {expected} witnesses ({actual})",
+        TransactionExpired { valid_until: BlockDate, block_date: BlockDate } = "Transaction is valid until {valid_until} but the block is at {block_date}",
+        OldUtxoRedemptionClosed { deadline: BlockDate, block_date: BlockDate } = "Old utxos can only be redeemed until {deadline} but the block is at {block_date}",
         FeeCalculationError { error: ValueError } = "Error while computing the fees: {error}",
         PraosActiveSlotsCoeffInvalid { error: ActiveSlotsCoeffError } = "Praos active slot coefficient invalid: {error}",
         UtxoInputsTotal { error: ValueError } = "Error while computing the transaction's total input: {error}",
@@ -116,6 +155,9 @@ custom_error! {
         Multisig { source: multisig::LedgerError } = "Error or Invalid multisig",
         NotBalanced { inputs: Value, outputs: Value } = "Inputs, outputs and fees are not balanced, transaction with {inputs} input and {outputs} output",
         ZeroOutput { output: Output<Address> } = "Empty output",
+        OutputValueTooLarge { output: Output<Address>, max: Value } = "Output value is above the maximum of {max}",
+        OutputBelowMinimum { output: Output<Address>, minimum: Value } = "Output value is below the minimum utxo value of {minimum}",
+        BlockedAddress { address: Address } = "Transaction spends from or sends to a blocked address",
         OutputGroupInvalid { output: Output<Address> } = "Output group invalid",
         Delegation { source: DelegationError } = "Error or Invalid delegation ",
         AccountIdentifierInvalid = "Invalid account identifier",
@@ -127,6 +169,9 @@ custom_error! {
         Update { source: update::Error } = "Error or Invalid update",
         WrongChainLength { actual: ChainLength, expected: ChainLength } = "Wrong chain length, expected {expected} but received {actual}",
         NonMonotonicDate { block_date: BlockDate, chain_date: BlockDate } = "Non Monotonic date, chain date is at {chain_date} but the block is at {block_date}",
+        InvalidBlockDate { block_date: BlockDate } = "Block date {block_date} is past the last slot of its epoch",
+        SlotGapTooLarge { gap: u64, max: u32 } = "Block is {gap} slots after its parent, more than the maximum of {max} slots",
+        TooManyTransactionsInBlock { max: u32, actual: usize } = "Block has {actual} transactions, more than the maximum of {max}",
         IncompleteLedger = "Ledger cannot be reconstructed from serialized state because of missing entries",
         PotValueInvalid { error: ValueError } = "Ledger pot value invalid: {error}",
 }
@@ -137,6 +182,8 @@ impl Ledger {
         static_params: LedgerStaticParameters,
         era: TimeEra,
     ) -> Self {
+        let leader_cache =
+            LeaderEligibilityCache::without_stake(BlockDate::first().epoch, &settings);
         Ledger {
             utxos: utxo::Ledger::new(),
             oldutxos: utxo::Ledger::new(),
@@ -145,15 +192,42 @@ impl Ledger {
             updates: update::UpdateState::new(),
             multisig: multisig::Ledger::new(),
             delegation: DelegationState::new(),
+            stake_cache: StakeDistributionCache::empty(),
+            leader_cache,
             static_params: Arc::new(static_params),
             date: BlockDate::first(),
             chain_length: ChainLength(0),
             era,
             pot: Value::zero(),
+            certificate_index: None,
+            active_pools: ActivePoolsHistory::new(),
         }
     }
 
     pub fn new<'a, I>(block0_initial_hash: HeaderHash, contents: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        Self::from_block0(block0_initial_hash, contents, false)
+    }
+
+    /// Create the ledger like `new`, with the certificate index enabled so
+    /// that the certificates of block0 are recorded as well.
+    pub fn new_with_certificate_index<'a, I>(
+        block0_initial_hash: HeaderHash,
+        contents: I,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        Self::from_block0(block0_initial_hash, contents, true)
+    }
+
+    fn from_block0<'a, I>(
+        block0_initial_hash: HeaderHash,
+        contents: I,
+        certificate_index: bool,
+    ) -> Result<Self, Error>
     where
         I: IntoIterator<Item = &'a Fragment>,
     {
@@ -173,8 +247,18 @@ impl Ledger {
         let mut discrimination = None;
         let mut slots_per_epoch = None;
         let mut kes_update_speed = None;
+        let mut unique_tags = Vec::new();
 
         for param in init_ents.iter() {
+            let tag = Tag::from(param);
+            if !tag.is_repeatable() {
+                if unique_tags.contains(&tag) {
+                    return Err(Error::Block0 {
+                        source: Block0Error::InitialMessageDuplicateParam { tag },
+                    });
+                }
+                unique_tags.push(tag);
+            }
             match param {
                 ConfigParam::Block0Date(d) => {
                     block0_start_time = Some(*d);
@@ -224,9 +308,10 @@ impl Ledger {
         let tf = TimeFrame::new(timeline, SlotDuration::from_secs(slot_duration as u32));
         let slot0 = tf.slot0();
 
-        let era = TimeEra::new(slot0, Epoch(0), slots_per_epoch);
+        let era = TimeEra::new(slot0, chain_time::Epoch(0), slots_per_epoch);
 
-        let settings = setting::Settings::new().apply(&regular_ents)?;
+        let mut settings = setting::Settings::new().apply(&regular_ents)?;
+        settings.slots_per_epoch = slots_per_epoch;
 
         if settings.bft_leaders.is_empty() {
             return Err(Error::Block0 {
@@ -235,6 +320,9 @@ impl Ledger {
         }
 
         let mut ledger = Ledger::empty(settings, static_params, era);
+        if certificate_index {
+            ledger.enable_certificate_index();
+        }
 
         let ledger_params = ledger.get_ledger_parameters();
 
@@ -246,7 +334,8 @@ impl Ledger {
                     });
                 }
                 Fragment::OldUtxoDeclaration(old) => {
-                    ledger.oldutxos = apply_old_declaration(ledger.oldutxos, old)?;
+                    ledger.oldutxos =
+                        apply_old_declaration(ledger.oldutxos, old, ledger.chain_length)?;
                 }
                 Fragment::Transaction(authenticated_tx) => {
                     if authenticated_tx.transaction.inputs.len() != 0 {
@@ -260,18 +349,39 @@ impl Ledger {
                         });
                     }
                     let transaction_id = authenticated_tx.transaction.hash();
+                    for output in authenticated_tx.transaction.outputs.iter() {
+                        output_verify(
+                            &ledger.delegation,
+                            &ledger.static_params,
+                            &ledger_params,
+                            output,
+                        )?;
+                    }
                     let (new_utxos, new_accounts, new_multisig) =
                         internal_apply_transaction_output(
-                            ledger.utxos,
-                            ledger.accounts,
+                            ledger.utxos.clone(),
+                            ledger.accounts.clone(),
                             ledger.multisig,
-                            &ledger.static_params,
-                            &ledger_params,
+                            ledger.chain_length,
                             &transaction_id,
                             &authenticated_tx.transaction.outputs,
                         )?;
+                    ledger.stake_cache = ledger.stake_cache.apply_transaction(
+                        &ledger.accounts,
+                        &new_accounts,
+                        &ledger.utxos,
+                        &[],
+                        &[],
+                        &authenticated_tx.transaction.outputs,
+                    );
                     ledger.utxos = new_utxos;
-                    ledger.accounts = new_accounts;
+                    ledger.accounts = record_account_balance_changes(
+                        new_accounts,
+                        ledger.date,
+                        &[],
+                        &[],
+                        &authenticated_tx.transaction.outputs,
+                    )?;
                     ledger.multisig = new_multisig;
                 }
                 Fragment::UpdateProposal(_) => {
@@ -287,29 +397,49 @@ impl Ledger {
                 Fragment::Certificate(authenticated_cert_tx) => {
                     if authenticated_cert_tx.transaction.inputs.len() != 0 {
                         return Err(Error::Block0 {
-                            source: Block0Error::TransactionHasInput,
+                            source: Block0Error::CertificateHasInput,
                         });
                     }
                     if authenticated_cert_tx.witnesses.len() != 0 {
                         return Err(Error::Block0 {
-                            source: Block0Error::TransactionHasWitnesses,
+                            source: Block0Error::CertificateHasWitnesses,
                         });
                     }
                     if authenticated_cert_tx.transaction.outputs.len() != 0 {
                         return Err(Error::Block0 {
-                            source: Block0Error::TransactionHasOutput,
+                            source: Block0Error::CertificateHasOutput,
                         });
                     }
-                    ledger = ledger
-                        .apply_certificate_content(&authenticated_cert_tx.transaction.extra)?;
+                    let certificate = &authenticated_cert_tx.transaction.extra;
+                    let affected = ledger.certificate_affected_accounts(&certificate.content);
+                    ledger = ledger.apply_certificate_content(certificate)?;
+                    ledger.record_certificate(
+                        affected,
+                        ledger.date,
+                        authenticated_cert_tx.transaction.hash(),
+                        &certificate.content,
+                    );
                 }
             }
         }
 
         ledger.validate_utxo_total_value()?;
+        let epoch = ledger.date.epoch;
+        ledger.record_active_pools(epoch);
+        ledger.leader_cache = LeaderEligibilityCache::new(epoch, &ledger);
         Ok(ledger)
     }
 
+    /// Validate a serialized genesis block, returning the static parameters
+    /// of the ledger it creates.
+    pub fn validate_block0_bytes(bytes: &[u8]) -> Result<LedgerStaticParameters, Error> {
+        let block0: Block = read_from_raw(bytes).map_err(|_| Error::Block0 {
+            source: Block0Error::Undecodable,
+        })?;
+        let ledger = Ledger::new(block0.id(), block0.contents.iter())?;
+        Ok(ledger.get_static_parameters().clone())
+    }
+
     /// Try to apply messages to a State, and return the new State if succesful
     pub fn apply_block<'a, I>(
         &'a self,
@@ -338,6 +468,32 @@ impl Ledger {
             });
         }
 
+        if let Some(max) = new_ledger.settings.max_slot_gap {
+            let gap = flat_slot(&new_ledger.era, metadata.block_date)
+                - flat_slot(&new_ledger.era, new_ledger.date);
+            if gap > max as u64 {
+                return Err(Error::SlotGapTooLarge { gap, max });
+            }
+        }
+
+        // certificates are carried by transactions and count towards the
+        // maximum, update proposals and votes do not
+        let contents: Vec<&Fragment> = contents.into_iter().collect();
+        let transactions = contents
+            .iter()
+            .filter(|fragment| match fragment {
+                Fragment::Transaction(_) | Fragment::Certificate(_) => true,
+                _ => false,
+            })
+            .count();
+        let max = new_ledger.settings.max_number_of_transactions_per_block;
+        if transactions > max as usize {
+            return Err(Error::TooManyTransactionsInBlock {
+                max,
+                actual: transactions,
+            });
+        }
+
         let (updates, settings) = new_ledger.updates.process_proposals(
             new_ledger.settings,
             new_ledger.date,
@@ -346,10 +502,33 @@ impl Ledger {
         new_ledger.updates = updates;
         new_ledger.settings = settings;
 
-        for content in contents {
-            new_ledger = new_ledger.apply_fragment(ledger_params, content, metadata)?;
+        // a change of the number of slots per epoch takes effect from the
+        // epoch the update proposal was enacted at
+        let block_epoch = chain_time::Epoch(metadata.block_date.epoch);
+        if metadata.block_date.epoch != new_ledger.date.epoch
+            && new_ledger.settings.slots_per_epoch != new_ledger.era.slots_per_epoch_at(block_epoch)
+        {
+            new_ledger.era = new_ledger
+                .era
+                .add_segment(block_epoch, new_ledger.settings.slots_per_epoch);
+        }
+
+        if metadata.block_date.slot_id >= new_ledger.era.slots_per_epoch_at(block_epoch) {
+            return Err(Error::InvalidBlockDate {
+                block_date: metadata.block_date,
+            });
+        }
+
+        if metadata.block_date.epoch != new_ledger.date.epoch {
+            new_ledger.record_active_pools(metadata.block_date.epoch);
+            // the leaders of the epoch are elected by the stake held when
+            // entering it
+            new_ledger.leader_cache =
+                LeaderEligibilityCache::new(metadata.block_date.epoch, &new_ledger);
         }
 
+        new_ledger = new_ledger.apply_contents(ledger_params, contents, metadata)?;
+
         new_ledger.date = metadata.block_date;
         metadata
             .nonce
@@ -358,6 +537,133 @@ impl Ledger {
         Ok(new_ledger)
     }
 
+    /// Like `apply_block`, only applying the fragments accepted by `filter`.
+    ///
+    /// The skipped fragments have no effect on the ledger, while the chain
+    /// length, the date and the nonce are still updated for the block. The
+    /// resulting ledger is not a full validator: it misses the effects of the
+    /// skipped fragments, so its UTxOs and balances do not match the chain
+    /// and a kept fragment depending on a skipped one will fail to apply.
+    pub fn apply_block_filtered<'a, I, F>(
+        &'a self,
+        ledger_params: &LedgerParameters,
+        contents: I,
+        metadata: &HeaderContentEvalContext,
+        filter: F,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+        F: Fn(&Fragment) -> bool,
+    {
+        let contents = contents.into_iter().filter(|fragment| filter(fragment));
+        self.apply_block(ledger_params, contents, metadata)
+    }
+
+    /// Like `apply_block`, also returning the changes of settings enacted
+    /// by the update proposals accepted when reaching the block's date
+    pub fn apply_block_tracking_settings<'a, I>(
+        &'a self,
+        ledger_params: &LedgerParameters,
+        contents: I,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<(Self, Option<setting::SettingsChange>), Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        let new_ledger = self.apply_block(ledger_params, contents, metadata)?;
+        let change = self.settings.diff(&new_ledger.settings);
+        Ok((new_ledger, change))
+    }
+
+    /// Like `apply_block`, also returning the update proposals processed
+    /// when reaching the block's date, either because they were accepted,
+    /// rejected or because they expired
+    pub fn apply_block_tracking_proposals<'a, I>(
+        &'a self,
+        ledger_params: &LedgerParameters,
+        contents: I,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<(Self, Vec<update::UpdateProposalId>), Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        let new_ledger = self.apply_block(ledger_params, contents, metadata)?;
+        let processed = self
+            .updates
+            .proposals
+            .keys()
+            .filter(|proposal_id| !new_ledger.updates.proposals.contains_key(proposal_id))
+            .cloned()
+            .collect();
+        Ok((new_ledger, processed))
+    }
+
+    /// Like `apply_block`, also returning the log of the state changed by
+    /// the block, allowing `undo` to reverse it
+    pub fn apply_block_with_undo<'a, I>(
+        &'a self,
+        ledger_params: &LedgerParameters,
+        contents: I,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<(Self, UndoLog), Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        let contents: Vec<&Fragment> = contents.into_iter().collect();
+        let log = UndoLog::new(self, metadata.block_date, contents.iter().cloned());
+        let new_ledger = self.apply_block(ledger_params, contents, metadata)?;
+        Ok((new_ledger, log))
+    }
+
+    /// Reverse the application of the block the log was recorded for.
+    ///
+    /// This ledger has to be the one resulting from applying the block, the
+    /// ledger the block was applied to is returned.
+    pub fn undo(&self, log: &UndoLog) -> Result<Self, Error> {
+        if self.chain_length != log.chain_length.next() {
+            return Err(Error::WrongChainLength {
+                actual: self.chain_length,
+                expected: log.chain_length.next(),
+            });
+        }
+
+        let mut ledger = self.clone();
+        for (id, unspents) in &log.utxos {
+            ledger.utxos = ledger.utxos.restore(id, unspents.clone());
+        }
+        for (id, unspents) in &log.old_utxos {
+            ledger.oldutxos = ledger.oldutxos.restore(id, unspents.clone());
+        }
+        for (id, state) in &log.accounts {
+            ledger.accounts = ledger.accounts.restore(id, state.clone());
+        }
+        for (id, state) in &log.multisig_accounts {
+            ledger.multisig = ledger.multisig.restore_account_state(id, state.clone());
+        }
+        for (id, info, retirement) in &log.stake_pools {
+            ledger.delegation = ledger
+                .delegation
+                .restore_stake_pool(id, info.clone(), *retirement);
+        }
+
+        ledger.settings = setting::Settings::new().apply(&log.settings)?;
+        ledger.settings.consensus_nonce = log.consensus_nonce.clone();
+        ledger.updates = log.updates.clone();
+        ledger.pot = log.pot;
+        ledger.era = ledger.era.truncate(chain_time::Epoch(log.date.epoch));
+        ledger.date = log.date;
+        ledger.chain_length = log.chain_length;
+        ledger.certificate_index = ledger
+            .certificate_index
+            .map(|index| index.truncate(log.chain_length));
+        ledger.active_pools = ledger.active_pools.truncate(log.chain_length);
+        ledger.refresh_stake_cache();
+        if let Some(selection) = &log.leader_selection {
+            ledger.leader_cache = LeaderEligibilityCache::from_snapshot(selection.clone());
+        }
+        Ok(ledger)
+    }
+
     /// Try to apply a message to the State, and return the new State if successful
     ///
     /// this does not _advance_ the state to the new _state_ but apply a simple fragment
@@ -369,22 +675,48 @@ impl Ledger {
         content: &Fragment,
         metadata: &HeaderContentEvalContext,
     ) -> Result<Self, Error> {
-        let mut new_ledger = self.clone();
+        self.clone()
+            .apply_fragment_owned(ledger_params, content, metadata)
+    }
+
+    /// Apply the fragments in order, threading this ledger through them
+    /// instead of copying it for each fragment.
+    ///
+    /// The ledger is consumed even if a fragment fails to apply, leaving it
+    /// partially updated. The caller must therefore pass a fresh copy of the
+    /// ledger, so that the contents are applied all or nothing.
+    fn apply_contents<'a, I>(
+        mut self,
+        ledger_params: &LedgerParameters,
+        contents: I,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        for content in contents {
+            self = self.apply_fragment_owned(ledger_params, content, metadata)?;
+        }
+        Ok(self)
+    }
+
+    fn apply_fragment_owned(
+        self,
+        ledger_params: &LedgerParameters,
+        content: &Fragment,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<Self, Error> {
+        let mut new_ledger = self;
 
         match content {
-            Fragment::Initial(_) => {
-                return Err(Error::Block0 {
-                    source: Block0Error::OnlyMessageReceived,
-                })
-            }
-            Fragment::OldUtxoDeclaration(_) => {
-                return Err(Error::Block0 {
-                    source: Block0Error::OnlyMessageReceived,
-                });
-            }
+            Fragment::Initial(_) => return Err(Error::InitialMessageNotInBlock0),
+            Fragment::OldUtxoDeclaration(_) => return Err(Error::OldUtxoDeclarationNotInBlock0),
             Fragment::Transaction(authenticated_tx) => {
-                let (new_ledger_, _fee) =
-                    new_ledger.apply_transaction(&authenticated_tx, &ledger_params)?;
+                let (new_ledger_, _fee) = new_ledger.apply_transaction_at(
+                    &authenticated_tx,
+                    &ledger_params,
+                    metadata.block_date,
+                )?;
                 new_ledger = new_ledger_;
             }
             Fragment::UpdateProposal(update_proposal) => {
@@ -398,8 +730,11 @@ impl Ledger {
                 new_ledger = new_ledger.apply_update_vote(&vote)?;
             }
             Fragment::Certificate(authenticated_cert_tx) => {
-                let (new_ledger_, _fee) =
-                    new_ledger.apply_certificate(authenticated_cert_tx, &ledger_params)?;
+                let (new_ledger_, _fee) = new_ledger.apply_certificate_at(
+                    authenticated_cert_tx,
+                    &ledger_params,
+                    metadata.block_date,
+                )?;
                 new_ledger = new_ledger_;
             }
         }
@@ -407,33 +742,143 @@ impl Ledger {
         Ok(new_ledger)
     }
 
+    /// Apply all the fragments of the group, in order, or none of them.
+    ///
+    /// The group is applied on a copy of the ledger, which is returned only
+    /// if every fragment applied successfully. Otherwise the error of the
+    /// first failing fragment is returned.
+    pub fn apply_fragment_group(
+        &self,
+        ledger_params: &LedgerParameters,
+        group: &FragmentGroup,
+        metadata: &HeaderContentEvalContext,
+    ) -> Result<Self, Error> {
+        self.clone()
+            .apply_contents(ledger_params, group.fragments(), metadata)
+    }
+
+    /// Apply the transaction, as of the current date of the ledger
     pub fn apply_transaction<Extra>(
-        mut self,
+        self,
         signed_tx: &AuthenticatedTransaction<Address, Extra>,
         dyn_params: &LedgerParameters,
     ) -> Result<(Self, Value), Error>
     where
         Extra: property::Serialize,
         LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
+    {
+        let date = self.date;
+        self.apply_transaction_at(signed_tx, dyn_params, date)
+    }
+
+    fn apply_transaction_at<Extra>(
+        self,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        dyn_params: &LedgerParameters,
+        block_date: BlockDate,
+    ) -> Result<(Self, Value), Error>
+    where
+        Extra: property::Serialize,
+        LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
+    {
+        self.check_self_transfer(signed_tx)?;
+        let fee = calculate_fee(&dyn_params.fees, &signed_tx.transaction)?;
+        let new_ledger = self.apply_transaction_with_fee(signed_tx, dyn_params, block_date, fee)?;
+        Ok((new_ledger, fee))
+    }
+
+    /// Reject a plain transaction only sending its inputs back to where they
+    /// are spent from, if the settings say so. Certificates change the state
+    /// even when paying back their inputs, so they are not concerned.
+    fn check_self_transfer<Extra>(
+        &self,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+    ) -> Result<(), Error> {
+        if self.settings.reject_self_transfers && is_self_transfer(self, signed_tx) {
+            return Err(Error::NoOpTransaction);
+        }
+        Ok(())
+    }
+
+    fn apply_transaction_with_fee<Extra>(
+        mut self,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        dyn_params: &LedgerParameters,
+        block_date: BlockDate,
+        fee: Value,
+    ) -> Result<Self, Error>
+    where
+        Extra: property::Serialize,
     {
         let transaction_id = signed_tx.transaction.hash();
-        let fee = dyn_params
-            .fees
-            .calculate(&signed_tx.transaction)
-            .map(Ok)
-            .unwrap_or(Err(Error::FeeCalculationError {
-                error: ValueError::Overflow,
-            }))?;
+        let accounts_before = self.accounts.clone();
+        let utxos_before = self.utxos.clone();
         self = internal_apply_transaction(
             self,
             dyn_params,
+            block_date,
             &transaction_id,
+            signed_tx.transaction.valid_until,
             &signed_tx.transaction.inputs[..],
             &signed_tx.transaction.outputs[..],
             &signed_tx.witnesses[..],
             fee,
         )?;
-        Ok((self, fee))
+        self.stake_cache = self
+            .stake_cache
+            .apply_transaction(
+                &accounts_before,
+                &self.accounts,
+                &utxos_before,
+                &signed_tx.transaction.inputs[..],
+                &signed_tx.witnesses[..],
+                &signed_tx.transaction.outputs[..],
+            )
+            .checkpoint(&self.accounts, &self.delegation, &self.utxos);
+        Ok(self)
+    }
+
+    /// Check whether the transaction is valid against this ledger, as of
+    /// its current date, returning the fee the transaction pays.
+    ///
+    /// The transaction goes through the very same checks as when applied,
+    /// but the ledger is only borrowed and no new ledger is produced.
+    pub fn validate_transaction<Extra>(
+        &self,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        dyn_params: &LedgerParameters,
+    ) -> Result<Value, Error>
+    where
+        Extra: property::Serialize,
+        LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
+    {
+        self.check_self_transfer(signed_tx)?;
+        let fee = calculate_fee(&dyn_params.fees, &signed_tx.transaction)?;
+        internal_validate_transaction(
+            self,
+            dyn_params,
+            self.date,
+            &signed_tx.transaction.hash(),
+            signed_tx.transaction.valid_until,
+            &signed_tx.transaction.inputs[..],
+            &signed_tx.transaction.outputs[..],
+            &signed_tx.witnesses[..],
+            fee,
+        )?;
+        Ok(fee)
+    }
+
+    #[deprecated(note = "use validate_transaction")]
+    pub fn validate_transaction_readonly<Extra>(
+        &self,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        dyn_params: &LedgerParameters,
+    ) -> Result<Value, Error>
+    where
+        Extra: property::Serialize,
+        LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
+    {
+        self.validate_transaction(signed_tx, dyn_params)
     }
 
     pub fn apply_update(mut self, update: &update::UpdateProposal) -> Result<Self, Error> {
@@ -472,9 +917,7 @@ impl Ledger {
                 }
 
                 if let Some(account_key) = reg.stake_key_id.to_single_account() {
-                    self.accounts = self
-                        .accounts
-                        .set_delegation(&account_key, Some(reg.pool_id.clone()))?;
+                    self = self.set_account_delegation(&account_key, &reg.pool_id)?;
                 } else {
                     return Err(DelegationError::StakeDelegationAccountIsInvalid(
                         reg.stake_key_id.clone(),
@@ -483,47 +926,336 @@ impl Ledger {
                 }
             }
             certificate::CertificateContent::StakePoolRegistration(ref reg) => {
-                self.delegation = self.delegation.register_stake_pool(reg.clone())?
+                self.delegation = self.delegation.register_stake_pool(reg.clone())?;
+                self.stake_cache = self.stake_cache.register_pool(&reg.to_id());
             }
             certificate::CertificateContent::StakePoolRetirement(ref reg) => {
-                self.delegation = self.delegation.deregister_stake_pool(&reg.pool_id)?
+                self.delegation = self.delegation.deregister_stake_pool(&reg.pool_id)?;
+                self.stake_cache = self.stake_cache.retire_pool(&reg.pool_id);
+            }
+            certificate::CertificateContent::AccountLock(ref lock) => {
+                let account_key = lock
+                    .account_id
+                    .to_single_account()
+                    .ok_or(Error::AccountIdentifierInvalid)?;
+                let accounts = std::slice::from_ref(&account_key);
+                if certificate::count_signers(lock, accounts, &certificate.signatures) != 1 {
+                    return Err(Error::AccountLockSigIsInvalid {
+                        account: account_key,
+                    });
+                }
+                self.accounts = self.accounts.set_lock(&account_key, lock.until)?;
+            }
+            certificate::CertificateContent::StakePoolOwnershipTransfer(ref transfer) => {
+                let owners = &self
+                    .delegation
+                    .pool_info(&transfer.pool_id)
+                    .ok_or_else(|| {
+                        DelegationError::StakePoolDoesNotExist(transfer.pool_id.clone())
+                    })?
+                    .owners;
+                let signers = certificate::count_signers(transfer, owners, &certificate.signatures);
+                if signers <= owners.len() / 2 {
+                    return Err(DelegationError::StakePoolOwnershipTransferSigIsInvalid.into());
+                }
+                self.delegation = self
+                    .delegation
+                    .transfer_ownership(&transfer.pool_id, transfer.new_owners.clone())?;
+            }
+            certificate::CertificateContent::BulkDelegation(ref bulk) => {
+                // the ledger is dropped on the first failing delegation, so
+                // either all of them are applied or none
+                for (account_id, pool_id) in bulk.delegations.iter() {
+                    if !self.delegation.stake_pool_exists(pool_id) {
+                        return Err(DelegationError::StakeDelegationPoolKeyIsInvalid(
+                            pool_id.clone(),
+                        )
+                        .into());
+                    }
+                    let accounts = std::slice::from_ref(account_id);
+                    if certificate::count_signers(bulk, accounts, &certificate.signatures) == 0 {
+                        return Err(DelegationError::BulkDelegationSigIsInvalid(
+                            account_id.clone(),
+                        )
+                        .into());
+                    }
+                    self = self.set_account_delegation(account_id, pool_id)?;
+                }
             }
         }
+        self.stake_cache =
+            self.stake_cache
+                .checkpoint(&self.accounts, &self.delegation, &self.utxos);
         Ok(self)
     }
 
+    /// Set the pool an account delegates to, moving its stake to the pool
+    fn set_account_delegation(
+        mut self,
+        account_id: &account::Identifier,
+        pool_id: &StakePoolId,
+    ) -> Result<Self, Error> {
+        let accounts = self
+            .accounts
+            .set_delegation(account_id, Some(pool_id.clone()))?;
+        self.stake_cache = self
+            .stake_cache
+            .apply_delegation(&self.accounts, &accounts, account_id);
+        self.accounts = accounts;
+        Ok(self)
+    }
+
+    /// Apply the certificate transaction, as of the current date of the ledger
     pub fn apply_certificate(
+        self,
+        auth_cert: &AuthenticatedTransaction<Address, certificate::Certificate>,
+        dyn_params: &LedgerParameters,
+    ) -> Result<(Self, Value), Error> {
+        let date = self.date;
+        self.apply_certificate_at(auth_cert, dyn_params, date)
+    }
+
+    /// Apply only the content of a certificate, for inspection: there is no
+    /// transaction carrying it, so no fee is paid and no witness is checked.
+    /// The signatures of the certificate are still checked by the contents
+    /// requiring them.
+    #[cfg(any(test, feature = "property-test-api"))]
+    pub fn apply_certificate_content_dry(
+        &self,
+        certificate: &certificate::Certificate,
+    ) -> Result<Self, Error> {
+        self.clone().apply_certificate_content(certificate)
+    }
+
+    fn apply_certificate_at(
         mut self,
         auth_cert: &AuthenticatedTransaction<Address, certificate::Certificate>,
         dyn_params: &LedgerParameters,
+        block_date: BlockDate,
     ) -> Result<(Self, Value), Error> {
         let verified = auth_cert.transaction.extra.verify();
         if verified == chain_crypto::Verification::Failed {
             return Err(Error::CertificateInvalidSignature);
         };
-        let (new_ledger, fee) = self.apply_transaction(auth_cert, dyn_params)?;
+        let fee = (calculate_fee(&dyn_params.fees, &auth_cert.transaction)?
+            + calculate_fee(&dyn_params.per_certificate_fees, &auth_cert.transaction)?)
+        .map_err(|error| Error::FeeCalculationError { error })?;
+        let new_ledger = self.apply_transaction_with_fee(auth_cert, dyn_params, block_date, fee)?;
 
+        let content = &auth_cert.transaction.extra.content;
+        let affected = new_ledger.certificate_affected_accounts(content);
         self = new_ledger.apply_certificate_content(&auth_cert.transaction.extra)?;
+        self.record_certificate(affected, block_date, auth_cert.transaction.hash(), content);
 
         Ok((self, fee))
     }
 
+    /// Get the accounts affected by the certificate, if the certificate
+    /// index is enabled. They depend on the stake pool owners, so this has
+    /// to be called before the certificate is applied.
+    fn certificate_affected_accounts(
+        &self,
+        content: &certificate::CertificateContent,
+    ) -> Option<Vec<account::Identifier>> {
+        self.certificate_index
+            .as_ref()
+            .map(|_| certificates::affected_accounts(&self.delegation, content))
+    }
+
+    /// Record the applied certificate in the certificate index against the
+    /// accounts returned by `certificate_affected_accounts`
+    fn record_certificate(
+        &mut self,
+        affected: Option<Vec<account::Identifier>>,
+        date: BlockDate,
+        transaction_id: TransactionId,
+        content: &certificate::CertificateContent,
+    ) {
+        if let Some(affected) = affected {
+            let certificate = CertificateRef {
+                chain_length: self.chain_length,
+                date,
+                transaction_id,
+                content: content.clone(),
+            };
+            self.certificate_index = self
+                .certificate_index
+                .as_ref()
+                .map(|index| index.add(&affected, certificate));
+        }
+    }
+
+    /// Start recording the certificates affecting each account, if not
+    /// already recorded.
+    ///
+    /// Only the certificates applied from then on are recorded, use
+    /// `new_with_certificate_index` to also record the ones of block0.
+    pub fn enable_certificate_index(&mut self) {
+        if self.certificate_index.is_none() {
+            self.certificate_index = Some(CertificateIndex::new());
+        }
+    }
+
+    /// Get the certificates affecting the account, in the order they were
+    /// applied, if the certificate index is enabled
+    pub fn account_certificates(&self, id: &account::Identifier) -> Vec<CertificateRef> {
+        self.certificate_index
+            .as_ref()
+            .map(|index| index.get(id))
+            .unwrap_or_default()
+    }
+
     pub fn get_stake_distribution(&self) -> StakeDistribution {
         stake::get_distribution(&self.accounts, &self.delegation, &self.utxos)
     }
 
+    /// Get the stake delegated to the given stake pool, cheaper than
+    /// looking it up in `get_stake_distribution`
+    pub fn stake_for_pool(&self, stake_pool_id: &StakePoolId) -> Value {
+        stake::get_pool_stake(&self.accounts, &self.delegation, &self.utxos, stake_pool_id)
+    }
+
+    /// Get the stake distribution maintained incrementally while applying
+    /// transactions and certificates, cheaper than `get_stake_distribution`
+    /// which recomputes it from scratch.
+    pub fn cached_stake_distribution(&self) -> &StakeDistribution {
+        self.stake_cache.distribution()
+    }
+
+    fn refresh_stake_cache(&mut self) {
+        self.stake_cache =
+            StakeDistributionCache::new(&self.accounts, &self.delegation, &self.utxos);
+    }
+
+    /// Check whether the stake pool, given its VRF key, is elected leader
+    /// of the slot at the given date, which has to be in the current epoch.
+    ///
+    /// Eligibility is evaluated against the stake distribution of the ledger
+    /// when entering the epoch. The answers are cached for the epoch, so the
+    /// stake of the pool is only evaluated the first time a slot is checked.
+    pub fn is_slot_leader(
+        &self,
+        pool_id: &StakePoolId,
+        vrf_key: &SecretKey<Curve25519_2HashDH>,
+        date: BlockDate,
+    ) -> Result<bool, leadership::Error> {
+        self.leader_cache.is_slot_leader(pool_id, vrf_key, date)
+    }
+
+    /// Same as `is_slot_leader`, without using the cached answers
+    pub fn is_slot_leader_uncached(
+        &self,
+        pool_id: &StakePoolId,
+        vrf_key: &SecretKey<Curve25519_2HashDH>,
+        date: BlockDate,
+    ) -> Result<bool, leadership::Error> {
+        self.leader_cache
+            .is_slot_leader_uncached(pool_id, vrf_key, date)
+    }
+
+    /// The leader eligibility answers cached for the current epoch
+    pub fn leader_eligibility_cache(&self) -> &LeaderEligibilityCache {
+        &self.leader_cache
+    }
+
     /// access the ledger static parameters
     pub fn get_static_parameters(&self) -> &LedgerStaticParameters {
         self.static_params.as_ref()
     }
 
+    /// Get the address discrimination set at block0
+    pub fn discrimination(&self) -> Discrimination {
+        self.static_params.discrimination
+    }
+
     pub fn accounts(&self) -> &account::Ledger {
         &self.accounts
     }
 
+    /// Get the spending counter an account witness has to be signed with
+    /// to spend from the account, if the account exists
+    pub fn account_spending_counter(
+        &self,
+        id: &account::Identifier,
+    ) -> Option<account::SpendingCounter> {
+        self.account_state(id).map(|state| state.counter)
+    }
+
+    /// Explain why the witness of a transaction spending from a single
+    /// account does not verify.
+    ///
+    /// Returns `AccountSpendingCounterMismatch` if the witness was signed
+    /// with a spending counter close to the expected one, that is with a
+    /// stale or premature counter, and `AccountInvalidSignature` if it is
+    /// not a signature of the account at all. This is not part of the
+    /// validation, as it costs up to a few dozen signature verifications:
+    /// clients call it to diagnose a rejected transaction.
+    pub fn diagnose_account_witness(
+        &self,
+        account: &account::Identifier,
+        transaction_id: &TransactionId,
+        witness: &Witness,
+    ) -> Result<(), Error> {
+        let sig = match witness {
+            Witness::Account(sig) => sig,
+            _ => return Err(Error::ExpectingAccountWitness),
+        };
+        let block0_hash = &self.static_params.block0_initial_hash;
+        let expected = self.accounts.get_state(account)?.counter;
+        let tidsc = WitnessAccountData::new(block0_hash, transaction_id, &expected);
+        if sig.verify(&account.clone().into(), &tidsc) == chain_crypto::Verification::Success {
+            return Ok(());
+        }
+        match find_signed_spending_counter(sig, account, block0_hash, transaction_id, expected) {
+            Some(actual) => Err(Error::AccountSpendingCounterMismatch { expected, actual }),
+            None => Err(Error::AccountInvalidSignature {
+                account: account.clone(),
+                witness: witness.clone(),
+            }),
+        }
+    }
+
+    /// Get the balance, spending counter and delegation of an account,
+    /// either single or multisig, if it exists
+    pub fn account_state(&self, id: &account::Identifier) -> Option<account::AccountState> {
+        let (kind, state) = match self.accounts.get_state(id) {
+            Ok(state) => (account::AccountKind::Single, state),
+            Err(_) => {
+                let id = AccountIdentifier::from_single_account(id.clone()).to_multi_account();
+                (
+                    account::AccountKind::Multisig,
+                    self.multisig.account_state(&id)?,
+                )
+            }
+        };
+        Some(account::AccountState {
+            kind,
+            value: state.value,
+            counter: state.counter,
+            delegation: state.delegation.clone(),
+        })
+    }
+
+    /// Get the number of slots from the last change of the balance of an
+    /// account to the given date, if the account exists and its balance
+    /// changed at a known date
+    pub fn account_coin_age(&self, id: &account::Identifier, now: BlockDate) -> Option<u64> {
+        let last_change = self.accounts.get_state(id).ok()?.last_change?;
+        Some(flat_slot(&self.era, now).saturating_sub(flat_slot(&self.era, last_change)))
+    }
+
     pub fn get_ledger_parameters(&self) -> LedgerParameters {
         LedgerParameters {
             fees: *self.settings.linear_fees,
+            max_output_value: self.settings.max_output_value,
+            reject_zero_account_credit: self.settings.reject_zero_account_credit,
+            min_utxo_value: self.settings.min_utxo_value,
+            transaction_limits: TransactionLimits {
+                max_inputs: self.settings.max_transaction_inputs as usize,
+                max_outputs: self.settings.max_transaction_outputs as usize,
+                max_witnesses: self.settings.max_transaction_witnesses as usize,
+            },
+            per_certificate_fees: self.settings.per_certificate_fees,
         }
     }
 
@@ -539,10 +1271,94 @@ impl Ledger {
         self.chain_length
     }
 
+    /// Get the number of blocks applied on top of the block of the given
+    /// chain length, such as the one including a transaction.
+    pub fn confirmations(&self, inclusion_length: ChainLength) -> u64 {
+        self.chain_length.0.saturating_sub(inclusion_length.0) as u64
+    }
+
+    /// Get the earliest date a block of the given chain length can have,
+    /// reached if every slot after the block0 got a block
+    pub fn min_block_date_for_length(&self, length: ChainLength) -> BlockDate {
+        let first_slot = u64::from(self.era.from_era_to_slot(EpochPosition {
+            epoch: chain_time::Epoch(0),
+            slot: EpochSlotOffset(0),
+        }));
+        let position = self
+            .era
+            .from_slot_to_era(Slot::from(first_slot + length.0 as u64))
+            .expect("slot is after the beginning of the era");
+        BlockDate {
+            epoch: position.epoch.0,
+            slot_id: position.slot.0,
+        }
+    }
+
     pub fn settings(&mut self) -> &mut setting::Settings {
         &mut self.settings
     }
 
+    /// Iterate over the BFT leaders, in the order of their rotation
+    pub fn bft_leaders<'a>(&'a self) -> impl Iterator<Item = &'a leadership::bft::LeaderId> + 'a {
+        self.settings.bft_leaders()
+    }
+
+    /// Get the registration information of a registered stake pool
+    pub fn stake_pool_info(&self, pool_id: &StakePoolId) -> Option<&StakePoolInfo> {
+        self.delegation.pool_info(pool_id)
+    }
+
+    /// Iterate over every registered stake pool, ordered by stake pool id
+    pub fn stake_pools<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a StakePoolId, &'a StakePoolInfo)> + 'a {
+        let mut pools: Vec<_> = self.delegation.stake_pools.iter().collect();
+        pools.sort_by(|(a, _), (b, _)| a.cmp(b));
+        pools.into_iter()
+    }
+
+    /// Get the stake pools that were active when entering the given epoch,
+    /// sorted by identifier, if the epoch is still within the stability
+    /// depth
+    pub fn active_pools_at(&self, epoch: Epoch) -> Option<Vec<StakePoolId>> {
+        self.active_pools.get(epoch).map(|pools| pools.to_vec())
+    }
+
+    /// Freeze the set of stake pools registered and not retired at the
+    /// start of the given epoch
+    fn record_active_pools(&mut self, epoch: Epoch) {
+        let mut pools: Vec<StakePoolId> = self
+            .delegation
+            .stake_pools
+            .iter()
+            .map(|(pool_id, _)| pool_id.clone())
+            .filter(|pool_id| match self.delegation.retirement_epoch(pool_id) {
+                Some(retirement) => retirement > epoch,
+                None => true,
+            })
+            .collect();
+        pools.sort();
+        self.active_pools = self.active_pools.record(
+            epoch,
+            self.chain_length,
+            pools,
+            self.settings.epoch_stability_depth,
+        );
+    }
+
+    /// Iterate over every delegated account along with the stake pool it
+    /// delegates to. Accounts without delegation are not listed.
+    pub fn delegations<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (account::Identifier, StakePoolId)> + 'a {
+        self.accounts.iter().filter_map(|(identifier, state)| {
+            state
+                .delegation()
+                .as_ref()
+                .map(|pool_id| (identifier.clone(), pool_id.clone()))
+        })
+    }
+
     pub fn delegation(&mut self) -> &mut DelegationState {
         &mut self.delegation
     }
@@ -551,25 +1367,156 @@ impl Ledger {
         self.date
     }
 
+    /// Get the update proposals that cannot be accepted anymore before they
+    /// expire, given an estimate of the number of blocks remaining in the
+    /// current epoch.
+    pub fn stalled_proposals(
+        &self,
+        remaining_blocks_estimate: u32,
+    ) -> Vec<update::UpdateProposalId> {
+        self.updates
+            .stalled_proposals(&self.settings, self.date, remaining_blocks_estimate)
+    }
+
+    /// The current consensus nonce, hex encoded, for diagnostics
+    pub fn consensus_nonce_hex(&self) -> String {
+        self.settings
+            .consensus_nonce
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
     pub fn era(&self) -> &TimeEra {
         &self.era
     }
 
+    /// Number of UTxOs
+    pub fn utxo_count(&self) -> usize {
+        self.utxos.count()
+    }
+
+    /// Total value held by the UTxOs
+    pub fn utxo_total_value(&self) -> Result<Value, ValueError> {
+        self.utxos.total_value()
+    }
+
+    /// Total value held by the legacy UTxOs
+    pub fn old_utxo_total_value(&self) -> Result<Value, ValueError> {
+        Value::sum(self.oldutxos.iter().map(|entry| entry.output.value))
+    }
+
+    /// Total value held by the accounts
+    pub fn account_total_value(&self) -> Result<Value, ValueError> {
+        self.accounts.get_total_value()
+    }
+
+    /// Total value held by the multisig accounts
+    pub fn multisig_total_value(&self) -> Result<Value, ValueError> {
+        self.multisig.get_total_value()
+    }
+
+    /// Iterate over the declarations of every multisig account, ordered by
+    /// identifier, including the accounts holding no value
+    pub fn multisig_accounts<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (multisig::Identifier, &'a multisig::Declaration)> + 'a {
+        let mut declarations: Vec<_> = self
+            .multisig
+            .iter_declarations()
+            .map(|(identifier, declaration)| (identifier.clone(), declaration))
+            .collect();
+        declarations.sort_by(|a, b| a.0.cmp(&b.0));
+        declarations.into_iter()
+    }
+
+    /// Get the declaration of a multisig account, if it exists
+    pub fn multisig_declaration(
+        &self,
+        identifier: &multisig::Identifier,
+    ) -> Option<&multisig::Declaration> {
+        self.multisig.get_declaration(identifier)
+    }
+
+    /// Total value controlled by a wallet holding the given keys: the utxos
+    /// spendable by one of the keys, group outputs included, and the
+    /// balances of the accounts of the keys.
+    pub fn wallet_balance(&self, keys: &[PublicKey<Ed25519>]) -> Value {
+        let utxo_values = self
+            .utxos
+            .iter()
+            .filter(|entry| match entry.output.address.kind() {
+                Kind::Single(pk) | Kind::Group(pk, _) => keys.contains(pk),
+                _ => false,
+            })
+            .map(|entry| entry.output.value);
+        let account_values = keys.iter().filter_map(|key| {
+            self.accounts
+                .get_state(&account::Identifier::from(key.clone()))
+                .ok()
+                .map(|state| state.get_value())
+        });
+        utxo_values
+            .chain(account_values)
+            .fold(Value::zero(), |total, value| {
+                Value(total.0.saturating_add(value.0))
+            })
+    }
+
+    /// Get every input currently spendable for the given address, along
+    /// with its value: the unspent outputs sent to the address and, if the
+    /// address refers to an account with a balance, the account input.
+    pub fn spendable_inputs(&self, addr: &Address) -> Vec<(Input, Value)> {
+        let mut inputs: Vec<(Input, Value)> = self
+            .utxos
+            .iter()
+            .filter(|entry| &entry.output.address == addr)
+            .map(|entry| {
+                let value = entry.output.value;
+                (Input::from_utxo_entry(entry), value)
+            })
+            .collect();
+
+        let account_input = match addr.kind() {
+            Kind::Account(pk) | Kind::Delegation(pk, _) | Kind::Group(_, pk) => {
+                let identifier = account::Identifier::from(pk.clone());
+                self.accounts.get_state(&identifier).ok().map(|state| {
+                    (
+                        Input::from_account_single(identifier, state.value()),
+                        state.value(),
+                    )
+                })
+            }
+            Kind::Multisig(identifier) => {
+                let identifier = multisig::Identifier::from(identifier.clone());
+                self.multisig
+                    .get_value(&identifier)
+                    .ok()
+                    .map(|value| (Input::from_multisig_account(identifier, value), value))
+            }
+            Kind::Single(_) => None,
+        };
+        inputs.extend(account_input.filter(|(_, value)| *value != Value::zero()));
+        inputs
+    }
+
+    /// Total value in the ledger, the pot included
+    pub fn total_value(&self) -> Result<Value, ValueError> {
+        Value::sum(
+            vec![
+                self.old_utxo_total_value()?,
+                self.utxo_total_value()?,
+                self.account_total_value()?,
+                self.multisig_total_value()?,
+                self.pot,
+            ]
+            .into_iter(),
+        )
+    }
+
     fn validate_utxo_total_value(&self) -> Result<(), Error> {
-        let old_utxo_values = self.oldutxos.iter().map(|entry| entry.output.value);
-        let new_utxo_values = self.utxos.iter().map(|entry| entry.output.value);
-        let account_value = self.accounts.get_total_value().map_err(|_| Error::Block0 {
-            source: Block0Error::UtxoTotalValueTooBig,
-        })?;
-        let multisig_value = self.multisig.get_total_value().map_err(|_| Error::Block0 {
-            source: Block0Error::UtxoTotalValueTooBig,
-        })?;
-        let all_utxo_values = old_utxo_values
-            .chain(new_utxo_values)
-            .chain(Some(account_value))
-            .chain(Some(multisig_value))
-            .chain(Some(self.pot));
-        Value::sum(all_utxo_values).map_err(|_| Error::Block0 {
+        self.total_value().map_err(|_| Error::Block0 {
             source: Block0Error::UtxoTotalValueTooBig,
         })?;
         Ok(())
@@ -579,6 +1526,7 @@ impl Ledger {
 fn apply_old_declaration(
     mut utxos: utxo::Ledger<legacy::OldAddress>,
     decl: &legacy::UtxoDeclaration,
+    chain_length: ChainLength,
 ) -> Result<utxo::Ledger<legacy::OldAddress>, Error> {
     assert!(decl.addrs.len() < 255);
     let txid = decl.hash();
@@ -590,41 +1538,87 @@ fn apply_old_declaration(
         };
         outputs.push((i as u8, output))
     }
-    utxos = utxos.add(&txid, &outputs)?;
+    utxos = utxos.add(&txid, &outputs, chain_length)?;
     Ok(utxos)
 }
 
 /// Apply the transaction
-fn internal_apply_transaction(
-    mut ledger: Ledger,
+fn calculate_fee<P, F: FeeAlgorithm<P>>(fee_algorithm: &F, part: &P) -> Result<Value, Error> {
+    fee_algorithm
+        .calculate(part)
+        .ok_or(Error::FeeCalculationError {
+            error: ValueError::Overflow,
+        })
+}
+
+/// Get the slot of the date as a number of slots from the beginning of time
+fn flat_slot(era: &TimeEra, date: BlockDate) -> u64 {
+    let epoch_start = era.from_era_to_slot(EpochPosition {
+        epoch: chain_time::Epoch(date.epoch),
+        slot: EpochSlotOffset(0),
+    });
+    u64::from(epoch_start) + date.slot_id as u64
+}
+
+/// The sub-ledgers a transaction spends from, with its inputs spent
+struct SpentInputs {
+    utxos: utxo::Ledger<Address>,
+    oldutxos: utxo::Ledger<legacy::OldAddress>,
+    accounts: account::Ledger,
+    multisig: multisig::Ledger,
+}
+
+/// Run all the checks of a transaction against the ledger, without changing
+/// it, and return the sub-ledgers with the inputs of the transaction spent.
+///
+/// The inputs are spent one after the other, so an input spent twice by the
+/// same transaction is rejected.
+fn internal_validate_transaction(
+    ledger: &Ledger,
     dyn_params: &LedgerParameters,
+    block_date: BlockDate,
     transaction_id: &TransactionId,
+    valid_until: Option<BlockDate>,
     inputs: &[Input],
     outputs: &[Output<Address>],
     witnesses: &[Witness],
     fee: Value,
-) -> Result<Ledger, Error> {
-    if inputs.len() > MAX_TRANSACTION_INPUTS_COUNT {
+) -> Result<SpentInputs, Error> {
+    if let Some(valid_until) = valid_until {
+        if block_date > valid_until {
+            return Err(Error::TransactionExpired {
+                valid_until,
+                block_date,
+            });
+        }
+    }
+
+    let limits = &dyn_params.transaction_limits;
+    if inputs.len() > limits.max_inputs {
         return Err(Error::TransactionHasTooManyInputs {
-            expected: MAX_TRANSACTION_INPUTS_COUNT,
+            expected: limits.max_inputs,
             actual: inputs.len(),
         });
     }
 
-    if outputs.len() > MAX_TRANSACTION_OUTPUTS_COUNT {
+    if outputs.len() > limits.max_outputs {
         return Err(Error::TransactionHasTooManyOutputs {
-            expected: MAX_TRANSACTION_OUTPUTS_COUNT,
+            expected: limits.max_outputs,
             actual: outputs.len(),
         });
     }
 
-    if witnesses.len() > MAX_TRANSACTION_WITNESSES_COUNT {
+    if witnesses.len() > limits.max_witnesses {
         return Err(Error::TransactionHasTooManyWitnesses {
-            expected: MAX_TRANSACTION_WITNESSES_COUNT,
+            expected: limits.max_witnesses,
             actual: witnesses.len(),
         });
     }
 
+    if ledger.settings.require_sorted_inputs && !inputs.windows(2).all(|w| w[0] <= w[1]) {
+        return Err(Error::InputsNotSorted);
+    }
+
     // 1. verify that number of signatures matches number of
     // transactions
     if inputs.len() != witnesses.len() {
@@ -634,25 +1628,48 @@ fn internal_apply_transaction(
         });
     }
 
+    if !ledger.settings.blocked_addresses.is_empty() {
+        let input_addresses = inputs
+            .iter()
+            .zip(witnesses.iter())
+            .filter_map(|(input, witness)| input_address(ledger, input, witness));
+        let output_addresses = outputs.iter().map(|output| output.address.clone());
+        for address in input_addresses.chain(output_addresses) {
+            if ledger.settings.is_address_blocked(&address) {
+                return Err(Error::BlockedAddress { address });
+            }
+        }
+    }
+
     // 2. validate inputs of transaction by gathering what we know of it,
     // then verifying the associated witness
+    let mut spent = SpentInputs {
+        utxos: ledger.utxos.clone(),
+        oldutxos: ledger.oldutxos.clone(),
+        accounts: ledger.accounts.clone(),
+        multisig: ledger.multisig.clone(),
+    };
     for (input, witness) in inputs.iter().zip(witnesses.iter()) {
         match input.to_enum() {
             InputEnum::UtxoInput(utxo) => {
-                ledger = input_utxo_verify(ledger, transaction_id, &utxo, witness)?
+                spent =
+                    input_utxo_verify(ledger, spent, block_date, transaction_id, &utxo, witness)?
             }
             InputEnum::AccountInput(account_id, value) => {
                 let (single, multi) = input_account_verify(
-                    ledger.accounts,
-                    ledger.multisig,
+                    spent.accounts,
+                    spent.multisig,
+                    block_date.epoch,
+                    ledger.settings.account_min_balance,
+                    ledger.settings.max_spending_counter.into(),
                     &ledger.static_params.block0_initial_hash,
                     transaction_id,
                     &account_id,
                     value,
                     witness,
                 )?;
-                ledger.accounts = single;
-                ledger.multisig = multi;
+                spent.accounts = single;
+                spent.multisig = multi;
             }
         }
     }
@@ -669,47 +1686,163 @@ fn internal_apply_transaction(
         });
     }
 
-    // 4. add the new outputs
-    let (new_utxos, new_accounts, new_multisig) = internal_apply_transaction_output(
-        ledger.utxos,
-        ledger.accounts,
-        ledger.multisig,
-        &ledger.static_params,
+    // 4. verify the outputs
+    for output in outputs {
+        output_verify(
+            &ledger.delegation,
+            &ledger.static_params,
+            dyn_params,
+            output,
+        )?;
+    }
+
+    Ok(spent)
+}
+
+fn internal_apply_transaction(
+    mut ledger: Ledger,
+    dyn_params: &LedgerParameters,
+    block_date: BlockDate,
+    transaction_id: &TransactionId,
+    valid_until: Option<BlockDate>,
+    inputs: &[Input],
+    outputs: &[Output<Address>],
+    witnesses: &[Witness],
+    fee: Value,
+) -> Result<Ledger, Error> {
+    let spent = internal_validate_transaction(
+        &ledger,
         dyn_params,
+        block_date,
+        transaction_id,
+        valid_until,
+        inputs,
+        outputs,
+        witnesses,
+        fee,
+    )?;
+    ledger.oldutxos = spent.oldutxos;
+
+    // add the new outputs
+    let (new_utxos, new_accounts, new_multisig) = internal_apply_transaction_output(
+        spent.utxos,
+        spent.accounts,
+        spent.multisig,
+        ledger.chain_length,
         transaction_id,
         outputs,
     )?;
     ledger.utxos = new_utxos;
-    ledger.accounts = new_accounts;
+    ledger.accounts =
+        record_account_balance_changes(new_accounts, block_date, inputs, witnesses, outputs)?;
     ledger.multisig = new_multisig;
 
-    // 5. add fee to pot
+    // add fee to pot
     ledger.pot = (ledger.pot + fee).map_err(|error| Error::PotValueInvalid { error })?;
 
     Ok(ledger)
 }
 
+/// Record the date at which the balance of the single accounts spent from
+/// or paid to by a transaction changed
+fn record_account_balance_changes(
+    mut accounts: account::Ledger,
+    date: BlockDate,
+    inputs: &[Input],
+    witnesses: &[Witness],
+    outputs: &[Output<Address>],
+) -> Result<account::Ledger, Error> {
+    let spent = inputs
+        .iter()
+        .zip(witnesses.iter())
+        .filter_map(|(input, witness)| match (input.to_enum(), witness) {
+            (InputEnum::AccountInput(account_id, _), Witness::Account(_)) => {
+                account_id.to_single_account()
+            }
+            _ => None,
+        });
+    let paid = outputs
+        .iter()
+        .filter_map(|output| match output.address.kind() {
+            Kind::Account(identifier) | Kind::Delegation(identifier, _) => {
+                Some(identifier.clone().into())
+            }
+            _ => None,
+        });
+    for account in spent.chain(paid) {
+        // an account emptied at its last spending counter is removed
+        if accounts.exists(&account) {
+            accounts = accounts.set_last_change(&account, date)?;
+        }
+    }
+    Ok(accounts)
+}
+
+/// Check an output can be added to the ledger
+fn output_verify(
+    delegation: &DelegationState,
+    static_params: &LedgerStaticParameters,
+    dyn_params: &LedgerParameters,
+    output: &Output<Address>,
+) -> Result<(), Error> {
+    // Reject zero-valued outputs, unless crediting an account and
+    // allowed by the settings.
+    if output.value == Value::zero() {
+        let ignored = match output.address.kind() {
+            Kind::Account(_) => !dyn_params.reject_zero_account_credit,
+            _ => false,
+        };
+        if !ignored {
+            return Err(Error::ZeroOutput {
+                output: output.clone(),
+            });
+        }
+    }
+
+    if let Some(max) = dyn_params.max_output_value {
+        if output.value > max {
+            return Err(Error::OutputValueTooLarge {
+                output: output.clone(),
+                max,
+            });
+        }
+    }
+
+    if output.address.discrimination() != static_params.discrimination {
+        return Err(Error::InvalidDiscrimination);
+    }
+
+    match output.address.kind() {
+        // accounts aggregate their credits, only new utxos have a minimum
+        Kind::Single(_) | Kind::Group(_, _) if output.value < dyn_params.min_utxo_value => {
+            Err(Error::OutputBelowMinimum {
+                output: output.clone(),
+                minimum: dyn_params.min_utxo_value,
+            })
+        }
+        // an account created by the output is delegated to the stake pool
+        // at the same time, the pool has to exist
+        Kind::Delegation(_, pool_id) => {
+            let pool_id = StakePoolId::from(*pool_id);
+            if !delegation.stake_pool_exists(&pool_id) {
+                return Err(DelegationError::StakeDelegationPoolKeyIsInvalid(pool_id).into());
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
 fn internal_apply_transaction_output(
     mut utxos: utxo::Ledger<Address>,
     mut accounts: account::Ledger,
     mut multisig: multisig::Ledger,
-    static_params: &LedgerStaticParameters,
-    _dyn_params: &LedgerParameters,
+    chain_length: ChainLength,
     transaction_id: &TransactionId,
     outputs: &[Output<Address>],
 ) -> Result<(utxo::Ledger<Address>, account::Ledger, multisig::Ledger), Error> {
     let mut new_utxos = Vec::new();
     for (index, output) in outputs.iter().enumerate() {
-        // Reject zero-valued outputs.
-        if output.value == Value::zero() {
-            return Err(Error::ZeroOutput {
-                output: output.clone(),
-            });
-        }
-
-        if output.address.discrimination() != static_params.discrimination {
-            return Err(Error::InvalidDiscrimination);
-        }
         match output.address.kind() {
             Kind::Single(_) => {
                 new_utxos.push((index as u8, output.clone()));
@@ -723,6 +1856,10 @@ fn internal_apply_transaction_output(
                 new_utxos.push((index as u8, output.clone()));
             }
             Kind::Account(identifier) => {
+                // crediting nothing neither changes nor creates the account
+                if output.value == Value::zero() {
+                    continue;
+                }
                 // don't have a way to make a newtype ref from the ref so .clone()
                 let account = identifier.clone().into();
                 accounts = match accounts.add_value(&account, output.value) {
@@ -737,30 +1874,104 @@ fn internal_apply_transaction_output(
                 let identifier = multisig::Identifier::from(identifier.clone());
                 multisig = multisig.add_value(&identifier, output.value)?;
             }
+            Kind::Delegation(identifier, pool_id) => {
+                // the delegation of an existing account can only be changed
+                // with its signature, so the pool is then ignored.
+                let account = identifier.clone().into();
+                let pool_id = StakePoolId::from(*pool_id);
+                accounts = match accounts.add_value(&account, output.value) {
+                    Ok(accounts) => accounts,
+                    Err(account::LedgerError::NonExistent) => accounts
+                        .add_account(&account, output.value, ())?
+                        .set_delegation(&account, Some(pool_id))?,
+                    Err(error) => return Err(error.into()),
+                };
+            }
         }
     }
 
-    utxos = utxos.add(transaction_id, &new_utxos)?;
+    utxos = utxos.add(transaction_id, &new_utxos, chain_length)?;
     Ok((utxos, accounts, multisig))
 }
 
+/// Get the address an input spends from, if known. The address of an
+/// old utxo is not a chain address, so it is never returned.
+fn input_address(ledger: &Ledger, input: &Input, witness: &Witness) -> Option<Address> {
+    let discrimination = ledger.static_params.discrimination;
+    match (input.to_enum(), witness) {
+        (InputEnum::UtxoInput(utxo), Witness::Utxo(_)) => ledger
+            .utxos
+            .get(&utxo.transaction_id, &utxo.output_index)
+            .map(|entry| entry.output.address.clone()),
+        (InputEnum::AccountInput(account_id, _), Witness::Account(_)) => account_id
+            .to_single_account()
+            .map(|account| Address(discrimination, Kind::Account(account.into()))),
+        (InputEnum::AccountInput(account_id, _), Witness::Multisig(_)) => {
+            let mut identifier = [0; 32];
+            identifier.copy_from_slice(account_id.to_multi_account().as_ref());
+            Some(Address(discrimination, Kind::Multisig(identifier)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether all the outputs of the transaction go back to the addresses its
+/// inputs are spent from
+fn is_self_transfer<Extra>(
+    ledger: &Ledger,
+    signed_tx: &AuthenticatedTransaction<Address, Extra>,
+) -> bool {
+    let tx = &signed_tx.transaction;
+    // left to the regular validation to report
+    if tx.inputs.len() != signed_tx.witnesses.len() {
+        return false;
+    }
+    let input_addresses: Option<Vec<Address>> = tx
+        .inputs
+        .iter()
+        .zip(signed_tx.witnesses.iter())
+        .map(|(input, witness)| input_address(ledger, input, witness))
+        .collect();
+    match input_addresses {
+        Some(input_addresses) => {
+            !tx.outputs.is_empty()
+                && tx
+                    .outputs
+                    .iter()
+                    .all(|output| input_addresses.contains(&output.address))
+        }
+        None => false,
+    }
+}
+
 fn input_utxo_verify(
-    mut ledger: Ledger,
+    ledger: &Ledger,
+    mut spent: SpentInputs,
+    block_date: BlockDate,
     transaction_id: &TransactionId,
     utxo: &UtxoPointer,
     witness: &Witness,
-) -> Result<Ledger, Error> {
+) -> Result<SpentInputs, Error> {
     match witness {
         Witness::Account(_) => Err(Error::ExpectingUtxoWitness),
         Witness::Multisig(_) => Err(Error::ExpectingUtxoWitness),
         Witness::OldUtxo(xpub, signature) => {
-            let (old_utxos, associated_output) = ledger
+            if let Some(deadline) = ledger.settings.old_utxo_redemption_deadline {
+                if block_date > deadline {
+                    return Err(Error::OldUtxoRedemptionClosed {
+                        deadline,
+                        block_date,
+                    });
+                }
+            }
+
+            let (old_utxos, associated_output) = spent
                 .oldutxos
                 .remove(&utxo.transaction_id, utxo.output_index)?;
 
-            ledger.oldutxos = old_utxos;
+            spent.oldutxos = old_utxos;
             if utxo.value != associated_output.value {
-                return Err(Error::UtxoValueNotMatching {
+                return Err(Error::InputValueMismatch {
                     expected: utxo.value,
                     value: associated_output.value,
                 });
@@ -785,15 +1996,15 @@ fn input_utxo_verify(
                 });
             };
 
-            Ok(ledger)
+            Ok(spent)
         }
         Witness::Utxo(signature) => {
-            let (new_utxos, associated_output) = ledger
+            let (new_utxos, associated_output) = spent
                 .utxos
                 .remove(&utxo.transaction_id, utxo.output_index)?;
-            ledger.utxos = new_utxos;
+            spent.utxos = new_utxos;
             if utxo.value != associated_output.value {
-                return Err(Error::UtxoValueNotMatching {
+                return Err(Error::InputValueMismatch {
                     expected: utxo.value,
                     value: associated_output.value,
                 });
@@ -812,14 +2023,60 @@ fn input_utxo_verify(
                     witness: witness.clone(),
                 });
             };
-            Ok(ledger)
+            Ok(spent)
         }
     }
 }
 
+/// Number of spending counters on each side of the expected one tried when
+/// an account witness does not verify
+const SPENDING_COUNTER_SEARCH_WINDOW: u32 = 16;
+
+/// Look for a spending counter close to the expected one that the account
+/// witness was signed with. A witness verifying with another counter is a
+/// genuine signature made with a stale or premature counter, rather than a
+/// bad signature.
+fn find_signed_spending_counter(
+    sig: &SpendingSignature<WitnessAccountData>,
+    account: &account::Identifier,
+    block0_hash: &HeaderHash,
+    transaction_id: &TransactionId,
+    expected: account::SpendingCounter,
+) -> Option<account::SpendingCounter> {
+    let expected = u32::from(expected);
+    let public_key: PublicKey<Ed25519> = account.clone().into();
+    (expected.saturating_sub(SPENDING_COUNTER_SEARCH_WINDOW)
+        ..=expected.saturating_add(SPENDING_COUNTER_SEARCH_WINDOW))
+        .filter(|counter| *counter != expected)
+        .map(account::SpendingCounter::from)
+        .find(|counter| {
+            let tidsc = WitnessAccountData::new(block0_hash, transaction_id, counter);
+            sig.verify(&public_key, &tidsc) == chain_crypto::Verification::Success
+        })
+}
+
+/// An account can only be emptied entirely, or left with at least the minimum balance
+fn check_min_balance(
+    account: AccountIdentifier,
+    remaining: Value,
+    min: Value,
+) -> Result<(), Error> {
+    if remaining != Value::zero() && remaining < min {
+        return Err(Error::AccountBelowMinBalance {
+            account,
+            remaining,
+            min,
+        });
+    }
+    Ok(())
+}
+
 fn input_account_verify(
     mut ledger: account::Ledger,
     mut mledger: multisig::Ledger,
+    current_epoch: Epoch,
+    min_balance: Value,
+    max_spending_counter: account::SpendingCounter,
     block0_hash: &HeaderHash,
     transaction_id: &TransactionId,
     account: &AccountIdentifier,
@@ -837,8 +2094,29 @@ fn input_account_verify(
                 .to_single_account()
                 .ok_or(Error::AccountIdentifierInvalid)?;
 
-            let (new_ledger, spending_counter) = ledger.remove_value(&account, value)?;
+            let state = ledger.get_state(&account)?;
+            if state.is_locked_at(current_epoch) {
+                return Err(Error::AccountLocked {
+                    until: state.locked_until.unwrap_or(current_epoch),
+                    account,
+                });
+            }
+
+            if value > state.value() {
+                return Err(Error::InputValueMismatch {
+                    expected: value,
+                    value: state.value(),
+                });
+            }
+
+            let (new_ledger, spending_counter) =
+                ledger.remove_value(&account, value, max_spending_counter)?;
             ledger = new_ledger;
+            check_min_balance(
+                AccountIdentifier::from_single_account(account.clone()),
+                ledger.get_state(&account)?.value(),
+                min_balance,
+            )?;
 
             let tidsc = WitnessAccountData::new(block0_hash, transaction_id, &spending_counter);
             let verified = sig.verify(&account.clone().into(), &tidsc);
@@ -854,8 +2132,21 @@ fn input_account_verify(
             // refine account to a multisig account identifier
             let account = account.to_multi_account();
 
+            let available = mledger.get_value(&account)?;
+            if value > available {
+                return Err(Error::InputValueMismatch {
+                    expected: value,
+                    value: available,
+                });
+            }
+
             let (new_ledger, declaration, spending_counter) =
-                mledger.remove_value(&account, value)?;
+                mledger.remove_value(&account, value, max_spending_counter)?;
+            check_min_balance(
+                AccountIdentifier::from_multi_account(account.clone()),
+                new_ledger.get_value(&account)?,
+                min_balance,
+            )?;
 
             let data_to_verify =
                 WitnessMultisigData::new(&block0_hash, &transaction_id, &spending_counter);
@@ -889,6 +2180,12 @@ pub enum Entry<'a> {
             &'a crate::update::UpdateProposalState,
         ),
     ),
+    RejectedProposal(
+        (
+            &'a crate::update::UpdateProposalId,
+            &'a crate::update::RejectionReason,
+        ),
+    ),
     MultisigAccount(
         (
             &'a crate::multisig::Identifier,
@@ -907,6 +2204,8 @@ pub enum Entry<'a> {
             &'a crate::stake::StakePoolInfo,
         ),
     ),
+    ActivePools((Epoch, ChainLength, &'a [StakePoolId])),
+    LeaderSelection(LeaderSelectionSnapshot),
 }
 
 pub struct Globals {
@@ -929,11 +2228,19 @@ enum IterState<'a> {
             crate::update::UpdateProposalState,
         >,
     ),
+    RejectedProposals(
+        std::collections::btree_map::Iter<
+            'a,
+            crate::update::UpdateProposalId,
+            crate::update::RejectionReason,
+        >,
+    ),
     MultisigAccounts(crate::accounting::account::Iter<'a, crate::multisig::Identifier, ()>),
     MultisigDeclarations(
         imhamt::HamtIter<'a, crate::multisig::Identifier, crate::multisig::Declaration>,
     ),
     StakePools(imhamt::HamtIter<'a, crate::stake::StakePoolId, crate::stake::StakePoolInfo>),
+    ActivePools(std::collections::btree_map::Iter<'a, Epoch, (ChainLength, Vec<StakePoolId>)>),
     Done,
 }
 
@@ -987,11 +2294,18 @@ impl<'a> Iterator for LedgerIterator<'a> {
             }
             IterState::UpdateProposals(iter) => match iter.next() {
                 None => {
-                    self.state = IterState::MultisigAccounts(self.ledger.multisig.iter_accounts());
+                    self.state = IterState::RejectedProposals(self.ledger.updates.rejected.iter());
                     self.next()
                 }
                 Some(x) => Some(Entry::UpdateProposal(x)),
             },
+            IterState::RejectedProposals(iter) => match iter.next() {
+                None => {
+                    self.state = IterState::MultisigAccounts(self.ledger.multisig.iter_accounts());
+                    self.next()
+                }
+                Some(x) => Some(Entry::RejectedProposal(x)),
+            },
             IterState::MultisigAccounts(iter) => match iter.next() {
                 None => {
                     self.state =
@@ -1009,11 +2323,22 @@ impl<'a> Iterator for LedgerIterator<'a> {
             },
             IterState::StakePools(iter) => match iter.next() {
                 None => {
-                    self.state = IterState::Done;
+                    self.state = IterState::ActivePools(self.ledger.active_pools.iter());
                     self.next()
                 }
                 Some(x) => Some(Entry::StakePool(x)),
             },
+            IterState::ActivePools(iter) => match iter.next() {
+                None => {
+                    self.state = IterState::Done;
+                    Some(Entry::LeaderSelection(self.ledger.leader_cache.snapshot()))
+                }
+                Some((epoch, (chain_length, pools))) => Some(Entry::ActivePools((
+                    *epoch,
+                    *chain_length,
+                    pools.as_slice(),
+                ))),
+            },
             IterState::Done => None,
         }
     }
@@ -1037,7 +2362,9 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
         let mut updates = update::UpdateState::new();
         let mut multisig_accounts = vec![];
         let mut multisig_declarations = vec![];
-        let delegation = DelegationState::new();
+        let mut delegation = DelegationState::new();
+        let mut active_pools = vec![];
+        let mut leader_selection = None;
         let mut globals = None;
 
         for entry in iter {
@@ -1049,13 +2376,15 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
                 Entry::Utxo(entry) => {
                     utxos
                         .entry(entry.transaction_id)
-                        .or_insert(vec![])
+                        .or_insert((entry.creation_height, vec![]))
+                        .1
                         .push((entry.output_index, entry.output.clone()));
                 }
                 Entry::OldUtxo(entry) => {
                     oldutxos
                         .entry(entry.transaction_id)
-                        .or_insert(vec![])
+                        .or_insert((entry.creation_height, vec![]))
+                        .1
                         .push((entry.output_index, entry.output.clone()));
                 }
                 Entry::Account((account_id, account_state)) => {
@@ -1069,6 +2398,9 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
                         .proposals
                         .insert(proposal_id.clone(), proposal_state.clone());
                 }
+                Entry::RejectedProposal((proposal_id, reason)) => {
+                    updates.rejected.insert(proposal_id.clone(), reason.clone());
+                }
                 Entry::MultisigAccount((account_id, account_state)) => {
                     multisig_accounts.push((account_id.clone(), account_state.clone()));
                 }
@@ -1076,29 +2408,54 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
                     multisig_declarations.push((id.clone(), decl.clone()));
                 }
                 Entry::StakePool((pool_id, pool_state)) => {
-                    delegation
+                    delegation.stake_pools = delegation
                         .stake_pools
                         .insert(pool_id.clone(), pool_state.clone())
-                        .unwrap();
+                        .map_err(|_| {
+                            DelegationError::StakePoolAlreadyRegistered(pool_id.clone())
+                        })?;
+                }
+                Entry::ActivePools((epoch, chain_length, pools)) => {
+                    active_pools.push((epoch, chain_length, pools.to_vec()));
+                }
+                Entry::LeaderSelection(selection) => {
+                    leader_selection = Some(selection);
                 }
             }
         }
 
         let globals = globals.ok_or(Error::IncompleteLedger)?;
-
-        Ok(Ledger {
-            utxos: utxos.into_iter().collect(),
-            oldutxos: oldutxos.into_iter().collect(),
+        let leader_selection = leader_selection.ok_or(Error::IncompleteLedger)?;
+
+        let mut ledger = Ledger {
+            utxos: utxos
+                .into_iter()
+                .map(|(tid, (creation_height, outputs))| (tid, creation_height, outputs))
+                .collect(),
+            oldutxos: oldutxos
+                .into_iter()
+                .map(|(tid, (creation_height, outputs))| (tid, creation_height, outputs))
+                .collect(),
             accounts: accounts.into_iter().collect(),
             settings: setting::Settings::new().apply(&config_params)?,
             updates,
             multisig: multisig::Ledger::restore(multisig_accounts, multisig_declarations),
             delegation,
+            stake_cache: StakeDistributionCache::empty(),
+            leader_cache: LeaderEligibilityCache::from_snapshot(leader_selection),
             static_params: Arc::new(globals.static_params),
             date: globals.date,
             chain_length: globals.chain_length,
             era: globals.era,
             pot: Value::zero(),
-        })
+            certificate_index: None,
+            active_pools: active_pools.into_iter().collect(),
+        };
+        ledger.refresh_stake_cache();
+        let epoch = ledger.date.epoch;
+        if ledger.active_pools.get(epoch).is_none() {
+            ledger.record_active_pools(epoch);
+        }
+        Ok(ledger)
     }
 }
@@ -1,6 +1,13 @@
+mod active_pools;
+pub mod certificates;
 pub mod ledger;
+pub mod snapshot;
+pub mod undo;
 
+pub use certificates::CertificateRef;
 pub use ledger::*;
+pub use snapshot::LedgerSnapshot;
+pub use undo::UndoLog;
 
 cfg_if! {
    if #[cfg(test)] {
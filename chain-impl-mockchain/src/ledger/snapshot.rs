@@ -0,0 +1,47 @@
+//! Read-only view of the ledger, to serve queries while the chain advances.
+
+use super::ledger::Ledger;
+use crate::account;
+use crate::stake::{StakeDistribution, StakePoolId, StakePoolInfo};
+use crate::utxo;
+use chain_addr::Address;
+
+/// A frozen state of the ledger exposing only the read methods.
+///
+/// Taking a snapshot clones the ledger, which only copies the roots of its
+/// persistent maps and increments reference counts: the entries are shared
+/// with the ledger and the other snapshots, and the memory they use is only
+/// released when the last of them is dropped. Later blocks applied to the
+/// ledger are not visible in the snapshot.
+///
+/// The snapshot is `Send + Sync`, so it can be shared between the threads
+/// serving queries, for example behind an `Arc`.
+#[derive(Clone)]
+pub struct LedgerSnapshot(Ledger);
+
+impl Ledger {
+    /// Take a read-only snapshot of the current state of the ledger
+    pub fn snapshot(&self) -> LedgerSnapshot {
+        LedgerSnapshot(self.clone())
+    }
+}
+
+impl LedgerSnapshot {
+    pub fn utxos<'a>(&'a self) -> utxo::Iter<'a, Address> {
+        self.0.utxos()
+    }
+
+    pub fn stake_pools<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a StakePoolId, &'a StakePoolInfo)> + 'a {
+        self.0.stake_pools()
+    }
+
+    pub fn account_state(&self, id: &account::Identifier) -> Option<account::AccountState> {
+        self.0.account_state(id)
+    }
+
+    pub fn get_stake_distribution(&self) -> StakeDistribution {
+        self.0.get_stake_distribution()
+    }
+}
@@ -0,0 +1,408 @@
+#![cfg(test)]
+
+use crate::account;
+use crate::block::{BlockDate, ChainLength, HeaderContentEvalContext};
+use crate::certificate::{AccountLock, Certificate, CertificateContent};
+use crate::config::ConfigParam;
+use crate::fragment::Fragment;
+use crate::key::Hash;
+use crate::ledger::Error;
+use crate::multisig::{DeclElement, Declaration};
+use crate::testing::address::AddressData;
+use crate::testing::ledger::{self, ConfigBuilder};
+use crate::testing::tx_builder::TransactionBuilder;
+use crate::transaction::*;
+use crate::value::*;
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::PublicKey;
+
+fn account_lock(lock: AccountLock, signer: &AddressData) -> Fragment {
+    let mut certificate = Certificate {
+        content: CertificateContent::AccountLock(lock),
+        signatures: Vec::new(),
+    };
+    certificate.sign(&signer.private_key);
+    Fragment::Certificate(AuthenticatedTransaction {
+        transaction: Transaction {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            valid_until: None,
+            extra: certificate,
+        },
+        witnesses: Vec::new(),
+    })
+}
+
+#[test]
+pub fn locked_account_cannot_be_spent_until_target_epoch() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+
+    let lock = AccountLock {
+        account_id: AccountIdentifier::from_single_account(account_id.clone()),
+        until: 1,
+    };
+    let messages = [
+        ledger::create_initial_transaction(Output::from_address(
+            account.address.clone(),
+            Value(1000),
+        )),
+        account_lock(lock, &account),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(account.make_input(Value(1000), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(1000)))
+        .authenticate()
+        .with_witness(&block0_hash, &account)
+        .seal();
+    let fees = ledger.get_ledger_parameters();
+    let spend = [Fragment::Transaction(signed_tx)];
+
+    let date = |epoch, slot_id| HeaderContentEvalContext {
+        block_date: BlockDate { epoch, slot_id },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    match ledger.apply_block(&fees, &spend, &date(0, 1)) {
+        Err(Error::AccountLocked { account, until }) => {
+            assert_eq!(account, account_id);
+            assert_eq!(until, 1);
+        }
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("locked account was spent"),
+    }
+
+    // the spend is accepted in the very first block of the unlock epoch
+    assert!(ledger.apply_block(&fees, &spend, &date(1, 0)).is_ok());
+}
+
+#[test]
+pub fn account_lock_signed_by_another_key_is_rejected() {
+    let account = AddressData::account(Discrimination::Test);
+    let attacker = AddressData::account(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+
+    let messages = [ledger::create_initial_transaction(Output::from_address(
+        account.address.clone(),
+        Value(1000),
+    ))];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let lock = AccountLock {
+        account_id: AccountIdentifier::from_single_account(account_id.clone()),
+        until: u32::max_value(),
+    };
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    match ledger.apply_fragment(&fees, &account_lock(lock.clone(), &attacker), &metadata) {
+        Err(Error::AccountLockSigIsInvalid { account }) => assert_eq!(account, account_id),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("account was locked without its signature"),
+    }
+    assert!(ledger
+        .apply_fragment(&fees, &account_lock(lock, &account), &metadata)
+        .is_ok());
+}
+
+#[test]
+pub fn account_lock_cannot_be_shortened() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let lock = |until| AccountLock {
+        account_id: AccountIdentifier::from_single_account(account_id.clone()),
+        until,
+    };
+
+    let messages = [
+        ledger::create_initial_transaction(Output::from_address(
+            account.address.clone(),
+            Value(1000),
+        )),
+        account_lock(lock(2), &account),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let date = |epoch| HeaderContentEvalContext {
+        block_date: BlockDate { epoch, slot_id: 0 },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+
+    match ledger.apply_fragment(&fees, &account_lock(lock(0), &account), &date(1)) {
+        Err(Error::Account {
+            source: account::LedgerError::LockShortened { until, requested },
+        }) => assert_eq!((until, requested), (2, 0)),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("account lock was shortened"),
+    }
+    // extending the lock is allowed
+    assert!(ledger
+        .apply_fragment(&fees, &account_lock(lock(3), &account), &date(1))
+        .is_ok());
+
+    // the funds stay locked even with the shortening lock in the same block
+    let spend = Fragment::Transaction(
+        TransactionBuilder::new()
+            .with_input(account.make_input(Value(1000), None))
+            .with_output(Output::from_address(receiver.address.clone(), Value(1000)))
+            .authenticate()
+            .with_witness(&block0_hash, &account)
+            .seal(),
+    );
+    let block = [account_lock(lock(0), &account), spend];
+    assert!(ledger.apply_block(&fees, &block, &date(1)).is_err());
+    match ledger.apply_block(&fees, &block[1..], &date(1)) {
+        Err(Error::AccountLocked { until, .. }) => assert_eq!(until, 2),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("locked account was spent"),
+    }
+}
+
+#[test]
+pub fn spend_below_account_min_balance_is_rejected() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+
+    let messages = [ledger::create_initial_transaction(Output::from_address(
+        account.address.clone(),
+        Value(100),
+    ))];
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::AccountMinBalance(Value(10)));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&messages, config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let leaving_dust = TransactionBuilder::new()
+        .with_input(account.make_input(Value(99), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(99)))
+        .authenticate()
+        .with_witness(&block0_hash, &account)
+        .seal();
+    match ledger.clone().apply_transaction(&leaving_dust, &fees) {
+        Err(Error::AccountBelowMinBalance { remaining, min, .. }) => {
+            assert_eq!(remaining, Value(1));
+            assert_eq!(min, Value(10));
+        }
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("account was left below the minimum balance"),
+    }
+
+    let emptying = TransactionBuilder::new()
+        .with_input(account.make_input(Value(100), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &account)
+        .seal();
+    assert!(ledger.apply_transaction(&emptying, &fees).is_ok());
+}
+
+#[test]
+pub fn account_coin_age_grows_across_blocks_and_resets_on_spend() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let unknown_id = account::Identifier::from(receiver.public_key.clone());
+
+    let messages = [ledger::create_initial_transaction(Output::from_address(
+        account.address.clone(),
+        Value(1000),
+    ))];
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&messages, config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let date = |slot_id| BlockDate { epoch: 0, slot_id };
+
+    assert_eq!(ledger.account_coin_age(&account_id, date(5)), Some(5));
+    assert_eq!(ledger.account_coin_age(&unknown_id, date(5)), None);
+
+    let empty_block = HeaderContentEvalContext {
+        block_date: date(3),
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::empty(), &empty_block)
+        .unwrap();
+    assert_eq!(ledger.account_coin_age(&account_id, date(7)), Some(7));
+
+    let spend = Fragment::Transaction(
+        TransactionBuilder::new()
+            .with_input(account.make_input(Value(100), None))
+            .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+            .authenticate()
+            .with_witness(&block0_hash, &account)
+            .seal(),
+    );
+    let spending_block = HeaderContentEvalContext {
+        block_date: date(5),
+        chain_length: ChainLength(2),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::once(&spend), &spending_block)
+        .unwrap();
+    assert_eq!(ledger.account_coin_age(&account_id, date(5)), Some(0));
+    assert_eq!(ledger.account_coin_age(&account_id, date(9)), Some(4));
+}
+
+#[test]
+pub fn account_coin_age_resets_at_the_date_of_the_spending_block() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+
+    let messages = [ledger::create_initial_transaction(Output::from_address(
+        account.address.clone(),
+        Value(1000),
+    ))];
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&messages, config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let spend = Fragment::Transaction(
+        TransactionBuilder::new()
+            .with_input(account.make_input(Value(100), None))
+            .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+            .authenticate()
+            .with_witness(&block0_hash, &account)
+            .seal(),
+    );
+    let spending_block = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 2,
+            slot_id: 10,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::once(&spend), &spending_block)
+        .unwrap();
+    assert_eq!(
+        ledger.account_coin_age(&account_id, spending_block.block_date),
+        Some(0)
+    );
+    assert_eq!(
+        ledger.account_coin_age(
+            &account_id,
+            BlockDate {
+                epoch: 3,
+                slot_id: 0
+            }
+        ),
+        Some(90)
+    );
+}
+
+#[test]
+pub fn account_state_bundles_balance_counter_and_delegation() {
+    let account = AddressData::account(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![]);
+    let pool_id = stake_pool.to_id();
+    let mut pool_bytes = [0; 32];
+    pool_bytes.copy_from_slice(pool_id.as_ref());
+    let delegation_address = Address(
+        Discrimination::Test,
+        Kind::Delegation(account.public_key.clone(), pool_bytes),
+    );
+
+    let messages = [
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+        ledger::create_initial_transaction(Output::from_address(delegation_address, Value(1000))),
+    ];
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    assert_eq!(
+        ledger.account_state(&account_id),
+        Some(account::AccountState {
+            kind: account::AccountKind::Single,
+            value: Value(1000),
+            counter: account::SpendingCounter::zero(),
+            delegation: Some(pool_id),
+        })
+    );
+
+    let declaration = Declaration {
+        threshold: 1,
+        owners: vec![
+            DeclElement::Owner(Hash::hash_bytes(&[1])),
+            DeclElement::Owner(Hash::hash_bytes(&[2])),
+        ],
+    };
+    let multisig_id = declaration.to_identifier();
+    ledger.multisig = ledger
+        .multisig
+        .add_account(&declaration)
+        .unwrap()
+        .add_value(&multisig_id, Value(50))
+        .unwrap();
+    let multisig_account_id =
+        account::Identifier::from(PublicKey::from_binary(multisig_id.as_ref()).unwrap());
+    assert_eq!(
+        ledger.account_state(&multisig_account_id),
+        Some(account::AccountState {
+            kind: account::AccountKind::Multisig,
+            value: Value(50),
+            counter: account::SpendingCounter::zero(),
+            delegation: None,
+        })
+    );
+
+    let unknown = AddressData::account(Discrimination::Test);
+    assert_eq!(
+        ledger.account_state(&account::Identifier::from(unknown.public_key)),
+        None
+    );
+}
+
+#[test]
+pub fn multisig_accounts_are_listed_by_identifier() {
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let declarations: Vec<Declaration> = (0..3)
+        .map(|seed| Declaration {
+            threshold: 2,
+            owners: (0..3)
+                .map(|owner| DeclElement::Owner(Hash::hash_bytes(&[seed, owner])))
+                .collect(),
+        })
+        .collect();
+    for declaration in declarations.iter() {
+        ledger.multisig = ledger.multisig.add_account(declaration).unwrap();
+    }
+    // only one of the accounts holds value, the others are anchors
+    let funded = declarations[1].to_identifier();
+    ledger.multisig = ledger.multisig.add_value(&funded, Value(50)).unwrap();
+
+    let mut expected: Vec<_> = declarations
+        .iter()
+        .map(|declaration| (declaration.to_identifier(), declaration))
+        .collect();
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    let listed: Vec<_> = ledger.multisig_accounts().collect();
+    assert_eq!(listed, expected);
+
+    let declaration = ledger.multisig_declaration(&funded).unwrap();
+    assert_eq!((declaration.threshold(), declaration.total()), (2, 3));
+    let unknown = Declaration {
+        threshold: 1,
+        owners: vec![DeclElement::Owner(Hash::hash_bytes(&[9]))],
+    };
+    assert_eq!(ledger.multisig_declaration(&unknown.to_identifier()), None);
+}
@@ -0,0 +1,89 @@
+use crate::testing::address::AddressData;
+use crate::testing::ledger::{self, ConfigBuilder};
+use crate::testing::tx_builder::TransactionBuilder;
+use crate::{
+    block::{BlockDate, ChainLength, HeaderContentEvalContext},
+    config::ConfigParam,
+    fragment::Fragment,
+    ledger::{Ledger, LedgerParameters},
+    value::Value,
+};
+use chain_addr::Discrimination;
+use test::Bencher;
+
+const NB_TRANSACTIONS: usize = 500;
+
+fn block_of_transactions() -> (
+    Ledger,
+    LedgerParameters,
+    Vec<Fragment>,
+    HeaderContentEvalContext,
+) {
+    let faucets: Vec<_> = (0..NB_TRANSACTIONS)
+        .map(|_| AddressData::utxo(Discrimination::Test))
+        .collect();
+    let receiver = AddressData::utxo(Discrimination::Test);
+    // an initial transaction has less than 255 outputs
+    let messages: Vec<_> = faucets
+        .chunks(250)
+        .map(|faucets| {
+            ledger::create_initial_transactions(
+                &faucets
+                    .iter()
+                    .map(|faucet| faucet.make_output(Value(100)))
+                    .collect(),
+            )
+        })
+        .collect();
+    let mut config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    config.push(ConfigParam::MaxNumberOfTransactionsPerBlock(
+        NB_TRANSACTIONS as u32,
+    ));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&messages, config).unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let fragments = faucets
+        .iter()
+        .map(|faucet| {
+            let utxo = ledger
+                .utxos()
+                .find(|utxo| utxo.output.address == faucet.address);
+            let signed_tx = TransactionBuilder::new()
+                .with_input(faucet.make_input(Value(100), utxo))
+                .with_output(receiver.make_output(Value(100)))
+                .authenticate()
+                .with_witness(&block0_hash, faucet)
+                .seal();
+            Fragment::Transaction(signed_tx)
+        })
+        .collect();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    (ledger, params, fragments, metadata)
+}
+
+#[bench]
+fn apply_block_copying_ledger_per_fragment(b: &mut Bencher) {
+    let (ledger, params, fragments, metadata) = block_of_transactions();
+    b.iter(|| {
+        let mut new_ledger = ledger.clone();
+        for fragment in &fragments {
+            new_ledger = new_ledger
+                .apply_fragment(&params, fragment, &metadata)
+                .unwrap();
+        }
+        new_ledger
+    })
+}
+
+#[bench]
+fn apply_block_threading_ledger(b: &mut Bencher) {
+    let (ledger, params, fragments, metadata) = block_of_transactions();
+    b.iter(|| ledger.apply_block(&params, &fragments, &metadata).unwrap())
+}
@@ -0,0 +1,1039 @@
+#![cfg(test)]
+
+use crate::account;
+use crate::block::{BlockDate, HeaderContentEvalContext, HeaderHash};
+use crate::certificate::{
+    BulkDelegation, Certificate, CertificateContent, StakeDelegation, StakePoolOwnershipTransfer,
+    StakePoolRetirement,
+};
+use crate::config::ConfigParam;
+use crate::fragment::Fragment;
+use crate::leadership::genesis::GenesisPraosLeader;
+use crate::ledger::{Error, Ledger, UndoLog};
+use crate::stake::{DelegationError, StakePoolId, StakePoolInfo};
+use crate::testing::address::AddressData;
+use crate::testing::ledger::{self, ConfigBuilder};
+use crate::testing::tx_builder::TransactionBuilder;
+use crate::transaction::*;
+use crate::value::*;
+use chain_addr::{Address, Discrimination, Kind};
+use chain_core::mempack::read_from_raw;
+use chain_core::property::{ChainLength as _, Serialize as _};
+use chain_crypto::{Curve25519_2HashDH, SecretKey, SumEd25519_12};
+
+#[test]
+pub fn cached_stake_distribution_follows_delegation_change() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let account = AddressData::account(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![account_id.clone()]);
+    let pool_id = stake_pool.to_id();
+
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(faucet.address.clone(), Value(1000)),
+            Output::from_address(account.address.clone(), Value(500)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+
+    let delegation =
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(account_id),
+            pool_id: pool_id.clone(),
+        }));
+    let fees = ledger.get_ledger_parameters();
+    let (ledger, _) = match delegation {
+        Fragment::Certificate(cert) => ledger.apply_certificate(&cert, &fees).unwrap(),
+        _ => unreachable!(),
+    };
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(1000), ledger.utxos().next()))
+        .with_output(Output::from_address(account.address.clone(), Value(1000)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(
+        ledger.cached_stake_distribution().get_stake_for(&pool_id),
+        Some(Value(1500))
+    );
+}
+
+#[test]
+pub fn cached_stake_distribution_follows_pool_registration_and_retirement() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let account = AddressData::account(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![account_id.clone()]);
+    let pool_id = stake_pool.to_id();
+    let other_pool = ledger::create_stake_pool(vec![]);
+    let other_pool_id = other_pool.to_id();
+    // a group utxo whose stake is held by the account
+    let group_address = Address(
+        Discrimination::Test,
+        Kind::Group(faucet.public_key.clone(), account.public_key.clone()),
+    );
+    let delegation = |pool_id: &StakePoolId| {
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(account_id.clone()),
+            pool_id: pool_id.clone(),
+        }))
+    };
+    let retirement = |pool_id: &StakePoolId, pool_info: &StakePoolInfo| Certificate {
+        content: CertificateContent::StakePoolRetirement(StakePoolRetirement {
+            pool_id: pool_id.clone(),
+            pool_info: pool_info.clone(),
+        }),
+        signatures: Vec::new(),
+    };
+    let certificate = |fragment: Fragment| match fragment {
+        Fragment::Certificate(cert) => cert.transaction.extra,
+        _ => unreachable!(),
+    };
+
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(account.address.clone(), Value(500)),
+            Output::from_address(group_address, Value(300)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(
+            stake_pool.clone(),
+        )),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(
+            other_pool.clone(),
+        )),
+        delegation(&pool_id),
+    ];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(
+        ledger.cached_stake_distribution().stake_of(&pool_id),
+        Value(800)
+    );
+
+    // the stake of a retired pool is dangling, until the pool comes back
+    let ledger = ledger
+        .apply_certificate_content_dry(&retirement(&pool_id, &stake_pool))
+        .unwrap();
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(ledger.cached_stake_distribution().dangling, Value(800));
+    let registration = certificate(ledger::create_certificate(
+        CertificateContent::StakePoolRegistration(stake_pool.clone()),
+    ));
+    let ledger = ledger.apply_certificate_content_dry(&registration).unwrap();
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(
+        ledger.cached_stake_distribution().stake_of(&pool_id),
+        Value(800)
+    );
+
+    let ledger = ledger
+        .apply_certificate_content_dry(&certificate(delegation(&other_pool_id)))
+        .unwrap();
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(
+        ledger.cached_stake_distribution().stake_of(&other_pool_id),
+        Value(800)
+    );
+    assert_eq!(
+        ledger.cached_stake_distribution().stake_of(&pool_id),
+        Value(0)
+    );
+}
+
+#[test]
+pub fn stake_of_pool_sums_its_delegators() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![alice_id.clone()]);
+    let pool_id = stake_pool.to_id();
+    let unknown_pool_id = ledger::create_stake_pool(vec![]).to_id();
+
+    let delegate = |id: account::Identifier| {
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(id),
+            pool_id: pool_id.clone(),
+        }))
+    };
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(alice.address.clone(), Value(300)),
+            Output::from_address(bob.address.clone(), Value(200)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+        delegate(alice_id),
+        delegate(bob_id),
+    ];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let distribution = ledger.get_stake_distribution();
+    assert_eq!(distribution.stake_of(&pool_id), Value(500));
+    assert_eq!(distribution.stake_of(&unknown_pool_id), Value::zero());
+}
+
+#[test]
+pub fn stake_for_pool_agrees_with_the_distribution() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let group = AddressData::delegation(Discrimination::Test);
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let group_id = match group.address.kind() {
+        Kind::Group(_, account_key) => account::Identifier::from(account_key.clone()),
+        _ => unreachable!(),
+    };
+    let stake_pool = ledger::create_stake_pool(vec![alice_id.clone()]);
+    let other_pool = ledger::create_stake_pool(vec![bob_id.clone()]);
+    let unknown_pool_id = ledger::create_stake_pool(vec![]).to_id();
+
+    let delegate = |id: account::Identifier, pool: &StakePoolInfo| {
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(id),
+            pool_id: pool.to_id(),
+        }))
+    };
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(alice.address.clone(), Value(300)),
+            Output::from_address(bob.address.clone(), Value(200)),
+            Output::from_address(group.address.clone(), Value(50)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(
+            stake_pool.clone(),
+        )),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(
+            other_pool.clone(),
+        )),
+        delegate(alice_id, &stake_pool),
+        delegate(bob_id, &other_pool),
+        delegate(group_id, &stake_pool),
+    ];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let distribution = ledger.get_stake_distribution();
+    for pool_id in &[stake_pool.to_id(), other_pool.to_id(), unknown_pool_id] {
+        assert_eq!(
+            ledger.stake_for_pool(pool_id),
+            distribution.stake_of(pool_id)
+        );
+    }
+    assert_eq!(ledger.stake_for_pool(&stake_pool.to_id()), Value(350));
+    assert_eq!(distribution.total_stake(), Value(550));
+}
+
+#[test]
+pub fn delegations_list_every_delegated_account() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let carol = AddressData::account(Discrimination::Test);
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let first_pool = ledger::create_stake_pool(vec![alice_id.clone()]);
+    let second_pool = ledger::create_stake_pool(vec![bob_id.clone()]);
+    let first_pool_id = first_pool.to_id();
+    let second_pool_id = second_pool.to_id();
+
+    let delegate = |id: account::Identifier, pool_id: &StakePoolId| {
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(id),
+            pool_id: pool_id.clone(),
+        }))
+    };
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(alice.address.clone(), Value(300)),
+            Output::from_address(bob.address.clone(), Value(200)),
+            Output::from_address(carol.address.clone(), Value(100)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(first_pool)),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(second_pool)),
+        delegate(alice_id.clone(), &first_pool_id),
+        delegate(bob_id.clone(), &second_pool_id),
+    ];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let mut delegations: Vec<_> = ledger.delegations().collect();
+    delegations.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected = vec![(alice_id, first_pool_id), (bob_id, second_pool_id)];
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(delegations, expected);
+}
+
+#[test]
+pub fn registered_stake_pool_info_can_be_read_back() {
+    let owner = AddressData::account(Discrimination::Test);
+    let owner_id = account::Identifier::from(owner.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![owner_id.clone()]);
+    let pool_id = stake_pool.to_id();
+
+    let messages = [ledger::create_certificate(
+        CertificateContent::StakePoolRegistration(stake_pool.clone()),
+    )];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let info = ledger.stake_pool_info(&pool_id).unwrap();
+    assert_eq!(info.serial, stake_pool.serial);
+    assert_eq!(info.owners, vec![owner_id]);
+    assert_eq!(info.initial_key, stake_pool.initial_key);
+}
+
+#[test]
+pub fn stake_pools_are_listed_by_id() {
+    let owner = AddressData::account(Discrimination::Test);
+    let owner_id = account::Identifier::from(owner.public_key.clone());
+    let stake_pools: Vec<StakePoolInfo> = (0..3)
+        .map(|_| ledger::create_stake_pool(vec![owner_id.clone()]))
+        .collect();
+
+    let messages: Vec<Fragment> = stake_pools
+        .iter()
+        .map(|stake_pool| {
+            ledger::create_certificate(CertificateContent::StakePoolRegistration(
+                stake_pool.clone(),
+            ))
+        })
+        .collect();
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let mut expected: Vec<StakePoolId> = stake_pools.iter().map(|pool| pool.to_id()).collect();
+    expected.sort();
+    let listed: Vec<StakePoolId> = ledger.stake_pools().map(|(id, _)| id.clone()).collect();
+    assert_eq!(listed, expected);
+    assert!(ledger
+        .stake_pools()
+        .all(|(id, info)| info.to_id() == *id && info.owners == vec![owner_id.clone()]));
+}
+
+#[test]
+pub fn delegation_output_funds_and_delegates_account() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::account(Discrimination::Test);
+    let receiver_id = account::Identifier::from(receiver.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![]);
+    let pool_id = stake_pool.to_id();
+
+    let messages = [
+        ledger::create_initial_transaction(Output::from_address(
+            faucet.address.clone(),
+            Value(1000),
+        )),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let mut pool_bytes = [0u8; 32];
+    pool_bytes.copy_from_slice(pool_id.as_ref());
+    let delegation_address = Address(
+        Discrimination::Test,
+        Kind::Delegation(receiver.public_key.clone(), pool_bytes),
+    );
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(1000), ledger.utxos().next()))
+        .with_output(Output::from_address(delegation_address, Value(1000)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let fees = ledger.get_ledger_parameters();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+
+    let state = ledger.accounts().get_state(&receiver_id).unwrap();
+    assert_eq!(state.value(), Value(1000));
+    assert_eq!(state.delegation(), &Some(pool_id.clone()));
+    assert_eq!(
+        ledger.cached_stake_distribution(),
+        &ledger.get_stake_distribution()
+    );
+    assert_eq!(
+        ledger.get_stake_distribution().stake_of(&pool_id),
+        Value(1000)
+    );
+}
+
+#[test]
+pub fn delegation_output_leaves_existing_account_delegation_unchanged() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let victim = AddressData::account(Discrimination::Test);
+    let victim_id = account::Identifier::from(victim.public_key.clone());
+    let victim_pool = ledger::create_stake_pool(vec![victim_id.clone()]);
+    let victim_pool_id = victim_pool.to_id();
+    let attacker_pool = ledger::create_stake_pool(vec![]);
+    let attacker_pool_id = attacker_pool.to_id();
+
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(faucet.address.clone(), Value(1)),
+            Output::from_address(victim.address.clone(), Value(1000)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(victim_pool)),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(attacker_pool)),
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(victim_id.clone()),
+            pool_id: victim_pool_id.clone(),
+        })),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    let mut pool_bytes = [0u8; 32];
+    pool_bytes.copy_from_slice(attacker_pool_id.as_ref());
+    let delegation_address = Address(
+        Discrimination::Test,
+        Kind::Delegation(victim.public_key.clone(), pool_bytes),
+    );
+    let faucet_utxo = ledger
+        .utxos()
+        .find(|entry| entry.output.address == faucet.address);
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(1), faucet_utxo))
+        .with_output(Output::from_address(delegation_address, Value(1)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let fees = ledger.get_ledger_parameters();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+
+    let state = ledger.accounts().get_state(&victim_id).unwrap();
+    assert_eq!(state.value(), Value(1001));
+    assert_eq!(state.delegation(), &Some(victim_pool_id));
+    assert_eq!(
+        ledger.get_stake_distribution().stake_of(&attacker_pool_id),
+        Value::zero()
+    );
+}
+
+fn ownership_transfer(
+    pool_id: &StakePoolId,
+    new_owners: Vec<account::Identifier>,
+    signer: &AddressData,
+) -> AuthenticatedTransaction<Address, Certificate> {
+    let mut certificate = Certificate {
+        content: CertificateContent::StakePoolOwnershipTransfer(StakePoolOwnershipTransfer {
+            pool_id: pool_id.clone(),
+            new_owners,
+        }),
+        signatures: Vec::new(),
+    };
+    certificate.sign(&signer.private_key);
+    AuthenticatedTransaction {
+        transaction: Transaction {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            valid_until: None,
+            extra: certificate,
+        },
+        witnesses: Vec::new(),
+    }
+}
+
+fn bulk_delegation(
+    delegations: Vec<(account::Identifier, StakePoolId)>,
+    signers: &[&AddressData],
+) -> AuthenticatedTransaction<Address, Certificate> {
+    let mut certificate = Certificate {
+        content: CertificateContent::BulkDelegation(BulkDelegation { delegations }),
+        signatures: Vec::new(),
+    };
+    for signer in signers {
+        certificate.sign(&signer.private_key);
+    }
+    AuthenticatedTransaction {
+        transaction: Transaction {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            valid_until: None,
+            extra: certificate,
+        },
+        witnesses: Vec::new(),
+    }
+}
+
+#[test]
+pub fn bulk_delegation_is_applied_only_if_every_account_signed() {
+    let accounts: Vec<AddressData> =
+        std::iter::repeat_with(|| AddressData::account(Discrimination::Test))
+            .take(3)
+            .collect();
+    let account_ids: Vec<account::Identifier> = accounts
+        .iter()
+        .map(|account| account::Identifier::from(account.public_key.clone()))
+        .collect();
+    let stake_pool = ledger::create_stake_pool(vec![account_ids[0].clone()]);
+    let pool_id = stake_pool.to_id();
+
+    let messages = [
+        ledger::create_initial_transactions(
+            &accounts
+                .iter()
+                .map(|account| Output::from_address(account.address.clone(), Value(100)))
+                .collect(),
+        ),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+    ];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let delegations: Vec<(account::Identifier, StakePoolId)> = account_ids
+        .iter()
+        .map(|account_id| (account_id.clone(), pool_id.clone()))
+        .collect();
+
+    // the last account is signed for by a stranger
+    let stranger = AddressData::account(Discrimination::Test);
+    assert_eq!(
+        ledger
+            .clone()
+            .apply_certificate(
+                &bulk_delegation(
+                    delegations.clone(),
+                    &[&accounts[0], &accounts[1], &stranger]
+                ),
+                &fees
+            )
+            .map(|_| ()),
+        Err(Error::Delegation {
+            source: DelegationError::BulkDelegationSigIsInvalid(account_ids[2].clone())
+        })
+    );
+    assert!(account_ids
+        .iter()
+        .all(|id| ledger.accounts.get_state(id).unwrap().delegation() == &None));
+
+    let (ledger, _) = ledger
+        .apply_certificate(
+            &bulk_delegation(delegations, &[&accounts[0], &accounts[1], &accounts[2]]),
+            &fees,
+        )
+        .unwrap();
+    assert!(account_ids.iter().all(|id| {
+        ledger.accounts.get_state(id).unwrap().delegation() == &Some(pool_id.clone())
+    }));
+}
+
+#[test]
+pub fn account_certificates_list_delegation_and_pool_ownership() {
+    let account = AddressData::account(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let other = AddressData::account(Discrimination::Test);
+    let other_id = account::Identifier::from(other.public_key.clone());
+    let delegated_pool = ledger::create_stake_pool(vec![other_id.clone()]);
+
+    let messages = [
+        ledger::create_initial_transaction(Output::from_address(
+            account.address.clone(),
+            Value(100),
+        )),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(
+            delegated_pool.clone(),
+        )),
+    ];
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    ledger.enable_certificate_index();
+    let fees = ledger.get_ledger_parameters();
+
+    let contents = vec![
+        CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(account_id.clone()),
+            pool_id: delegated_pool.to_id(),
+        }),
+        CertificateContent::StakePoolRegistration(ledger::create_stake_pool(vec![
+            account_id.clone()
+        ])),
+    ];
+    for content in contents.iter() {
+        ledger = match ledger::create_certificate(content.clone()) {
+            Fragment::Certificate(cert) => ledger.apply_certificate(&cert, &fees).unwrap().0,
+            _ => unreachable!(),
+        };
+    }
+
+    let certificates: Vec<_> = ledger
+        .account_certificates(&account_id)
+        .into_iter()
+        .map(|certificate| certificate.content)
+        .collect();
+    assert_eq!(certificates, contents);
+    assert!(ledger.account_certificates(&other_id).is_empty());
+}
+
+#[test]
+pub fn account_certificates_list_block0_certificates() {
+    let owner = AddressData::account(Discrimination::Test);
+    let owner_id = account::Identifier::from(owner.public_key.clone());
+    let content =
+        CertificateContent::StakePoolRegistration(ledger::create_stake_pool(
+            vec![owner_id.clone()],
+        ));
+
+    let messages = [
+        Fragment::Initial(ConfigBuilder::new().build()),
+        ledger::create_certificate(content.clone()),
+    ];
+    let block0_hash = HeaderHash::hash_bytes(&[1, 2, 3]);
+
+    let ledger = Ledger::new(block0_hash, &messages).unwrap();
+    assert!(ledger.account_certificates(&owner_id).is_empty());
+
+    let ledger = Ledger::new_with_certificate_index(block0_hash, &messages).unwrap();
+    let certificates = ledger.account_certificates(&owner_id);
+    assert_eq!(certificates.len(), 1);
+    assert_eq!(certificates[0].content, content);
+    assert_eq!(certificates[0].date, BlockDate::first());
+    assert_eq!(certificates[0].chain_length, ledger.chain_length());
+}
+
+#[test]
+pub fn stake_pool_ownership_transfer_requires_the_current_owners() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![alice_id.clone()]);
+    let pool_id = stake_pool.to_id();
+
+    let messages = [ledger::create_certificate(
+        CertificateContent::StakePoolRegistration(stake_pool),
+    )];
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    assert_eq!(
+        ledger
+            .clone()
+            .apply_certificate(
+                &ownership_transfer(&pool_id, vec![bob_id.clone()], &bob),
+                &fees
+            )
+            .map(|_| ()),
+        Err(Error::Delegation {
+            source: DelegationError::StakePoolOwnershipTransferSigIsInvalid
+        })
+    );
+    let (ledger, _) = ledger
+        .apply_certificate(
+            &ownership_transfer(&pool_id, vec![bob_id.clone()], &alice),
+            &fees,
+        )
+        .unwrap();
+    assert_eq!(
+        ledger.stake_pool_info(&pool_id).unwrap().owners,
+        vec![bob_id]
+    );
+
+    assert_eq!(
+        ledger
+            .clone()
+            .apply_certificate(
+                &ownership_transfer(&pool_id, vec![alice_id.clone()], &alice),
+                &fees
+            )
+            .map(|_| ()),
+        Err(Error::Delegation {
+            source: DelegationError::StakePoolOwnershipTransferSigIsInvalid
+        })
+    );
+    let (ledger, _) = ledger
+        .apply_certificate(
+            &ownership_transfer(&pool_id, vec![alice_id.clone()], &bob),
+            &fees,
+        )
+        .unwrap();
+    assert_eq!(
+        ledger.stake_pool_info(&pool_id).unwrap().owners,
+        vec![alice_id]
+    );
+}
+
+#[test]
+pub fn slot_leader_eligibility_cache_agrees_and_clears_across_epochs() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account_id = account::Identifier::from(account.public_key.clone());
+    let vrf_key: SecretKey<Curve25519_2HashDH> =
+        SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let other_vrf_key: SecretKey<Curve25519_2HashDH> =
+        SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let kes_key: SecretKey<SumEd25519_12> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let stake_pool = StakePoolInfo {
+        serial: 0,
+        owners: vec![account_id.clone()],
+        initial_key: GenesisPraosLeader {
+            kes_public_key: kes_key.to_public(),
+            vrf_public_key: vrf_key.to_public(),
+        },
+    };
+    let pool_id = stake_pool.to_id();
+
+    let messages = [
+        ledger::create_initial_transaction(Output::from_address(
+            account.address.clone(),
+            Value(1000),
+        )),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(account_id),
+            pool_id: pool_id.clone(),
+        })),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let dates: Vec<BlockDate> = (0..50)
+        .map(|slot_id| BlockDate { epoch: 0, slot_id })
+        .collect();
+    let mut eligibility = Vec::new();
+    for date in dates.iter() {
+        let uncached = ledger
+            .is_slot_leader_uncached(&pool_id, &vrf_key, *date)
+            .unwrap();
+        assert_eq!(
+            ledger.is_slot_leader(&pool_id, &vrf_key, *date).unwrap(),
+            uncached
+        );
+        assert_eq!(
+            ledger.is_slot_leader(&pool_id, &vrf_key, *date).unwrap(),
+            uncached
+        );
+        eligibility.push(uncached);
+    }
+    assert_eq!(ledger.leader_eligibility_cache().len(), 50);
+
+    // the answers for another VRF key are not taken from the cache
+    for date in dates.iter() {
+        assert_eq!(
+            ledger
+                .is_slot_leader(&pool_id, &other_vrf_key, *date)
+                .unwrap(),
+            ledger
+                .is_slot_leader_uncached(&pool_id, &other_vrf_key, *date)
+                .unwrap()
+        );
+    }
+    assert_eq!(ledger.leader_eligibility_cache().len(), 100);
+
+    // moving most of the stake away during the epoch changes nothing
+    let signed_tx = TransactionBuilder::new()
+        .with_input(account.make_input(Value(900), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(900)))
+        .authenticate()
+        .with_witness(&block0_hash, &account)
+        .seal();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    assert_eq!(
+        ledger.get_stake_distribution().get_stake_for(&pool_id),
+        Some(Value(100))
+    );
+    for (date, eligible) in dates.iter().zip(eligibility.iter()) {
+        assert_eq!(
+            ledger
+                .is_slot_leader_uncached(&pool_id, &vrf_key, *date)
+                .unwrap(),
+            *eligible
+        );
+        assert_eq!(
+            ledger.is_slot_leader(&pool_id, &vrf_key, *date).unwrap(),
+            *eligible
+        );
+    }
+
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate::first().next_epoch(),
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::empty(), &metadata)
+        .unwrap();
+    assert!(ledger.leader_eligibility_cache().is_empty());
+    assert_eq!(ledger.leader_eligibility_cache().epoch(), 1);
+}
+
+/// A ledger in epoch 0 where most of the stake of the pool, whose VRF key
+/// is returned, moved to another pool after the epoch started, so the
+/// leaders of the epoch differ from the ones the current stake would elect
+fn ledger_with_stake_moved_mid_epoch() -> (Ledger, StakePoolId, SecretKey<Curve25519_2HashDH>) {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let vrf_key: SecretKey<Curve25519_2HashDH> =
+        SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let kes_key: SecretKey<SumEd25519_12> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let stake_pool = StakePoolInfo {
+        serial: 0,
+        owners: vec![alice_id.clone()],
+        initial_key: GenesisPraosLeader {
+            kes_public_key: kes_key.to_public(),
+            vrf_public_key: vrf_key.to_public(),
+        },
+    };
+    let pool_id = stake_pool.to_id();
+    let other_pool = ledger::create_stake_pool(vec![bob_id.clone()]);
+    let other_pool_id = other_pool.to_id();
+    let delegation = |account_id: &account::Identifier, pool_id: &StakePoolId| {
+        ledger::create_certificate(CertificateContent::StakeDelegation(StakeDelegation {
+            stake_key_id: AccountIdentifier::from_single_account(account_id.clone()),
+            pool_id: pool_id.clone(),
+        }))
+    };
+
+    let messages = [
+        ledger::create_initial_transactions(&vec![
+            Output::from_address(alice.address.clone(), Value(1000)),
+            Output::from_address(bob.address.clone(), Value(1000)),
+        ]),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(other_pool)),
+        delegation(&alice_id, &pool_id),
+        delegation(&bob_id, &other_pool_id),
+    ];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(alice.make_input(Value(900), None))
+        .with_output(Output::from_address(bob.address.clone(), Value(900)))
+        .authenticate()
+        .with_witness(&block0_hash, &alice)
+        .seal();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    (ledger, pool_id, vrf_key)
+}
+
+fn epoch0_eligibility(
+    ledger: &Ledger,
+    pool_id: &StakePoolId,
+    vrf_key: &SecretKey<Curve25519_2HashDH>,
+) -> Vec<bool> {
+    (0..100)
+        .map(|slot_id| {
+            ledger
+                .is_slot_leader_uncached(pool_id, vrf_key, BlockDate { epoch: 0, slot_id })
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+pub fn undoing_the_first_block_of_an_epoch_restores_leader_eligibility() {
+    let (ledger, pool_id, vrf_key) = ledger_with_stake_moved_mid_epoch();
+    let fees = ledger.get_ledger_parameters();
+    let eligibility = |ledger: &Ledger| epoch0_eligibility(ledger, &pool_id, &vrf_key);
+    let before = eligibility(&ledger);
+
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate::first().next_epoch(),
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    let (new_ledger, log) = ledger
+        .apply_block_with_undo(&fees, std::iter::empty(), &metadata)
+        .unwrap();
+    assert_eq!(new_ledger.leader_eligibility_cache().epoch(), 1);
+
+    let undone = new_ledger.undo(&log).unwrap();
+    assert_eq!(undone.leader_eligibility_cache().epoch(), 0);
+    assert_eq!(eligibility(&undone), before);
+
+    let bytes = log.serialize_as_vec().unwrap();
+    let decoded: UndoLog = read_from_raw(&bytes).unwrap();
+    assert_eq!(decoded, log);
+    assert_eq!(eligibility(&new_ledger.undo(&decoded).unwrap()), before);
+}
+
+#[test]
+pub fn restored_ledger_elects_leaders_from_the_epoch_start_stake() {
+    let (ledger, pool_id, vrf_key) = ledger_with_stake_moved_mid_epoch();
+    let before = epoch0_eligibility(&ledger, &pool_id, &vrf_key);
+
+    let restored: Result<Ledger, _> = ledger.iter().collect();
+    let restored = restored.unwrap();
+    assert_eq!(
+        restored.leader_eligibility_cache().snapshot(),
+        ledger.leader_eligibility_cache().snapshot()
+    );
+    assert_eq!(epoch0_eligibility(&restored, &pool_id, &vrf_key), before);
+    for (slot_id, eligible) in before.iter().enumerate() {
+        let date = BlockDate {
+            epoch: 0,
+            slot_id: slot_id as u32,
+        };
+        assert_eq!(
+            restored.is_slot_leader(&pool_id, &vrf_key, date).unwrap(),
+            *eligible
+        );
+    }
+}
+
+#[test]
+pub fn dry_applied_registration_registers_the_pool() {
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let owner = AddressData::account(Discrimination::Test);
+    let owner_id = account::Identifier::from(owner.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![owner_id]);
+    let pool_id = stake_pool.to_id();
+    let certificate = Certificate {
+        content: CertificateContent::StakePoolRegistration(stake_pool.clone()),
+        signatures: Vec::new(),
+    };
+
+    let registered = ledger.apply_certificate_content_dry(&certificate).unwrap();
+    assert_eq!(registered.stake_pool_info(&pool_id), Some(&stake_pool));
+    assert_eq!(ledger.stake_pool_info(&pool_id), None);
+}
+
+#[test]
+pub fn pool_retiring_mid_epoch_stays_in_the_epoch_snapshot() {
+    let owner = AddressData::account(Discrimination::Test);
+    let owner_id = account::Identifier::from(owner.public_key.clone());
+    let stake_pool = ledger::create_stake_pool(vec![owner_id]);
+    let pool_id = stake_pool.to_id();
+    let messages = [ledger::create_certificate(
+        CertificateContent::StakePoolRegistration(stake_pool.clone()),
+    )];
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::EpochStabilityDepth(1));
+    let (_, ledger) = ledger::create_initial_fake_ledger(&messages, config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    assert_eq!(ledger.active_pools_at(0), Some(vec![pool_id.clone()]));
+
+    let first_epoch = HeaderContentEvalContext {
+        block_date: BlockDate::first().next_epoch(),
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::empty(), &first_epoch)
+        .unwrap();
+    let restored: Result<Ledger, _> = ledger.iter().collect();
+    let restored = restored.unwrap();
+    assert_eq!(restored.stake_pool_info(&pool_id), Some(&stake_pool));
+    assert!(restored.stake_pools().eq(ledger.stake_pools()));
+
+    let retirement = Certificate {
+        content: CertificateContent::StakePoolRetirement(StakePoolRetirement {
+            pool_id: pool_id.clone(),
+            pool_info: stake_pool,
+        }),
+        signatures: Vec::new(),
+    };
+    let ledger = ledger.apply_certificate_content_dry(&retirement).unwrap();
+    assert_eq!(ledger.active_pools_at(1), Some(vec![pool_id.clone()]));
+
+    // the snapshots are kept when the ledger is serialized and restored
+    let restored: Result<Ledger, _> = ledger.iter().collect();
+    let restored = restored.unwrap();
+    assert_eq!(restored.active_pools_at(0), Some(vec![pool_id.clone()]));
+    assert_eq!(restored.active_pools_at(1), Some(vec![pool_id.clone()]));
+    assert!(restored.stake_pools().eq(ledger.stake_pools()));
+    assert_eq!(
+        restored.leader_eligibility_cache().snapshot(),
+        ledger.leader_eligibility_cache().snapshot()
+    );
+
+    let second_epoch = HeaderContentEvalContext {
+        block_date: first_epoch.block_date.next_epoch(),
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&fees, std::iter::empty(), &second_epoch)
+        .unwrap();
+    assert_eq!(ledger.active_pools_at(1), Some(vec![pool_id]));
+    assert_eq!(ledger.active_pools_at(2), Some(vec![]));
+    // the epoch 1 snapshot is stable, so the ones before it are pruned
+    assert_eq!(ledger.active_pools_at(0), None);
+}
+
+#[test]
+pub fn filtered_block_only_applies_the_kept_fragments() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let owner = AddressData::account(Discrimination::Test);
+    let stake_pool =
+        ledger::create_stake_pool(vec![account::Identifier::from(owner.public_key.clone())]);
+    let pool_id = stake_pool.to_id();
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let transfer = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let fragments = vec![
+        Fragment::Transaction(transfer),
+        ledger::create_certificate(CertificateContent::StakePoolRegistration(stake_pool)),
+    ];
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+
+    let filtered = ledger
+        .apply_block_filtered(&fees, &fragments, &metadata, |fragment| match fragment {
+            Fragment::Transaction(_) => false,
+            _ => true,
+        })
+        .unwrap();
+    assert!(filtered.stake_pool_info(&pool_id).is_some());
+    let utxos: Vec<_> = filtered.utxos().collect();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].output.address, faucet.address);
+    assert_eq!(filtered.chain_length(), metadata.chain_length);
+    assert_eq!(filtered.date(), metadata.block_date);
+
+    let full = ledger.apply_block(&fees, &fragments, &metadata).unwrap();
+    assert_eq!(
+        full.utxos().next().unwrap().output.address,
+        receiver.address
+    );
+}
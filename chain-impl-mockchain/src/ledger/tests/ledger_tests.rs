@@ -6,17 +6,40 @@ use crate::testing::ledger;
 use crate::testing::ledger::ConfigBuilder;
 use crate::testing::tx_builder::TransactionBuilder;
 use crate::{
+    account,
+    block::{BlockBuilder, BlockDate, ChainLength, HeaderContentEvalContext},
+    certificate::{Certificate, CertificateContent},
+    config::{ConfigParam, Tag},
+    fee::{LinearFee, PerCertificateFee},
+    fragment::{ConfigParams, Fragment, FragmentGroup},
+    key::Hash,
+    leadership::genesis::Nonce,
     ledger::{
-        Entry,
-        Error::{NotEnoughSignatures, TransactionHasTooManyOutputs},
-        Ledger,
+        Block0Error, Entry,
+        Error::{
+            AccountInvalidSignature, AccountSpendingCounterMismatch, Block0, BlockedAddress,
+            FragmentUndecodable, InitialMessageNotInBlock0, InputValueMismatch, InputsNotSorted,
+            InvalidBlockDate, NoOpTransaction, NotBalanced, NotEnoughSignatures,
+            OldUtxoDeclarationNotInBlock0, OldUtxoRedemptionClosed, OutputBelowMinimum,
+            OutputValueTooLarge, SlotGapTooLarge, TooManyTransactionsInBlock, TransactionExpired,
+            TransactionHasTooManyOutputs, UtxoError, WrongChainLength, ZeroOutput,
+        },
+        Ledger, UndoLog,
     },
+    legacy::UtxoDeclaration,
+    setting::{SettingChange, SettingsChange},
     transaction::*,
+    update::{RejectionReason, UpdateProposal, UpdateProposalState},
     value::*,
 };
-use chain_addr::Discrimination;
+use chain_addr::{Address, Discrimination, Kind};
+use chain_core::mempack::read_from_raw;
+use chain_core::property::Serialize;
+use chain_crypto::{Ed25519, Ed25519Bip32, SecretKey};
+use chain_time::era::{Epoch, EpochPosition, EpochSlotOffset};
 use quickcheck::TestResult;
 use quickcheck_macros::quickcheck;
+use std::collections::HashSet;
 
 macro_rules! assert_err {
     ($left: expr, $right: expr) => {
@@ -197,93 +220,1761 @@ pub fn transaction_with_more_than_253_outputs() {
 }
 
 #[test]
-pub fn iterate() {
+pub fn snapshot_keeps_its_view_while_the_ledger_advances() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let snapshot = ledger.snapshot();
+    assert_send_sync(&snapshot);
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    assert_eq!(
+        ledger.utxos().next().unwrap().output.address,
+        receiver.address
+    );
+
+    let shared = std::sync::Arc::new(snapshot);
+    let addresses = {
+        let shared = shared.clone();
+        std::thread::spawn(move || {
+            shared
+                .utxos()
+                .map(|utxo| utxo.output.address.clone())
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap()
+    };
+    assert_eq!(addresses, vec![faucet.address.clone()]);
+}
+
+#[test]
+pub fn block_with_too_many_transactions_is_rejected() {
     let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(faucet.address.clone(), Value(100)),
+        Output::from_address(faucet.address.clone(), Value(200)),
+    ]);
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::MaxNumberOfTransactionsPerBlock(1));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
 
+    let fragments: Vec<Fragment> = ledger
+        .utxos()
+        .map(|utxo| {
+            let value = utxo.output.value;
+            Fragment::Transaction(
+                TransactionBuilder::new()
+                    .with_input(faucet.make_input(value, Some(utxo)))
+                    .with_output(Output::from_address(receiver.address.clone(), value))
+                    .authenticate()
+                    .with_witness(&block0_hash, &faucet)
+                    .seal(),
+            )
+        })
+        .collect();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+
+    assert_err!(
+        TooManyTransactionsInBlock { max: 1, actual: 2 },
+        ledger.apply_block(&fees, &fragments, &metadata)
+    );
+    assert!(ledger
+        .apply_block(&fees, &fragments[..1], &metadata)
+        .is_ok());
+}
+
+#[test]
+pub fn transaction_can_spend_an_output_created_earlier_in_the_block() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let middle = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
     let message = ledger::create_initial_transaction(Output::from_address(
         faucet.address.clone(),
-        Value(42000),
+        Value(100),
     ));
-    let (_block0_hash, ledger) =
+    let (block0_hash, ledger) =
         ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
 
-    // FIXME: generate arbitrary ledger
+    let tx_a = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(middle.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let tx_b = TransactionBuilder::new()
+        .spend_from_pending(tx_a.transaction.hash(), 0, Value(100))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &middle)
+        .seal();
+    let fragments = vec![Fragment::Transaction(tx_a), Fragment::Transaction(tx_b)];
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
 
-    for item in ledger.iter() {
-        match item {
-            Entry::Globals(globals) => {
-                println!(
-                    "Globals date={} length={} block0_hash={} start_time={:?} discr={} kes_update_speed={}",
-                    globals.date,
-                    globals.chain_length,
-                    globals.static_params.block0_initial_hash,
-                    globals.static_params.block0_start_time,
-                    globals.static_params.discrimination,
-                    globals.static_params.kes_update_speed,
-                );
-            }
-            Entry::Utxo(entry) => {
-                println!(
-                    "Utxo {} {} {}",
-                    entry.transaction_id, entry.output_index, entry.output
-                );
-            }
-            Entry::OldUtxo(entry) => {
-                println!(
-                    "OldUtxo {} {} {}",
-                    entry.transaction_id, entry.output_index, entry.output
-                );
-            }
-            Entry::Account((id, state)) => {
-                println!(
-                    "Account {} {} {:?} {}",
-                    id,
-                    u32::from(state.counter),
-                    state.delegation,
-                    state.value,
-                );
-            }
-            Entry::ConfigParam(param) => {
-                println!(
-                    "ConfigParam {:?} {:?}",
-                    crate::config::Tag::from(&param),
-                    param,
-                );
-            }
-            Entry::UpdateProposal((id, state)) => {
-                println!(
-                    "UpdateProposal {} {:?} {} {:?}",
-                    id, state.proposal, state.proposal_date, state.votes
-                );
-            }
-            Entry::MultisigAccount((id, state)) => {
-                println!(
-                    "MultisigAccount {} {} {:?} {}",
-                    id,
-                    u32::from(state.counter),
-                    state.delegation,
-                    state.value,
-                );
-            }
-            Entry::MultisigDeclaration((id, decl)) => {
-                println!(
-                    "MultisigDeclaration {} {} {}",
-                    id,
-                    decl.threshold(),
-                    decl.total(),
-                );
-            }
-            Entry::StakePool((id, info)) => {
-                println!(
-                    "StakePool {} {} {:?} {:?}",
-                    id, info.serial, info.owners, info.initial_key,
-                );
-            }
-        }
+    let ledger = ledger.apply_block(&fees, &fragments, &metadata).unwrap();
+    let utxos: Vec<_> = ledger.utxos().collect();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].output.address, receiver.address);
+    assert_eq!(utxos[0].output.value, Value(100));
+}
+
+#[test]
+pub fn discrimination_is_the_one_of_block0() {
+    for discrimination in vec![Discrimination::Test, Discrimination::Production] {
+        let config = ConfigBuilder::new()
+            .with_discrimination(discrimination)
+            .build();
+        let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+        assert_eq!(ledger.discrimination(), discrimination);
     }
+}
 
-    let ledger2: Result<Ledger, _> = ledger.iter().collect();
-    let ledger2 = ledger2.unwrap();
+#[test]
+pub fn confirmations_increase_with_each_block() {
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let inclusion_length = ledger.chain_length();
+    assert_eq!(ledger.confirmations(inclusion_length), 0);
 
-    assert!(ledger == ledger2);
+    for length in 1..4 {
+        let metadata = HeaderContentEvalContext {
+            block_date: BlockDate {
+                epoch: 0,
+                slot_id: length,
+            },
+            chain_length: ChainLength(length),
+            nonce: None,
+        };
+        ledger = ledger
+            .apply_block(&params, std::iter::empty(), &metadata)
+            .unwrap();
+        assert_eq!(ledger.confirmations(inclusion_length), length as u64);
+        assert_eq!(ledger.confirmations(ledger.chain_length()), 0);
+    }
+}
+
+#[test]
+pub fn block_past_max_slot_gap_is_rejected() {
+    let mut config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    config.push(ConfigParam::MaxSlotGap(10));
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let within_gap = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 10,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    assert!(ledger
+        .apply_block(&params, std::iter::empty(), &within_gap)
+        .is_ok());
+
+    let past_gap = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 1,
+            slot_id: 0,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    assert_err!(
+        SlotGapTooLarge { gap: 100, max: 10 },
+        ledger.apply_block(&params, std::iter::empty(), &past_gap)
+    )
+}
+
+#[test]
+pub fn block_date_past_the_epoch_is_rejected() {
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let last_slot = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 99,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    assert!(ledger
+        .apply_block(&params, std::iter::empty(), &last_slot)
+        .is_ok());
+
+    let block_date = BlockDate {
+        epoch: 0,
+        slot_id: 100,
+    };
+    let past_epoch = HeaderContentEvalContext {
+        block_date,
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    assert_err!(
+        InvalidBlockDate { block_date },
+        ledger.apply_block(&params, std::iter::empty(), &past_epoch)
+    )
+}
+
+#[test]
+pub fn consensus_nonce_hex_follows_nonce_contributions() {
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+
+    let hex = ledger.consensus_nonce_hex();
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect();
+    assert_eq!(&bytes[..], ledger.settings.consensus_nonce.as_ref());
+
+    let params = ledger.get_ledger_parameters();
+    let with_nonce = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: Some(Nonce::zero()),
+    };
+    let next_ledger = ledger
+        .apply_block(&params, std::iter::empty(), &with_nonce)
+        .unwrap();
+    assert_ne!(next_ledger.consensus_nonce_hex(), hex);
+}
+
+#[test]
+pub fn fragment_group_with_invalid_transaction_is_not_applied() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+
+    let messages = [ledger::create_initial_transactions(&vec![
+        Output::from_address(alice.address.clone(), Value(100)),
+        Output::from_address(bob.address.clone(), Value(50)),
+    ])];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let valid_tx = TransactionBuilder::new()
+        .with_input(alice.make_input(Value(100), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &alice)
+        .seal();
+    // bob does not have enough funds
+    let invalid_tx = TransactionBuilder::new()
+        .with_input(bob.make_input(Value(60), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(60)))
+        .authenticate()
+        .with_witness(&block0_hash, &bob)
+        .seal();
+
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate::first(),
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let group = FragmentGroup::new(vec![valid_tx.clone(), invalid_tx]);
+    assert!(ledger
+        .apply_fragment_group(&params, &group, &metadata)
+        .is_err());
+
+    let valid_group = FragmentGroup::new(vec![valid_tx]);
+    let new_ledger = ledger
+        .apply_fragment_group(&params, &valid_group, &metadata)
+        .unwrap();
+    assert!(new_ledger != ledger);
+}
+
+#[test]
+pub fn account_input_above_the_balance_is_rejected() {
+    let account = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        account.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(account.make_input(Value(150), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(150)))
+        .authenticate()
+        .with_witness(&block0_hash, &account)
+        .seal();
+    assert_err!(
+        InputValueMismatch {
+            expected: Value(150),
+            value: Value(100)
+        },
+        ledger.apply_transaction(&signed_tx, &fees)
+    );
+}
+
+#[test]
+pub fn account_witness_signed_by_another_key_is_rejected() {
+    let alice = AddressData::account(Discrimination::Test);
+    let bob = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+
+    let messages = [ledger::create_initial_transactions(&vec![
+        Output::from_address(alice.address.clone(), Value(100)),
+        Output::from_address(bob.address.clone(), Value(100)),
+    ])];
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&messages, ConfigBuilder::new().build()).unwrap();
+
+    // spending from bob's account with a witness made with alice's key
+    let signed_tx = TransactionBuilder::new()
+        .with_input(bob.make_input(Value(100), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &alice)
+        .seal();
+
+    let bob_id = account::Identifier::from(bob.public_key.clone());
+    let fees = ledger.get_ledger_parameters();
+    match ledger.apply_transaction(&signed_tx, &fees) {
+        Err(AccountInvalidSignature { account, .. }) => assert_eq!(account, bob_id),
+        Err(error) => panic!("unexpected error: {:?}", error),
+        Ok(_) => panic!("transaction witnessed with the wrong key was accepted"),
+    }
+}
+
+#[test]
+pub fn account_witness_with_a_stale_spending_counter_is_diagnosed() {
+    let mut alice = AddressData::account(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+
+    let message =
+        ledger::create_initial_transaction(Output::from_address(alice.address.clone(), Value(100)));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+
+    let alice_id = account::Identifier::from(alice.public_key.clone());
+    assert_eq!(
+        ledger.account_spending_counter(&alice_id),
+        Some(account::SpendingCounter::zero())
+    );
+    assert_eq!(
+        ledger.account_spending_counter(&account::Identifier::from(
+            AddressData::account(Discrimination::Test).public_key
+        )),
+        None
+    );
+
+    alice.spending_counter = Some(account::SpendingCounter::from(1));
+    let signed_tx = TransactionBuilder::new()
+        .with_input(alice.make_input(Value(100), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &alice)
+        .seal();
+    let fees = ledger.get_ledger_parameters();
+    match ledger.clone().apply_transaction(&signed_tx, &fees) {
+        Err(AccountInvalidSignature { account, .. }) => assert_eq!(account, alice_id),
+        Err(error) => panic!("unexpected error: {:?}", error),
+        Ok(_) => panic!("transaction signed with a stale counter was accepted"),
+    }
+
+    let transaction_id = signed_tx.transaction.hash();
+    assert_err!(
+        AccountSpendingCounterMismatch {
+            expected: account::SpendingCounter::zero(),
+            actual: account::SpendingCounter::from(1),
+        },
+        ledger.diagnose_account_witness(&alice_id, &transaction_id, &signed_tx.witnesses[0])
+    );
+}
+
+#[test]
+pub fn block0_only_fragments_are_rejected_in_normal_block() {
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate::first(),
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+
+    assert_err!(
+        InitialMessageNotInBlock0,
+        ledger.apply_fragment(&params, &Fragment::Initial(ConfigParams::new()), &metadata)
+    );
+    assert_err!(
+        OldUtxoDeclarationNotInBlock0,
+        ledger.apply_fragment(
+            &params,
+            &Fragment::OldUtxoDeclaration(UtxoDeclaration { addrs: vec![] }),
+            &metadata
+        )
+    );
+}
+
+#[test]
+pub fn fragment_bytes_are_read_and_validated() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate::first(),
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let make_fragment = |value| {
+        Fragment::Transaction(
+            TransactionBuilder::new()
+                .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+                .with_output(Output::from_address(receiver.address.clone(), Value(value)))
+                .authenticate()
+                .with_witness(&block0_hash, &faucet)
+                .seal(),
+        )
+    };
+
+    let fragment = make_fragment(100);
+    let bytes = fragment.to_raw();
+    let read = Fragment::read_and_validate(bytes.as_ref(), &ledger, &params, &metadata).unwrap();
+    assert_eq!(read.to_raw(), bytes);
+
+    assert_err!(
+        FragmentUndecodable,
+        Fragment::read_and_validate(
+            &bytes.as_ref()[..bytes.as_ref().len() - 1],
+            &ledger,
+            &params,
+            &metadata
+        )
+    );
+
+    let unbalanced = make_fragment(50).to_raw();
+    assert_err!(
+        NotBalanced {
+            inputs: Value(100),
+            outputs: Value(50)
+        },
+        Fragment::read_and_validate(unbalanced.as_ref(), &ledger, &params, &metadata)
+    );
+
+    // fragment tag, no input, no output and no validity limit, followed by
+    // the certificate tag
+    let certificate = ledger::create_certificate(CertificateContent::StakePoolRegistration(
+        ledger::create_stake_pool(Vec::new()),
+    ));
+    let mut unknown_certificate = certificate.to_raw().as_ref().to_vec();
+    assert_eq!(&unknown_certificate[1..4], &[0, 0, 0]);
+    unknown_certificate[4] = 0xff;
+    assert_err!(
+        FragmentUndecodable,
+        Fragment::read_and_validate(&unknown_certificate, &ledger, &params, &metadata)
+    );
+}
+
+#[test]
+pub fn unsorted_inputs_are_rejected_when_required() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+
+    let messages = [ledger::create_initial_transactions(&vec![
+        Output::from_address(faucet.address.clone(), Value(100)),
+        Output::from_address(faucet.address.clone(), Value(200)),
+    ])];
+    let make_ledger = |require_sorted_inputs| {
+        let mut config = ConfigBuilder::new().build();
+        config.push(ConfigParam::RequireSortedInputs(require_sorted_inputs));
+        ledger::create_initial_fake_ledger(&messages, config).unwrap()
+    };
+
+    let (block0_hash, strict_ledger) = make_ledger(true);
+    let (_, lenient_ledger) = make_ledger(false);
+    let mut inputs: Vec<Input> = strict_ledger.utxos().map(Input::from_utxo_entry).collect();
+    inputs.sort();
+    inputs.reverse();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_inputs(inputs)
+        .with_output(Output::from_address(receiver.address.clone(), Value(300)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+
+    let params = strict_ledger.get_ledger_parameters();
+    assert_err!(
+        InputsNotSorted,
+        strict_ledger.apply_transaction(&signed_tx, &params)
+    );
+    assert!(lenient_ledger
+        .apply_transaction(&signed_tx, &params)
+        .is_ok());
+}
+
+#[test]
+pub fn block0_bytes_are_validated() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let mut builder = BlockBuilder::new();
+    builder.messages(vec![
+        Fragment::Initial(ConfigBuilder::new().build()),
+        ledger::create_initial_transaction(Output::from_address(
+            faucet.address.clone(),
+            Value(100),
+        )),
+    ]);
+    let bytes = builder.make_genesis_block().serialize_as_vec().unwrap();
+
+    let static_params = Ledger::validate_block0_bytes(&bytes).unwrap();
+    assert_eq!(static_params.discrimination, Discrimination::Test);
+
+    assert!(Ledger::validate_block0_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+pub fn total_values_by_kind_sum_to_ledger_total() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let account = AddressData::account(Discrimination::Test);
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(faucet.address.clone(), Value(1000)),
+        Output::from_address(account.address.clone(), Value(500)),
+    ]);
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+
+    assert_eq!(ledger.utxo_total_value().unwrap(), Value(1000));
+    assert_eq!(ledger.account_total_value().unwrap(), Value(500));
+    assert_eq!(ledger.multisig_total_value().unwrap(), Value::zero());
+    assert_eq!(ledger.old_utxo_total_value().unwrap(), Value::zero());
+    let sum = Value::sum(
+        vec![
+            ledger.utxo_total_value().unwrap(),
+            ledger.account_total_value().unwrap(),
+            ledger.multisig_total_value().unwrap(),
+            ledger.old_utxo_total_value().unwrap(),
+        ]
+        .into_iter(),
+    )
+    .unwrap();
+    assert_eq!(sum, ledger.total_value().unwrap());
+}
+
+#[test]
+pub fn enacted_fee_proposal_is_reported_as_settings_change() {
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let old_fee = ledger.settings.linear_fees();
+    let new_fee = LinearFee::new(1, 2, 3);
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::LinearFee(new_fee));
+    ledger.updates.proposals.insert(
+        Hash::hash_bytes(&[1]),
+        UpdateProposalState {
+            proposal: UpdateProposal { changes },
+            proposal_date: ledger.date(),
+            votes: ledger.settings.bft_leaders.iter().cloned().collect(),
+        },
+    );
+
+    let same_epoch = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: Some(Nonce::from([1; 32])),
+    };
+    let (ledger, change) = ledger
+        .apply_block_tracking_settings(&params, std::iter::empty(), &same_epoch)
+        .unwrap();
+    // the nonce changes with the block but is not a setting
+    assert_eq!(change, None);
+
+    let next_epoch = HeaderContentEvalContext {
+        block_date: same_epoch.block_date.next_epoch(),
+        chain_length: ChainLength(2),
+        nonce: Some(Nonce::from([2; 32])),
+    };
+    let (_, change) = ledger
+        .apply_block_tracking_settings(&params, std::iter::empty(), &next_epoch)
+        .unwrap();
+    assert_eq!(
+        change,
+        Some(SettingsChange {
+            changes: vec![SettingChange {
+                tag: Tag::LinearFee,
+                old: vec![ConfigParam::LinearFee(old_fee)],
+                new: vec![ConfigParam::LinearFee(new_fee)],
+            }],
+        })
+    );
+}
+
+#[test]
+pub fn config_hash_ignores_the_consensus_nonce() {
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: Some(Nonce::from([1; 32])),
+    };
+    let next = ledger
+        .apply_block(&params, std::iter::empty(), &metadata)
+        .unwrap();
+
+    assert_ne!(ledger.consensus_nonce_hex(), next.consensus_nonce_hex());
+    assert_eq!(ledger.settings.config_hash(), next.settings.config_hash());
+}
+
+#[test]
+pub fn expiring_proposal_is_reported_as_processed() {
+    let (_, mut ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let params = ledger.get_ledger_parameters();
+    ledger.settings.proposal_expiration = 1;
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::LinearFee(LinearFee::new(1, 2, 3)));
+    let proposal_id = Hash::hash_bytes(&[1]);
+    ledger.updates.proposals.insert(
+        proposal_id.clone(),
+        UpdateProposalState {
+            proposal: UpdateProposal { changes },
+            proposal_date: ledger.date(),
+            votes: HashSet::new(),
+        },
+    );
+
+    let last_epoch = HeaderContentEvalContext {
+        block_date: ledger.date().next_epoch(),
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let (ledger, processed) = ledger
+        .apply_block_tracking_proposals(&params, std::iter::empty(), &last_epoch)
+        .unwrap();
+    assert!(processed.is_empty());
+
+    let expired = HeaderContentEvalContext {
+        block_date: last_epoch.block_date.next_epoch(),
+        chain_length: ChainLength(2),
+        nonce: None,
+    };
+    let (ledger, processed) = ledger
+        .apply_block_tracking_proposals(&params, std::iter::empty(), &expired)
+        .unwrap();
+    assert_eq!(processed, vec![proposal_id]);
+    assert!(ledger.updates.proposals.is_empty());
+}
+
+#[test]
+pub fn enacted_slots_per_epoch_proposal_extends_the_era() {
+    let (_, mut ledger) = ledger::create_initial_fake_ledger(
+        &[],
+        ConfigBuilder::new().with_slots_per_epoch(10).build(),
+    )
+    .unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::SlotsPerEpoch(20));
+    ledger.updates.proposals.insert(
+        Hash::hash_bytes(&[1]),
+        UpdateProposalState {
+            proposal: UpdateProposal { changes },
+            proposal_date: ledger.date(),
+            votes: ledger.settings.bft_leaders.iter().cloned().collect(),
+        },
+    );
+
+    let same_epoch = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 5,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&params, std::iter::empty(), &same_epoch)
+        .unwrap();
+    assert_eq!(ledger.era().slots_per_epoch(), 10);
+
+    // the slot is only valid with the new number of slots per epoch
+    let next_epoch = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 1,
+            slot_id: 15,
+        },
+        chain_length: ChainLength(2),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&params, std::iter::empty(), &next_epoch)
+        .unwrap();
+    let era = ledger.era();
+    assert_eq!(era.slots_per_epoch_at(Epoch(0)), 10);
+    assert_eq!(era.slots_per_epoch_at(Epoch(1)), 20);
+
+    let date = |epoch, slot_id| BlockDate { epoch, slot_id };
+    assert_eq!(date(0, 9).next(era), date(1, 0));
+    assert_eq!(date(1, 10).next(era), date(1, 11));
+    assert_eq!(date(1, 19).next(era), date(2, 0));
+
+    let last_of_epoch0 = era.from_era_to_slot(EpochPosition {
+        epoch: Epoch(0),
+        slot: EpochSlotOffset(9),
+    });
+    let first_of_epoch1 = era.from_era_to_slot(EpochPosition {
+        epoch: Epoch(1),
+        slot: EpochSlotOffset(0),
+    });
+    assert_eq!(u64::from(last_of_epoch0) + 1, u64::from(first_of_epoch1));
+
+    assert_eq!(ledger.min_block_date_for_length(ChainLength(9)), date(0, 9));
+    assert_eq!(
+        ledger.min_block_date_for_length(ChainLength(10)),
+        date(1, 0)
+    );
+    assert_eq!(
+        ledger.min_block_date_for_length(ChainLength(35)),
+        date(2, 5)
+    );
+}
+
+#[test]
+pub fn block0_certificate_with_output_is_rejected() {
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let certificate = match ledger::create_certificate(CertificateContent::StakePoolRegistration(
+        ledger::create_stake_pool(vec![]),
+    )) {
+        Fragment::Certificate(mut cert) => {
+            cert.transaction
+                .outputs
+                .push(Output::from_address(receiver.address.clone(), Value(1)));
+            Fragment::Certificate(cert)
+        }
+        _ => unreachable!(),
+    };
+
+    assert_err!(
+        Block0 {
+            source: Block0Error::CertificateHasOutput
+        },
+        ledger::create_initial_fake_ledger(&[certificate], ConfigBuilder::new().build())
+    );
+}
+
+#[test]
+pub fn block0_duplicate_unique_param_is_rejected() {
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::LinearFee(LinearFee::new(1, 1, 1)));
+    config.push(ConfigParam::LinearFee(LinearFee::new(2, 2, 2)));
+    assert_err!(
+        Block0 {
+            source: Block0Error::InitialMessageDuplicateParam {
+                tag: Tag::LinearFee
+            }
+        },
+        ledger::create_initial_fake_ledger(&[], config)
+    );
+
+    // BFT leaders are declared with one parameter each
+    let mut config = ConfigBuilder::new().build();
+    let leader_key: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+    config.push(ConfigParam::AddBftLeader(leader_key.to_public().into()));
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+    assert_eq!(ledger.settings.bft_leaders.len(), 2);
+}
+
+#[test]
+pub fn bft_leaders_of_block0_are_listed() {
+    let mut config = ConfigBuilder::new().build();
+    for _ in 0..2 {
+        let leader_key: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+        config.push(ConfigParam::AddBftLeader(leader_key.to_public().into()));
+    }
+    let expected: Vec<_> = config
+        .iter()
+        .filter_map(|param| match param {
+            ConfigParam::AddBftLeader(leader) => Some(leader.clone()),
+            _ => None,
+        })
+        .collect();
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+
+    assert_eq!(expected.len(), 3);
+    assert_eq!(ledger.bft_leaders().cloned().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+pub fn wallet_balance_sums_utxos_and_accounts_of_its_keys() {
+    let account = AddressData::account(Discrimination::Test);
+    let single = AddressData::utxo(Discrimination::Test);
+    let group = AddressData::delegation(Discrimination::Test);
+    let stranger = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(account.address.clone(), Value(500)),
+        Output::from_address(single.address.clone(), Value(100)),
+        Output::from_address(group.address.clone(), Value(200)),
+        Output::from_address(stranger.address.clone(), Value(1000)),
+    ]);
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+
+    let keys = vec![
+        account.public_key.clone(),
+        single.public_key.clone(),
+        group.public_key.clone(),
+    ];
+    assert_eq!(ledger.wallet_balance(&keys), Value(800));
+    assert_eq!(ledger.wallet_balance(&[]), Value::zero());
+}
+
+#[test]
+pub fn unsigned_transaction_signed_offline_is_applied() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![faucet.make_input(Value(100), ledger.utxos().next())],
+        outputs: vec![Output::from_address(receiver.address.clone(), Value(100))],
+        valid_until: None,
+        extra: NoExtra,
+    };
+    let mut bytes = Vec::new();
+    transaction.serialize_unsigned(&mut bytes).unwrap();
+
+    // on the offline signer
+    let unsigned: UnsignedTransaction<Address, NoExtra> = read_from_raw(&bytes).unwrap();
+    assert_eq!(unsigned.transaction, transaction);
+    let witness = Witness::new_utxo(
+        &block0_hash,
+        &unsigned.transaction.hash(),
+        &faucet.private_key,
+    );
+
+    let signed_tx = unsigned.attach_witnesses(vec![witness]);
+    let fees = ledger.get_ledger_parameters();
+    let (ledger, _) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    assert_eq!(ledger.wallet_balance(&[receiver.public_key]), Value(100));
+}
+
+#[test]
+pub fn output_above_max_output_value_is_rejected() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(faucet.address.clone(), Value(60)),
+        Output::from_address(faucet.address.clone(), Value(60)),
+    ]);
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::MaxOutputValue(Value(60)));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let inputs: Vec<_> = ledger
+        .utxos()
+        .map(|utxo| faucet.make_input(Value(60), Some(utxo)))
+        .collect();
+
+    let too_large = TransactionBuilder::new()
+        .with_inputs(inputs.clone())
+        .with_output(Output::from_address(receiver.address.clone(), Value(120)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    match ledger.clone().apply_transaction(&too_large, &fees) {
+        Err(OutputValueTooLarge { max, .. }) => assert_eq!(max, Value(60)),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("output above the maximum value was accepted"),
+    }
+
+    let at_max = TransactionBuilder::new()
+        .with_inputs(inputs)
+        .with_outputs(vec![
+            Output::from_address(receiver.address.clone(), Value(60)),
+            Output::from_address(receiver.address.clone(), Value(60)),
+        ])
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert!(ledger.apply_transaction(&at_max, &fees).is_ok());
+}
+
+#[test]
+pub fn utxo_output_below_min_utxo_value_is_rejected() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account = AddressData::account(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::MinUtxoValue(Value(10)));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let input = faucet.make_input(Value(100), ledger.utxos().next());
+
+    let dust = Output::from_address(receiver.address.clone(), Value(5));
+    let with_dust = TransactionBuilder::new()
+        .with_input(input.clone())
+        .with_outputs(vec![
+            Output::from_address(receiver.address.clone(), Value(95)),
+            dust.clone(),
+        ])
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert_err!(
+        OutputBelowMinimum {
+            output: dust,
+            minimum: Value(10)
+        },
+        ledger.clone().apply_transaction(&with_dust, &fees)
+    );
+
+    // accounts aggregate their credits, so they can receive less
+    let to_account = TransactionBuilder::new()
+        .with_input(input)
+        .with_outputs(vec![
+            Output::from_address(receiver.address.clone(), Value(95)),
+            Output::from_address(account.address.clone(), Value(5)),
+        ])
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert!(ledger.apply_transaction(&to_account, &fees).is_ok());
+}
+
+#[test]
+pub fn transactions_with_blocked_addresses_are_rejected() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let blocked = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(faucet.address.clone(), Value(100)),
+        Output::from_address(blocked.address.clone(), Value(100)),
+    ]);
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::AddBlockedAddress(Hash::hash_bytes(
+        &blocked.address.to_bytes(),
+    )));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let utxo_of = |address: &Address| {
+        ledger
+            .utxos()
+            .find(|utxo| &utxo.output.address == address)
+            .unwrap()
+    };
+
+    let to_blocked = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), Some(utxo_of(&faucet.address))))
+        .with_output(Output::from_address(blocked.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert_err!(
+        BlockedAddress {
+            address: blocked.address.clone()
+        },
+        ledger.clone().apply_transaction(&to_blocked, &fees)
+    );
+
+    let from_blocked = TransactionBuilder::new()
+        .with_input(blocked.make_input(Value(100), Some(utxo_of(&blocked.address))))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &blocked)
+        .seal();
+    assert_err!(
+        BlockedAddress {
+            address: blocked.address.clone()
+        },
+        ledger.clone().apply_transaction(&from_blocked, &fees)
+    );
+
+    let allowed = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), Some(utxo_of(&faucet.address))))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert!(ledger.clone().apply_transaction(&allowed, &fees).is_ok());
+}
+
+#[test]
+pub fn zero_account_credit_follows_the_configured_policy() {
+    for reject_zero_account_credit in vec![true, false] {
+        let faucet = AddressData::utxo(Discrimination::Test);
+        let receiver = AddressData::utxo(Discrimination::Test);
+        let account = AddressData::account(Discrimination::Test);
+        let message = ledger::create_initial_transaction(Output::from_address(
+            faucet.address.clone(),
+            Value(100),
+        ));
+        let mut config = ConfigBuilder::new().build();
+        config.push(ConfigParam::RejectZeroAccountCredit(
+            reject_zero_account_credit,
+        ));
+        let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+        let fees = ledger.get_ledger_parameters();
+
+        let signed_tx = TransactionBuilder::new()
+            .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+            .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+            .with_output(Output::from_address(account.address.clone(), Value::zero()))
+            .authenticate()
+            .with_witness(&block0_hash, &faucet)
+            .seal();
+        let result = ledger.apply_transaction(&signed_tx, &fees);
+        if reject_zero_account_credit {
+            match result {
+                Err(ZeroOutput { output }) => assert_eq!(output.address, account.address),
+                Err(error) => panic!("unexpected error: {}", error),
+                Ok(_) => panic!("zero-valued account credit was accepted"),
+            }
+        } else {
+            let (ledger, _) = result.unwrap();
+            assert_eq!(ledger.wallet_balance(&[receiver.public_key]), Value(100));
+            assert!(!ledger.accounts().exists(&account.public_key.clone().into()));
+        }
+    }
+}
+
+#[test]
+pub fn self_transfer_follows_the_configured_policy() {
+    for reject_self_transfers in vec![true, false] {
+        let faucet = AddressData::utxo(Discrimination::Test);
+        let message = ledger::create_initial_transaction(Output::from_address(
+            faucet.address.clone(),
+            Value(100),
+        ));
+        let mut config = ConfigBuilder::new().build();
+        config.push(ConfigParam::RejectSelfTransfers(reject_self_transfers));
+        let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+        let fees = ledger.get_ledger_parameters();
+
+        let signed_tx = TransactionBuilder::new()
+            .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+            .with_output(Output::from_address(faucet.address.clone(), Value(100)))
+            .authenticate()
+            .with_witness(&block0_hash, &faucet)
+            .seal();
+        let metadata = HeaderContentEvalContext {
+            block_date: BlockDate {
+                epoch: 0,
+                slot_id: 1,
+            },
+            chain_length: ChainLength(1),
+            nonce: None,
+        };
+        let validated = ledger.validate_transaction(&signed_tx, &fees);
+        let applied = ledger.clone().apply_transaction(&signed_tx, &fees);
+        let result = ledger.apply_fragment(&fees, &Fragment::Transaction(signed_tx), &metadata);
+        if reject_self_transfers {
+            assert_err!(NoOpTransaction, validated);
+            assert_err!(NoOpTransaction, applied);
+            assert_err!(NoOpTransaction, result);
+        } else {
+            assert_eq!(validated, Ok(Value::zero()));
+            assert!(applied.is_ok());
+            let ledger = result.unwrap();
+            assert_eq!(ledger.wallet_balance(&[faucet.public_key]), Value(100));
+        }
+    }
+}
+
+#[test]
+pub fn validation_reports_fee_without_applying() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::LinearFee(LinearFee::new(1, 1, 0)));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(97)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert_eq!(ledger.validate_transaction(&signed_tx, &fees), Ok(Value(3)));
+    assert_eq!(ledger.utxos().count(), 1);
+
+    // the input is spent once the transaction is applied
+    let (spent, _) = ledger.clone().apply_transaction(&signed_tx, &fees).unwrap();
+    match spent.validate_transaction(&signed_tx, &fees) {
+        Err(UtxoError { .. }) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("transaction spending a spent utxo was valid"),
+    }
+    assert_eq!(ledger.validate_transaction(&signed_tx, &fees), Ok(Value(3)));
+}
+
+#[test]
+pub fn validation_catches_double_spend_within_a_transaction() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let input = faucet.make_input(Value(100), ledger.utxos().next());
+    let signed_tx = TransactionBuilder::new()
+        .with_input(input.clone())
+        .with_input(input)
+        .with_output(Output::from_address(receiver.address.clone(), Value(200)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    match ledger.validate_transaction(&signed_tx, &fees) {
+        Err(UtxoError { .. }) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("transaction spending the same utxo twice was valid"),
+    }
+    assert_eq!(ledger.utxos().count(), 1);
+}
+
+#[test]
+pub fn utxo_creation_height_is_the_chain_length_it_was_applied_at() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let params = ledger.get_ledger_parameters();
+    let initial = ledger.utxos().next().unwrap();
+    assert_eq!(
+        ledger
+            .utxos
+            .creation_height(&initial.transaction_id, &initial.output_index),
+        Some(ChainLength(0))
+    );
+
+    let empty_block = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&params, std::iter::empty(), &empty_block)
+        .unwrap();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let transaction_id = signed_tx.transaction.hash();
+    let fragment = Fragment::Transaction(signed_tx);
+    let block = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 2,
+        },
+        chain_length: ChainLength(2),
+        nonce: None,
+    };
+    let ledger = ledger
+        .apply_block(&params, std::iter::once(&fragment), &block)
+        .unwrap();
+    assert_eq!(
+        ledger.utxos.creation_height(&transaction_id, &0),
+        Some(ChainLength(2))
+    );
+    assert_eq!(ledger.utxos.creation_height(&transaction_id, &1), None);
+}
+
+#[test]
+pub fn spendable_inputs_combine_utxos_and_account() {
+    let group = AddressData::delegation(Discrimination::Test);
+    let stranger = AddressData::utxo(Discrimination::Test);
+    let account_key = match group.address.kind() {
+        Kind::Group(_, account_key) => account_key.clone(),
+        _ => unreachable!(),
+    };
+    let account_address = Address(Discrimination::Test, Kind::Account(account_key));
+    let message = ledger::create_initial_transactions(&vec![
+        Output::from_address(group.address.clone(), Value(100)),
+        Output::from_address(group.address.clone(), Value(200)),
+        Output::from_address(account_address, Value(300)),
+        Output::from_address(stranger.address.clone(), Value(1000)),
+    ]);
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+
+    let inputs = ledger.spendable_inputs(&group.address);
+    assert_eq!(inputs.len(), 3);
+    assert!(inputs.iter().all(|(input, value)| input.value == *value));
+    let mut values: Vec<_> = inputs.iter().map(|(_, value)| *value).collect();
+    values.sort();
+    assert_eq!(values, vec![Value(100), Value(200), Value(300)]);
+    assert_eq!(
+        inputs
+            .iter()
+            .filter(|(input, _)| match input.to_enum() {
+                InputEnum::AccountInput(_, _) => true,
+                InputEnum::UtxoInput(_) => false,
+            })
+            .count(),
+        1
+    );
+}
+
+#[test]
+pub fn replaced_witnesses_are_applied() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let impostor = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+        .authenticate()
+        .with_witness(&block0_hash, &impostor)
+        .seal();
+    assert!(ledger.clone().apply_transaction(&signed_tx, &fees).is_err());
+
+    let transaction_id = signed_tx.transaction.hash();
+    let witness = Witness::new_utxo(&block0_hash, &transaction_id, &faucet.private_key);
+    assert_eq!(
+        signed_tx
+            .clone()
+            .with_witnesses(vec![witness.clone(), witness.clone()]),
+        Err(WitnessesError::CountMismatch {
+            expected: 1,
+            actual: 2
+        })
+    );
+    let signed_tx = signed_tx.with_witnesses(vec![witness]).unwrap();
+    assert!(ledger.apply_transaction(&signed_tx, &fees).is_ok());
+}
+
+#[test]
+pub fn min_block_date_for_length_fills_every_slot() {
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+
+    assert_eq!(
+        ledger.min_block_date_for_length(ChainLength(1)),
+        BlockDate {
+            epoch: 0,
+            slot_id: 1
+        }
+    );
+    assert_eq!(
+        ledger.min_block_date_for_length(ChainLength(100)),
+        BlockDate {
+            epoch: 1,
+            slot_id: 0
+        }
+    );
+    assert_eq!(
+        ledger.min_block_date_for_length(ChainLength(250)),
+        BlockDate {
+            epoch: 2,
+            slot_id: 50
+        }
+    );
+}
+
+#[test]
+pub fn undoing_a_block_restores_the_ledger() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let account = AddressData::account(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let config = ConfigBuilder::new().with_slots_per_epoch(100).build();
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let params = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(60)))
+        .with_output(Output::from_address(account.address.clone(), Value(40)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let fragment = Fragment::Transaction(signed_tx);
+    let block = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        },
+        chain_length: ChainLength(1),
+        nonce: Some(Nonce::from([1; 32])),
+    };
+    let (new_ledger, log) = ledger
+        .apply_block_with_undo(&params, std::iter::once(&fragment), &block)
+        .unwrap();
+    assert!(new_ledger != ledger);
+    assert_eq!(log.chain_length(), ChainLength(0));
+
+    let undone = new_ledger.undo(&log).unwrap();
+    assert!(undone == ledger);
+    assert_eq!(undone.consensus_nonce_hex(), ledger.consensus_nonce_hex());
+    assert_err!(
+        WrongChainLength {
+            actual: ChainLength(0),
+            expected: ChainLength(1)
+        },
+        undone.undo(&log)
+    );
+
+    let bytes = log.serialize_as_vec().unwrap();
+    let decoded: UndoLog = read_from_raw(&bytes).unwrap();
+    assert_eq!(decoded, log);
+    assert!(new_ledger.undo(&decoded).unwrap() == ledger);
+}
+
+#[test]
+pub fn iterate() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(42000),
+    ));
+    let (_block0_hash, mut ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    ledger.updates.rejected.insert(
+        Hash::hash_bytes(&[1]),
+        RejectionReason::Conflict {
+            winner: Hash::hash_bytes(&[2]),
+            tag: Tag::LinearFee,
+        },
+    );
+
+    // FIXME: generate arbitrary ledger
+
+    for item in ledger.iter() {
+        match item {
+            Entry::Globals(globals) => {
+                println!(
+                    "Globals date={} length={} block0_hash={} start_time={:?} discr={} kes_update_speed={}",
+                    globals.date,
+                    globals.chain_length,
+                    globals.static_params.block0_initial_hash,
+                    globals.static_params.block0_start_time,
+                    globals.static_params.discrimination,
+                    globals.static_params.kes_update_speed,
+                );
+            }
+            Entry::Utxo(entry) => {
+                println!(
+                    "Utxo {} {} {}",
+                    entry.transaction_id, entry.output_index, entry.output
+                );
+            }
+            Entry::OldUtxo(entry) => {
+                println!(
+                    "OldUtxo {} {} {}",
+                    entry.transaction_id, entry.output_index, entry.output
+                );
+            }
+            Entry::Account((id, state)) => {
+                println!(
+                    "Account {} {} {:?} {}",
+                    id,
+                    u32::from(state.counter),
+                    state.delegation,
+                    state.value,
+                );
+            }
+            Entry::ConfigParam(param) => {
+                println!(
+                    "ConfigParam {:?} {:?}",
+                    crate::config::Tag::from(&param),
+                    param,
+                );
+            }
+            Entry::UpdateProposal((id, state)) => {
+                println!(
+                    "UpdateProposal {} {:?} {} {:?}",
+                    id, state.proposal, state.proposal_date, state.votes
+                );
+            }
+            Entry::RejectedProposal((id, reason)) => {
+                println!("RejectedProposal {} {:?}", id, reason);
+            }
+            Entry::MultisigAccount((id, state)) => {
+                println!(
+                    "MultisigAccount {} {} {:?} {}",
+                    id,
+                    u32::from(state.counter),
+                    state.delegation,
+                    state.value,
+                );
+            }
+            Entry::MultisigDeclaration((id, decl)) => {
+                println!(
+                    "MultisigDeclaration {} {} {}",
+                    id,
+                    decl.threshold(),
+                    decl.total(),
+                );
+            }
+            Entry::StakePool((id, info)) => {
+                println!(
+                    "StakePool {} {} {:?} {:?}",
+                    id, info.serial, info.owners, info.initial_key,
+                );
+            }
+            Entry::ActivePools((epoch, chain_length, pools)) => {
+                println!("ActivePools {} {} {:?}", epoch, chain_length, pools);
+            }
+            Entry::LeaderSelection(selection) => {
+                println!(
+                    "LeaderSelection {} {} {:?}",
+                    selection.epoch,
+                    selection.pools.len(),
+                    selection.distribution,
+                );
+            }
+        }
+    }
+
+    let ledger2: Result<Ledger, _> = ledger.iter().collect();
+    let ledger2 = ledger2.unwrap();
+
+    assert!(ledger == ledger2);
+}
+
+#[test]
+pub fn transaction_output_limit_follows_the_configured_setting() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::MaxTransactionOutputs(1));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+        .with_output(Output::from_address(receiver.address.clone(), Value(50)))
+        .with_output(Output::from_address(receiver.address.clone(), Value(50)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    assert_err!(
+        TransactionHasTooManyOutputs {
+            expected: 1,
+            actual: 2
+        },
+        ledger.apply_transaction(&signed_tx, &fees)
+    );
+}
+
+#[test]
+pub fn expired_transaction_is_rejected() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let metadata = HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: 0,
+            slot_id: 2,
+        },
+        chain_length: ChainLength(1),
+        nonce: None,
+    };
+
+    for (valid_until_slot, expired) in vec![(1, true), (2, false)] {
+        let valid_until = BlockDate {
+            epoch: 0,
+            slot_id: valid_until_slot,
+        };
+        let signed_tx = TransactionBuilder::new()
+            .with_input(faucet.make_input(Value(100), ledger.utxos().next()))
+            .with_output(Output::from_address(receiver.address.clone(), Value(100)))
+            .with_valid_until(valid_until)
+            .authenticate()
+            .with_witness(&block0_hash, &faucet)
+            .seal();
+        let result = ledger.apply_fragment(&fees, &Fragment::Transaction(signed_tx), &metadata);
+        if expired {
+            assert_err!(
+                TransactionExpired {
+                    valid_until,
+                    block_date: metadata.block_date
+                },
+                result
+            );
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+}
+
+#[test]
+pub fn old_utxo_redemption_is_closed_after_the_deadline() {
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let deadline = BlockDate {
+        epoch: 0,
+        slot_id: 1,
+    };
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::OldUtxoRedemptionDeadline(deadline));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+
+    let input = Input::from_utxo(UtxoPointer {
+        transaction_id: TransactionId::hash_bytes(&[]),
+        output_index: 0,
+        value: Value(100),
+    });
+    let transaction = Transaction {
+        inputs: vec![input],
+        outputs: vec![Output::from_address(receiver.address.clone(), Value(100))],
+        valid_until: None,
+        extra: NoExtra,
+    };
+    let old_key: SecretKey<Ed25519Bip32> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let signature = old_key.sign(&WitnessUtxoData::new(&block0_hash, &transaction.hash()));
+    let signed_tx = AuthenticatedTransaction {
+        transaction,
+        witnesses: vec![Witness::OldUtxo(old_key.to_public(), signature)],
+    };
+
+    for (slot_id, closed) in vec![(1, false), (2, true)] {
+        let metadata = HeaderContentEvalContext {
+            block_date: BlockDate { epoch: 0, slot_id },
+            chain_length: ChainLength(1),
+            nonce: None,
+        };
+        let result =
+            ledger.apply_fragment(&fees, &Fragment::Transaction(signed_tx.clone()), &metadata);
+        if closed {
+            assert_err!(
+                OldUtxoRedemptionClosed {
+                    deadline,
+                    block_date: metadata.block_date
+                },
+                result
+            );
+        } else {
+            // the redemption window is still open, so the input is looked up
+            match result {
+                Err(UtxoError { .. }) => {}
+                _ => panic!("expected the missing old utxo to be reported"),
+            }
+        }
+    }
+}
+
+#[test]
+pub fn stake_pool_registration_must_pay_its_surcharge() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let owner = AddressData::account(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(100),
+    ));
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::LinearFee(LinearFee::new(0, 0, 1)));
+    config.push(ConfigParam::PerCertificateFees(PerCertificateFee::new(
+        5, 10, 5,
+    )));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let stake_pool = ledger::create_stake_pool(vec![owner.public_key.clone().into()]);
+
+    // the certificate costs 1, and the registration 10 more
+    let registration = |change: u64| {
+        let transaction = Transaction {
+            inputs: vec![faucet.make_input(Value(100), ledger.utxos().next())],
+            outputs: vec![Output::from_address(
+                receiver.address.clone(),
+                Value(change),
+            )],
+            valid_until: None,
+            extra: Certificate {
+                content: CertificateContent::StakePoolRegistration(stake_pool.clone()),
+                signatures: Vec::new(),
+            },
+        };
+        let witness = Witness::new_utxo(&block0_hash, &transaction.hash(), &faucet.private_key);
+        AuthenticatedTransaction {
+            transaction,
+            witnesses: vec![witness],
+        }
+    };
+
+    let without_surcharge = registration(99);
+    let with_surcharge = registration(89);
+
+    assert_err!(
+        NotBalanced {
+            inputs: Value(100),
+            outputs: Value(110)
+        },
+        ledger.clone().apply_certificate(&without_surcharge, &fees)
+    );
+    let (_, fee) = ledger.apply_certificate(&with_surcharge, &fees).unwrap();
+    assert_eq!(fee, Value(11));
+}
+
+#[test]
+pub fn total_value_is_preserved_by_transactions_and_certificates() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let receiver = AddressData::utxo(Discrimination::Test);
+    let alice = AddressData::account(Discrimination::Test);
+    let message = ledger::create_initial_transaction(Output::from_address(
+        faucet.address.clone(),
+        Value(1000),
+    ));
+    let mut config = ConfigBuilder::new().build();
+    config.push(ConfigParam::LinearFee(LinearFee::new(2, 0, 1)));
+    config.push(ConfigParam::PerCertificateFees(PerCertificateFee::new(
+        0, 3, 0,
+    )));
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
+    let fees = ledger.get_ledger_parameters();
+    let total = ledger.total_value().unwrap();
+    assert_eq!(total, Value(1000));
+
+    // the fees only move value from the transactions to the pot
+    let signed_tx = TransactionBuilder::new()
+        .with_input(faucet.make_input(Value(1000), ledger.utxos().next()))
+        .with_output(Output::from_address(alice.address.clone(), Value(500)))
+        .with_output(Output::from_address(receiver.address.clone(), Value(498)))
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let (ledger, fee) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    assert_eq!(fee, Value(2));
+    assert_eq!(ledger.total_value().unwrap(), total);
+
+    let receiver_utxo = ledger
+        .utxos()
+        .find(|entry| entry.output.address == receiver.address);
+    let transaction = Transaction {
+        inputs: vec![receiver.make_input(Value(498), receiver_utxo)],
+        outputs: vec![Output::from_address(receiver.address.clone(), Value(492))],
+        valid_until: None,
+        extra: Certificate {
+            content: CertificateContent::StakePoolRegistration(ledger::create_stake_pool(vec![
+                alice.public_key.clone().into(),
+            ])),
+            signatures: Vec::new(),
+        },
+    };
+    let witness = Witness::new_utxo(&block0_hash, &transaction.hash(), &receiver.private_key);
+    let certificate = AuthenticatedTransaction {
+        transaction,
+        witnesses: vec![witness],
+    };
+    let (ledger, fee) = ledger.apply_certificate(&certificate, &fees).unwrap();
+    assert_eq!(fee, Value(6));
+    assert_eq!(ledger.total_value().unwrap(), total);
+
+    let signed_tx = TransactionBuilder::new()
+        .with_input(alice.make_input(Value(100), None))
+        .with_output(Output::from_address(receiver.address.clone(), Value(98)))
+        .authenticate()
+        .with_witness(&block0_hash, &alice)
+        .seal();
+    let (ledger, fee) = ledger.apply_transaction(&signed_tx, &fees).unwrap();
+    assert_eq!(fee, Value(2));
+    assert_eq!(ledger.total_value().unwrap(), total);
+    assert_eq!(ledger.pot, Value(10));
 }
@@ -1,3 +1,7 @@
+pub mod account_tests;
+#[cfg(feature = "with-bench")]
+pub mod bench;
+pub mod delegation_tests;
 pub mod discrimination_tests;
 pub mod initial_funds_tests;
 pub mod ledger_tests;
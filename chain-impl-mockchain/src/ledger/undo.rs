@@ -0,0 +1,556 @@
+//! Log of the ledger state changed by a block, allowing to reverse the
+//! application of the block without replaying the chain from block0.
+
+use super::ledger::Ledger;
+use crate::account;
+use crate::accounting::account::{AccountState, SpendingCounter};
+use crate::block::{BlockDate, ChainLength, Epoch};
+use crate::certificate::{Certificate, CertificateContent};
+use crate::config::Tag;
+use crate::fragment::config::ConfigParams;
+use crate::fragment::Fragment;
+use crate::leadership::bft::LeaderId;
+use crate::leadership::genesis::{ActiveSlotsCoeff, LeaderSelectionSnapshot, Nonce};
+use crate::legacy::OldAddress;
+use crate::milli::Milli;
+use crate::multisig;
+use crate::stake::{PoolStakeDistribution, StakeDistribution, StakePoolId, StakePoolInfo};
+use crate::transaction::{
+    AccountIdentifier, Input, InputEnum, Output, TransactionId, TransactionIndex,
+};
+use crate::update::{
+    RejectionReason, UpdateProposal, UpdateProposalId, UpdateProposalState, UpdateState,
+};
+use crate::value::Value;
+use chain_addr::{Address, Kind};
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::packer::Codec;
+use chain_core::property::{self, Serialize as _};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// The unspent outputs of a transaction, along with the chain length at
+/// which the transaction was added
+pub(crate) type Unspents<OutAddress> = (ChainLength, Vec<(TransactionIndex, Output<OutAddress>)>);
+
+/// The state of the ledger, before applying a block, for everything the
+/// block can change.
+///
+/// Only the entries the block refers to are recorded: the unspent outputs
+/// of the transactions spent from or created, and the accounts and stake
+/// pools used, each along with its state before the block, or none if it
+/// did not exist yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoLog {
+    pub(crate) chain_length: ChainLength,
+    pub(crate) date: BlockDate,
+    pub(crate) pot: Value,
+    pub(crate) settings: ConfigParams,
+    pub(crate) consensus_nonce: Nonce,
+    pub(crate) updates: UpdateState,
+    pub(crate) utxos: Vec<(TransactionId, Option<Unspents<Address>>)>,
+    pub(crate) old_utxos: Vec<(TransactionId, Option<Unspents<OldAddress>>)>,
+    pub(crate) accounts: Vec<(account::Identifier, Option<AccountState<()>>)>,
+    pub(crate) multisig_accounts: Vec<(multisig::Identifier, Option<AccountState<()>>)>,
+    pub(crate) stake_pools: Vec<(StakePoolId, Option<StakePoolInfo>, Option<Epoch>)>,
+    /// The leader selection frozen for the epoch of the ledger, only
+    /// recorded for a block starting a new epoch
+    pub(crate) leader_selection: Option<LeaderSelectionSnapshot>,
+}
+
+/// The keys of the ledger entries a block refers to
+#[derive(Default)]
+struct Touched {
+    transactions: Vec<TransactionId>,
+    accounts: Vec<account::Identifier>,
+    multisig_accounts: Vec<multisig::Identifier>,
+    stake_pools: Vec<StakePoolId>,
+}
+
+fn push_unique<T: PartialEq>(keys: &mut Vec<T>, key: T) {
+    if !keys.contains(&key) {
+        keys.push(key);
+    }
+}
+
+impl Touched {
+    fn transaction(&mut self, id: TransactionId, inputs: &[Input], outputs: &[Output<Address>]) {
+        push_unique(&mut self.transactions, id);
+        for input in inputs {
+            match input.to_enum() {
+                InputEnum::UtxoInput(utxo) => {
+                    push_unique(&mut self.transactions, utxo.transaction_id)
+                }
+                InputEnum::AccountInput(account_id, _) => self.account(&account_id),
+            }
+        }
+        for output in outputs {
+            match output.address.kind() {
+                Kind::Single(_) => {}
+                Kind::Group(_, identifier)
+                | Kind::Account(identifier)
+                | Kind::Delegation(identifier, _) => {
+                    push_unique(&mut self.accounts, identifier.clone().into())
+                }
+                Kind::Multisig(identifier) => push_unique(
+                    &mut self.multisig_accounts,
+                    multisig::Identifier::from(identifier.clone()),
+                ),
+            }
+        }
+    }
+
+    // the witness telling whether a single or a multisig account is meant,
+    // both are recorded
+    fn account(&mut self, account_id: &AccountIdentifier) {
+        if let Some(single) = account_id.to_single_account() {
+            push_unique(&mut self.accounts, single);
+        }
+        push_unique(&mut self.multisig_accounts, account_id.to_multi_account());
+    }
+
+    fn certificate(&mut self, certificate: &Certificate) {
+        match certificate.content {
+            CertificateContent::StakeDelegation(ref reg) => self.account(&reg.stake_key_id),
+            CertificateContent::StakePoolRegistration(ref reg) => {
+                push_unique(&mut self.stake_pools, reg.to_id())
+            }
+            CertificateContent::StakePoolRetirement(ref reg) => {
+                push_unique(&mut self.stake_pools, reg.pool_id.clone())
+            }
+            CertificateContent::AccountLock(ref lock) => self.account(&lock.account_id),
+            CertificateContent::StakePoolOwnershipTransfer(ref transfer) => {
+                push_unique(&mut self.stake_pools, transfer.pool_id.clone())
+            }
+            CertificateContent::BulkDelegation(ref bulk) => {
+                for (account_id, _) in bulk.delegations.iter() {
+                    push_unique(&mut self.accounts, account_id.clone())
+                }
+            }
+        }
+    }
+}
+
+impl UndoLog {
+    /// Record the state of the ledger the given block contents, dated at
+    /// the given date, are about to be applied to
+    pub(crate) fn new<'a, I>(ledger: &Ledger, block_date: BlockDate, contents: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Fragment>,
+    {
+        let mut touched = Touched::default();
+        for content in contents {
+            match content {
+                Fragment::Transaction(signed_tx) => touched.transaction(
+                    signed_tx.transaction.hash(),
+                    &signed_tx.transaction.inputs,
+                    &signed_tx.transaction.outputs,
+                ),
+                Fragment::Certificate(signed_tx) => {
+                    touched.transaction(
+                        signed_tx.transaction.hash(),
+                        &signed_tx.transaction.inputs,
+                        &signed_tx.transaction.outputs,
+                    );
+                    touched.certificate(&signed_tx.transaction.extra);
+                }
+                Fragment::Initial(_)
+                | Fragment::OldUtxoDeclaration(_)
+                | Fragment::UpdateProposal(_)
+                | Fragment::UpdateVote(_) => {}
+            }
+        }
+
+        // the nonce is not a parameter, it is recorded on its own
+        let mut settings = ledger.settings.clone();
+        settings.consensus_nonce = Nonce::zero();
+
+        UndoLog {
+            chain_length: ledger.chain_length,
+            date: ledger.date,
+            pot: ledger.pot,
+            settings: settings.to_config_params(),
+            consensus_nonce: ledger.settings.consensus_nonce.clone(),
+            updates: ledger.updates.clone(),
+            utxos: touched
+                .transactions
+                .iter()
+                .map(|id| (id.clone(), ledger.utxos.unspents(id)))
+                .collect(),
+            old_utxos: touched
+                .transactions
+                .iter()
+                .map(|id| (id.clone(), ledger.oldutxos.unspents(id)))
+                .collect(),
+            accounts: touched
+                .accounts
+                .into_iter()
+                .map(|id| {
+                    let state = ledger.accounts.get_state(&id).ok().cloned();
+                    (id, state)
+                })
+                .collect(),
+            multisig_accounts: touched
+                .multisig_accounts
+                .into_iter()
+                .map(|id| {
+                    let state = ledger.multisig.account_state(&id).cloned();
+                    (id, state)
+                })
+                .collect(),
+            stake_pools: touched
+                .stake_pools
+                .into_iter()
+                .map(|id| {
+                    let info = ledger.delegation.pool_info(&id).cloned();
+                    let retirement = ledger.delegation.retirement_epoch(&id);
+                    (id, info, retirement)
+                })
+                .collect(),
+            leader_selection: if block_date.epoch != ledger.date.epoch {
+                Some(ledger.leader_cache.snapshot())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The chain length of the ledger before the block was applied
+    pub fn chain_length(&self) -> ChainLength {
+        self.chain_length
+    }
+
+    /// The date of the ledger before the block was applied
+    pub fn date(&self) -> BlockDate {
+        self.date
+    }
+}
+
+fn put_option<W, T, F>(codec: &mut Codec<W>, value: &Option<T>, put: F) -> std::io::Result<()>
+where
+    W: Write,
+    F: FnOnce(&mut Codec<W>, &T) -> std::io::Result<()>,
+{
+    match value {
+        None => codec.put_u8(0),
+        Some(value) => {
+            codec.put_u8(1)?;
+            put(codec, value)
+        }
+    }
+}
+
+fn get_option<'a, T, F>(buf: &mut ReadBuf<'a>, get: F) -> Result<Option<T>, ReadError>
+where
+    F: FnOnce(&mut ReadBuf<'a>) -> Result<T, ReadError>,
+{
+    match buf.get_u8()? {
+        0 => Ok(None),
+        1 => get(buf).map(Some),
+        n => Err(ReadError::UnknownTag(n as u32)),
+    }
+}
+
+fn put_date<W: Write>(codec: &mut Codec<W>, date: &BlockDate) -> std::io::Result<()> {
+    codec.put_u32(date.epoch)?;
+    codec.put_u32(date.slot_id)
+}
+
+fn get_date(buf: &mut ReadBuf) -> Result<BlockDate, ReadError> {
+    let epoch = buf.get_u32()?;
+    let slot_id = buf.get_u32()?;
+    Ok(BlockDate { epoch, slot_id })
+}
+
+fn get_array(buf: &mut ReadBuf) -> Result<[u8; 32], ReadError> {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(buf.get_slice(32)?);
+    Ok(bytes)
+}
+
+fn put_old_address<W: Write>(codec: &mut Codec<W>, address: &OldAddress) -> std::io::Result<()> {
+    let bytes = address.as_ref();
+    codec.put_u16(bytes.len() as u16)?;
+    codec.write_all(bytes)
+}
+
+fn get_old_address(buf: &mut ReadBuf) -> Result<OldAddress, ReadError> {
+    let size = buf.get_u16()? as usize;
+    OldAddress::try_from(buf.get_slice(size)?)
+        .map_err(|_| ReadError::StructureInvalid("invalid old address".to_string()))
+}
+
+fn put_unspents<W, A, F>(
+    codec: &mut Codec<W>,
+    entries: &[(TransactionId, Option<Unspents<A>>)],
+    put_address: F,
+) -> std::io::Result<()>
+where
+    W: Write,
+    F: Fn(&mut Codec<W>, &A) -> std::io::Result<()>,
+{
+    codec.put_u32(entries.len() as u32)?;
+    for (id, unspents) in entries {
+        id.serialize(&mut *codec)?;
+        put_option(codec, unspents, |codec, (creation_height, outputs)| {
+            codec.put_u32(creation_height.0)?;
+            codec.put_u8(outputs.len() as u8)?;
+            for (index, output) in outputs {
+                codec.put_u8(*index)?;
+                put_address(codec, &output.address)?;
+                output.value.serialize(&mut *codec)
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn get_unspents<'a, A, F>(
+    buf: &mut ReadBuf<'a>,
+    get_address: F,
+) -> Result<Vec<(TransactionId, Option<Unspents<A>>)>, ReadError>
+where
+    F: Fn(&mut ReadBuf<'a>) -> Result<A, ReadError>,
+{
+    let len = buf.get_u32()?;
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        let id = TransactionId::read(buf)?;
+        let unspents = get_option(buf, |buf| {
+            let creation_height = ChainLength(buf.get_u32()?);
+            let nb_outputs = buf.get_u8()?;
+            let mut outputs = Vec::with_capacity(nb_outputs as usize);
+            for _ in 0..nb_outputs {
+                let index = buf.get_u8()?;
+                let address = get_address(buf)?;
+                let value = Value::read(buf)?;
+                outputs.push((index, Output { address, value }));
+            }
+            Ok((creation_height, outputs))
+        })?;
+        entries.push((id, unspents));
+    }
+    Ok(entries)
+}
+
+fn put_account_state<W: Write>(
+    codec: &mut Codec<W>,
+    state: &AccountState<()>,
+) -> std::io::Result<()> {
+    codec.put_u32(state.counter.into())?;
+    put_option(codec, &state.delegation, |codec, pool_id| {
+        pool_id.serialize(codec)
+    })?;
+    state.value.serialize(&mut *codec)?;
+    put_option(codec, &state.locked_until, |codec, epoch| {
+        codec.put_u32(*epoch)
+    })?;
+    put_option(codec, &state.last_change, put_date)
+}
+
+fn get_account_state(buf: &mut ReadBuf) -> Result<AccountState<()>, ReadError> {
+    let counter = SpendingCounter::from(buf.get_u32()?);
+    let delegation = get_option(buf, StakePoolId::read)?;
+    let value = Value::read(buf)?;
+    let locked_until = get_option(buf, |buf| buf.get_u32())?;
+    let last_change = get_option(buf, get_date)?;
+    Ok(AccountState {
+        counter,
+        delegation,
+        value,
+        locked_until,
+        last_change,
+        extra: (),
+    })
+}
+
+fn put_leader_selection<W: Write>(
+    codec: &mut Codec<W>,
+    selection: &LeaderSelectionSnapshot,
+) -> std::io::Result<()> {
+    codec.put_u32(selection.epoch)?;
+    codec.write_all(selection.epoch_nonce.as_ref())?;
+    codec.put_u64(Milli::from(selection.active_slots_coeff).to_millis())?;
+    codec.put_u32(selection.pools.len() as u32)?;
+    for info in &selection.pools {
+        info.serialize(&mut *codec)?;
+    }
+    let distribution = &selection.distribution;
+    distribution.unassigned.serialize(&mut *codec)?;
+    distribution.dangling.serialize(&mut *codec)?;
+    let mut to_pools: Vec<_> = distribution.to_pools.iter().collect();
+    to_pools.sort_by(|a, b| a.0.cmp(b.0));
+    codec.put_u32(to_pools.len() as u32)?;
+    for (id, pool) in to_pools {
+        id.serialize(&mut *codec)?;
+        pool.total_stake.serialize(&mut *codec)?;
+    }
+    Ok(())
+}
+
+fn get_leader_selection(buf: &mut ReadBuf) -> Result<LeaderSelectionSnapshot, ReadError> {
+    let epoch = buf.get_u32()?;
+    let epoch_nonce = Nonce::from(get_array(buf)?);
+    let active_slots_coeff = ActiveSlotsCoeff::try_from(Milli::from_millis(buf.get_u64()?))
+        .map_err(|error| ReadError::StructureInvalid(error.to_string()))?;
+    let mut pools = Vec::new();
+    for _ in 0..buf.get_u32()? {
+        pools.push(StakePoolInfo::read(buf)?);
+    }
+    let mut distribution = StakeDistribution::empty();
+    distribution.unassigned = Value::read(buf)?;
+    distribution.dangling = Value::read(buf)?;
+    for _ in 0..buf.get_u32()? {
+        let id = StakePoolId::read(buf)?;
+        let total_stake = Value::read(buf)?;
+        distribution
+            .to_pools
+            .insert(id, PoolStakeDistribution { total_stake });
+    }
+    Ok(LeaderSelectionSnapshot {
+        epoch,
+        epoch_nonce,
+        active_slots_coeff,
+        pools,
+        distribution,
+    })
+}
+
+fn put_updates<W: Write>(codec: &mut Codec<W>, updates: &UpdateState) -> std::io::Result<()> {
+    codec.put_u32(updates.proposals.len() as u32)?;
+    for (id, state) in &updates.proposals {
+        id.serialize(&mut *codec)?;
+        state.proposal.serialize(&mut *codec)?;
+        put_date(codec, &state.proposal_date)?;
+        codec.put_u32(state.votes.len() as u32)?;
+        for voter in &state.votes {
+            voter.serialize(&mut *codec)?;
+        }
+    }
+    codec.put_u32(updates.rejected.len() as u32)?;
+    for (id, reason) in &updates.rejected {
+        id.serialize(&mut *codec)?;
+        match reason {
+            RejectionReason::Conflict { winner, tag } => {
+                winner.serialize(&mut *codec)?;
+                codec.put_u16(*tag as u16)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_updates(buf: &mut ReadBuf) -> Result<UpdateState, ReadError> {
+    let mut proposals = BTreeMap::new();
+    for _ in 0..buf.get_u32()? {
+        let id = UpdateProposalId::read(buf)?;
+        let proposal = UpdateProposal::read(buf)?;
+        let proposal_date = get_date(buf)?;
+        let mut votes = HashSet::new();
+        for _ in 0..buf.get_u32()? {
+            votes.insert(LeaderId::read(buf)?);
+        }
+        proposals.insert(
+            id,
+            UpdateProposalState {
+                proposal,
+                proposal_date,
+                votes,
+            },
+        );
+    }
+    let mut rejected = BTreeMap::new();
+    for _ in 0..buf.get_u32()? {
+        let id = UpdateProposalId::read(buf)?;
+        let winner = UpdateProposalId::read(buf)?;
+        let tag = buf.get_u16()?;
+        let tag = Tag::from_u16(tag).ok_or(ReadError::UnknownTag(tag as u32))?;
+        rejected.insert(id, RejectionReason::Conflict { winner, tag });
+    }
+    Ok(UpdateState {
+        proposals,
+        rejected,
+    })
+}
+
+impl property::Serialize for UndoLog {
+    type Error = std::io::Error;
+    fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+        let mut codec = Codec::new(writer);
+        codec.put_u32(self.chain_length.0)?;
+        put_date(&mut codec, &self.date)?;
+        self.pot.serialize(&mut codec)?;
+        self.settings.serialize(&mut codec)?;
+        codec.write_all(self.consensus_nonce.as_ref())?;
+        put_updates(&mut codec, &self.updates)?;
+        put_unspents(&mut codec, &self.utxos, |codec, address| {
+            address.serialize(codec)
+        })?;
+        put_unspents(&mut codec, &self.old_utxos, put_old_address)?;
+        codec.put_u32(self.accounts.len() as u32)?;
+        for (id, state) in &self.accounts {
+            id.serialize(&mut codec)?;
+            put_option(&mut codec, state, put_account_state)?;
+        }
+        codec.put_u32(self.multisig_accounts.len() as u32)?;
+        for (id, state) in &self.multisig_accounts {
+            codec.write_all(id.as_ref())?;
+            put_option(&mut codec, state, put_account_state)?;
+        }
+        codec.put_u32(self.stake_pools.len() as u32)?;
+        for (id, info, retirement) in &self.stake_pools {
+            id.serialize(&mut codec)?;
+            put_option(&mut codec, info, |codec, info| info.serialize(codec))?;
+            put_option(&mut codec, retirement, |codec, epoch| codec.put_u32(*epoch))?;
+        }
+        put_option(&mut codec, &self.leader_selection, put_leader_selection)?;
+        Ok(())
+    }
+}
+
+impl Readable for UndoLog {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let chain_length = ChainLength(buf.get_u32()?);
+        let date = get_date(buf)?;
+        let pot = Value::read(buf)?;
+        let settings = ConfigParams::read(buf)?;
+        let consensus_nonce = Nonce::from(get_array(buf)?);
+        let updates = get_updates(buf)?;
+        let utxos = get_unspents(buf, Address::read)?;
+        let old_utxos = get_unspents(buf, get_old_address)?;
+        let mut accounts = Vec::new();
+        for _ in 0..buf.get_u32()? {
+            let id = account::Identifier::read(buf)?;
+            accounts.push((id, get_option(buf, get_account_state)?));
+        }
+        let mut multisig_accounts = Vec::new();
+        for _ in 0..buf.get_u32()? {
+            let id = multisig::Identifier::from(get_array(buf)?);
+            multisig_accounts.push((id, get_option(buf, get_account_state)?));
+        }
+        let mut stake_pools = Vec::new();
+        for _ in 0..buf.get_u32()? {
+            let id = StakePoolId::read(buf)?;
+            let info = get_option(buf, StakePoolInfo::read)?;
+            let retirement = get_option(buf, |buf| buf.get_u32())?;
+            stake_pools.push((id, info, retirement));
+        }
+        let leader_selection = get_option(buf, get_leader_selection)?;
+        Ok(UndoLog {
+            chain_length,
+            date,
+            pot,
+            settings,
+            consensus_nonce,
+            updates,
+            utxos,
+            old_utxos,
+            accounts,
+            multisig_accounts,
+            stake_pools,
+            leader_selection,
+        })
+    }
+}
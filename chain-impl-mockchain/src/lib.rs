@@ -1,8 +1,12 @@
+#![cfg_attr(feature = "with-bench", feature(test))]
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
 #[macro_use(custom_error)]
 extern crate custom_error;
+#[cfg(test)]
+#[cfg(feature = "with-bench")]
+extern crate test;
 
 pub mod account;
 pub mod accounting;
@@ -1,4 +1,6 @@
 use crate::{account, key};
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::property;
 use chain_crypto::{PublicKey, Signature};
 
 use super::index::{Index, TreeIndex, LEVEL_MAXLIMIT};
@@ -118,5 +120,139 @@ impl Declaration {
     }
 }
 
+/// Maximum number of participants of a declaration read from bytes,
+/// counting the owners of its sub declarations
+pub const MAX_PARTICIPANTS: usize = LEVEL_MAXLIMIT * LEVEL_MAXLIMIT;
+
+const DECL_ELEMENT_OWNER: u8 = 1;
+const DECL_ELEMENT_SUB: u8 = 2;
+
+impl property::Serialize for Declaration {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        assert!(self.owners.len() < 256);
+
+        use chain_core::packer::*;
+        use std::io::Write;
+        let mut codec = Codec::new(writer);
+        codec.put_u8(self.threshold)?;
+        codec.put_u8(self.owners.len() as u8)?;
+        for owner in &self.owners {
+            match owner {
+                DeclElement::Owner(hash) => {
+                    codec.put_u8(DECL_ELEMENT_OWNER)?;
+                    codec.write_all(hash.as_ref())?;
+                }
+                DeclElement::Sub(declaration) => {
+                    codec.put_u8(DECL_ELEMENT_SUB)?;
+                    declaration.serialize(&mut codec)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Declaration {
+    /// Read a declaration having at most `max_participants` participants,
+    /// failing before allocating anything for the ones above the limit.
+    pub fn read_bounded<'a>(
+        buf: &mut ReadBuf<'a>,
+        max_participants: usize,
+    ) -> Result<Self, ReadError> {
+        let mut participants = 0;
+        read_declaration(buf, max_participants, &mut participants, false)
+    }
+}
+
+fn read_declaration<'a>(
+    buf: &mut ReadBuf<'a>,
+    max_participants: usize,
+    participants: &mut usize,
+    is_sub: bool,
+) -> Result<Declaration, ReadError> {
+    let threshold = buf.get_u8()?;
+    let nb_owners = buf.get_u8()? as usize;
+    *participants += nb_owners;
+    if *participants > max_participants {
+        return Err(ReadError::TooManyParticipants(
+            *participants,
+            max_participants,
+        ));
+    }
+    let mut owners = Vec::with_capacity(nb_owners);
+    for _ in 0..nb_owners {
+        let owner = match buf.get_u8()? {
+            DECL_ELEMENT_OWNER => DeclElement::Owner(key::Hash::read(buf)?),
+            // only one level of sub declarations can be indexed
+            DECL_ELEMENT_SUB if !is_sub => {
+                DeclElement::Sub(read_declaration(buf, max_participants, participants, true)?)
+            }
+            DECL_ELEMENT_SUB => {
+                return Err(ReadError::StructureInvalid(
+                    "sub declaration nested in a sub declaration".to_string(),
+                ))
+            }
+            tag => return Err(ReadError::UnknownTag(tag as u32)),
+        };
+        owners.push(owner);
+    }
+    Ok(Declaration { threshold, owners })
+}
+
+impl Readable for Declaration {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        Declaration::read_bounded(buf, MAX_PARTICIPANTS)
+    }
+}
+
 pub type Pk = PublicKey<account::AccountAlg>;
 pub type Sig = Signature<WitnessMultisigData, account::AccountAlg>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_core::mempack::read_from_raw;
+    use chain_core::property::Serialize;
+
+    #[test]
+    fn declaration_serialization_roundtrip() {
+        let sub = Declaration {
+            threshold: 1,
+            owners: vec![
+                DeclElement::Owner(key::Hash::hash_bytes(&[1])),
+                DeclElement::Owner(key::Hash::hash_bytes(&[2])),
+            ],
+        };
+        let declaration = Declaration {
+            threshold: 2,
+            owners: vec![
+                DeclElement::Owner(key::Hash::hash_bytes(&[3])),
+                DeclElement::Sub(sub),
+            ],
+        };
+        let bytes = declaration.serialize_as_vec().unwrap();
+        assert_eq!(read_from_raw::<Declaration>(&bytes).unwrap(), declaration);
+    }
+
+    #[test]
+    fn declaration_claiming_a_million_participants_is_rejected() {
+        // 255 owners, each a sub declaration of 255 owners, each a sub
+        // declaration of 255 owners: more than 16 million participants
+        let mut bytes = Vec::new();
+        for _ in 0..3 {
+            bytes.extend_from_slice(&[1, 255, DECL_ELEMENT_SUB]);
+        }
+        let mut buf = ReadBuf::from(&bytes);
+        match Declaration::read(&mut buf) {
+            Err(ReadError::TooManyParticipants(255, MAX_PARTICIPANTS)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut buf = ReadBuf::from(&bytes);
+        match Declaration::read_bounded(&mut buf, 1_000_000) {
+            Err(ReadError::StructureInvalid(_)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -100,17 +100,39 @@ impl Ledger {
         self.declarations.iter()
     }
 
+    /// Get the declaration of a multisig account
+    pub fn get_declaration(&self, identifier: &Identifier) -> Option<&Declaration> {
+        self.declarations.lookup(identifier)
+    }
+
+    /// Get the balance of a multisig account
+    pub fn get_value(&self, identifier: &Identifier) -> Result<Value, LedgerError> {
+        let state = self.accounts.get_state(identifier)?;
+        Ok(state.value())
+    }
+
+    /// Iterate over all the multisig accounts, with their balance and declaration
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Identifier, Value, &'a Declaration)> + 'a {
+        self.accounts.iter().filter_map(move |(identifier, state)| {
+            self.declarations
+                .lookup(identifier)
+                .map(|declaration| (identifier.clone(), state.value(), declaration))
+        })
+    }
+
     /// If the account doesn't exist, or that the value would become negative, errors out.
     pub fn remove_value(
         &self,
         identifier: &Identifier,
         value: Value,
+        max_counter: SpendingCounter,
     ) -> Result<(Self, &Declaration, SpendingCounter), LedgerError> {
         let decl = self
             .declarations
             .lookup(identifier)
             .ok_or(LedgerError::DoesntExist)?;
-        let (new_accts, spending_counter) = self.accounts.remove_value(identifier, value)?;
+        let (new_accts, spending_counter) =
+            self.accounts.remove_value(identifier, value, max_counter)?;
         Ok((
             Self {
                 accounts: new_accts,
@@ -124,4 +146,65 @@ impl Ledger {
     pub fn get_total_value(&self) -> Result<Value, ValueError> {
         self.accounts.get_total_value()
     }
+
+    pub(crate) fn account_state(
+        &self,
+        identifier: &Identifier,
+    ) -> Option<&account::AccountState<()>> {
+        self.accounts.get_state(identifier).ok()
+    }
+
+    /// Set the state of a multisig account back to the given one, the
+    /// declaration of the account is left untouched
+    pub(crate) fn restore_account_state(
+        &self,
+        identifier: &Identifier,
+        state: Option<account::AccountState<()>>,
+    ) -> Self {
+        Self {
+            accounts: self.accounts.restore(identifier, state),
+            declarations: self.declarations.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::Hash;
+    use crate::multisig::DeclElement;
+
+    fn declaration(seed: u8) -> Declaration {
+        Declaration {
+            threshold: 1,
+            owners: vec![
+                DeclElement::Owner(Hash::hash_bytes(&[seed, 0])),
+                DeclElement::Owner(Hash::hash_bytes(&[seed, 1])),
+            ],
+        }
+    }
+
+    #[test]
+    fn iter_yields_balances_and_declarations() {
+        let decl1 = declaration(1);
+        let decl2 = declaration(2);
+        let id1 = decl1.to_identifier();
+        let id2 = decl2.to_identifier();
+
+        let ledger = Ledger::new()
+            .add_account(&decl1)
+            .unwrap()
+            .add_account(&decl2)
+            .unwrap()
+            .add_value(&id1, Value(100))
+            .unwrap()
+            .add_value(&id2, Value(200))
+            .unwrap();
+
+        let mut accounts: Vec<_> = ledger.iter().collect();
+        accounts.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![(id1, Value(100), &decl1), (id2, Value(200), &decl2)];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(accounts, expected);
+    }
 }
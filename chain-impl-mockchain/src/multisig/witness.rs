@@ -101,6 +101,9 @@ impl Readable for Witness {
     }
 }
 
+/// Signatures of the participants collected so far, to be turned into a
+/// `Witness` once enough of them are present.
+#[derive(Debug, Clone)]
 pub struct WitnessBuilder(BTreeMap<TreeIndex, (Pk, Sig)>);
 
 impl WitnessBuilder {
@@ -114,6 +117,24 @@ impl WitnessBuilder {
         assert_eq!(r.is_none(), true);
     }
 
+    /// Check whether a signature was appended for the given index
+    pub fn contains(&self, index: &TreeIndex) -> bool {
+        self.0.contains_key(index)
+    }
+
+    /// Iterate over the signatures appended so far, in index order
+    pub fn iter(&self) -> impl Iterator<Item = (&TreeIndex, &Pk, &Sig)> {
+        self.0.iter().map(|(index, (pk, sig))| (index, pk, sig))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn finalize(&self) -> Witness {
         let mut v = Vec::new();
         for (idx, (pk, sig)) in self.0.iter() {
@@ -123,6 +144,45 @@ impl WitnessBuilder {
     }
 }
 
+impl property::Serialize for WitnessBuilder {
+    type Error = std::io::Error;
+
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        use chain_core::packer::*;
+
+        let mut codec = Codec::new(writer);
+        codec.put_u8(self.0.len() as u8)?;
+        for (ti, (pk, sig)) in self.0.iter() {
+            codec.put_u16(ti.pack())?;
+            serialize_public_key(pk, &mut codec)?;
+            serialize_signature(sig, &mut codec)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for WitnessBuilder {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let len = buf.get_u8()? as usize;
+
+        let mut m = BTreeMap::new();
+        let mut prev_index = None;
+        for _ in 0..len {
+            let ti = deserialize_index(buf)?;
+            if prev_index.map_or(false, |prev| ti <= prev) {
+                return Err(ReadError::StructureInvalid(
+                    "index not in order".to_string(),
+                ));
+            }
+            let pk = deserialize_public_key(buf)?;
+            let sig = deserialize_signature(buf)?;
+            prev_index = Some(ti);
+            m.insert(ti, (pk, sig));
+        }
+        Ok(WitnessBuilder(m))
+    }
+}
+
 /// Verify that the declaration and the witnesses in parameters fulfill the requirements:
 ///
 /// * The threshold is met: there's at least T or more witnesses available
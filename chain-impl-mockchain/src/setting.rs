@@ -6,14 +6,32 @@ use crate::leadership::genesis::ActiveSlotsCoeff;
 use crate::milli::Milli;
 use crate::update::Error;
 use crate::{
-    block::ConsensusVersion,
-    config::ConfigParam,
-    fee::LinearFee,
+    block::{BlockDate, ConsensusVersion, SlotId},
+    config::{ConfigParam, Tag},
+    fee::{LinearFee, PerCertificateFee},
+    key::Hash,
     leadership::{bft, genesis},
+    value::Value,
 };
+use chain_addr::Address;
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
 use std::sync::Arc;
 
+/// A parameter differing between two settings, with all its old and new values
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettingChange {
+    pub tag: Tag,
+    pub old: Vec<ConfigParam>,
+    pub new: Vec<ConfigParam>,
+}
+
+/// The parameters differing between two settings
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettingsChange {
+    pub changes: Vec<SettingChange>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Settings {
     pub consensus_version: ConsensusVersion,
@@ -23,7 +41,7 @@ pub struct Settings {
     pub epoch_stability_depth: u32,
     pub active_slots_coeff: ActiveSlotsCoeff,
     pub max_number_of_transactions_per_block: u32,
-    pub bft_slots_ratio: Milli, // aka "d" parameter
+    pub bft_slots_ratio: Option<Milli>, // aka "d" parameter
     pub bft_leaders: Arc<Vec<bft::LeaderId>>,
     pub linear_fees: Arc<LinearFee>,
     /// The number of epochs that a proposal remains valid. To be
@@ -31,10 +49,57 @@ pub struct Settings {
     /// it expires at the start of epoch 'epoch_p +
     /// proposal_expiration + 1'. FIXME: make updateable.
     pub proposal_expiration: u32,
+    /// The maximum number of slots between a block and its parent,
+    /// unlimited if not set.
+    pub max_slot_gap: Option<u32>,
+    /// The minimum balance an account can be left with after a spend,
+    /// unless it is emptied entirely. Zero means no minimum.
+    pub account_min_balance: Value,
+    /// Whether the inputs of a transaction must appear in canonical
+    /// (sorted) order, with the witnesses following the same order.
+    pub require_sorted_inputs: bool,
+    /// The maximum value of a single transaction output, unlimited if
+    /// not set.
+    pub max_output_value: Option<Value>,
+    /// Whether an output crediting an account with a zero value is
+    /// rejected, like any other zero-valued output, or simply ignored.
+    pub reject_zero_account_credit: bool,
+    /// The maximum number of inputs of a transaction
+    pub max_transaction_inputs: u32,
+    /// The maximum number of outputs of a transaction
+    pub max_transaction_outputs: u32,
+    /// The maximum number of witnesses of a transaction
+    pub max_transaction_witnesses: u32,
+    /// The surcharges added to the fee of certificate transactions
+    pub per_certificate_fees: PerCertificateFee,
+    /// The number of slots after which, if the scheduled BFT leader has
+    /// not produced a block, the next leader in rotation also becomes
+    /// eligible. No fallback if not set.
+    pub bft_fallback_timeout: Option<u32>,
+    /// The last date at which old utxos can be redeemed, no deadline if
+    /// not set.
+    pub old_utxo_redemption_deadline: Option<BlockDate>,
+    /// The minimum value of an output creating a utxo. Zero means no
+    /// minimum.
+    pub min_utxo_value: Value,
+    /// The addresses transactions cannot spend from or send to, by the
+    /// hash of their binary representation. Nothing is blocked if empty.
+    pub blocked_addresses: Arc<BTreeSet<Hash>>,
+    /// Whether a transaction sending all its outputs back to the
+    /// addresses it spends from is rejected.
+    pub reject_self_transfers: bool,
+    /// The value past which the spending counter of an account cannot be
+    /// incremented; the last spending allowed must withdraw the whole balance.
+    pub max_spending_counter: u32,
 }
 
 pub const SLOTS_PERCENTAGE_RANGE: u8 = 100;
 
+//Default limits for input/output transactions and witnesses
+const MAX_TRANSACTION_INPUTS_COUNT: u32 = 256;
+const MAX_TRANSACTION_OUTPUTS_COUNT: u32 = 254;
+const MAX_TRANSACTION_WITNESSES_COUNT: u32 = 256;
+
 impl Settings {
     pub fn new() -> Self {
         Self {
@@ -45,10 +110,25 @@ impl Settings {
             epoch_stability_depth: 10, // num of block
             active_slots_coeff: ActiveSlotsCoeff::try_from(Milli::HALF).unwrap(),
             max_number_of_transactions_per_block: 100,
-            bft_slots_ratio: Milli::ONE,
+            bft_slots_ratio: None,
             bft_leaders: Arc::new(Vec::new()),
             linear_fees: Arc::new(LinearFee::new(0, 0, 0)),
             proposal_expiration: 100,
+            max_slot_gap: None,
+            account_min_balance: Value::zero(),
+            require_sorted_inputs: false,
+            max_output_value: None,
+            reject_zero_account_credit: true,
+            max_transaction_inputs: MAX_TRANSACTION_INPUTS_COUNT,
+            max_transaction_outputs: MAX_TRANSACTION_OUTPUTS_COUNT,
+            max_transaction_witnesses: MAX_TRANSACTION_WITNESSES_COUNT,
+            per_certificate_fees: PerCertificateFee::zero(),
+            bft_fallback_timeout: None,
+            old_utxo_redemption_deadline: None,
+            min_utxo_value: Value::zero(),
+            blocked_addresses: Arc::new(BTreeSet::new()),
+            reject_self_transfers: false,
+            max_spending_counter: std::u32::MAX,
         }
     }
 
@@ -56,10 +136,74 @@ impl Settings {
         *self.linear_fees
     }
 
+    /// Get the consensus electing the leader of the given slot of an epoch.
+    ///
+    /// Under genesis praos, a `bft_slots_ratio` share of the slots, evenly
+    /// spread over the epoch, is still given to the BFT leaders. All the
+    /// slots are elected by genesis praos if the ratio is not configured.
+    pub fn slot_consensus_type(&self, slot: SlotId) -> ConsensusVersion {
+        match self.consensus_version {
+            ConsensusVersion::Bft => ConsensusVersion::Bft,
+            ConsensusVersion::GenesisPraos => {
+                let ratio = match self.bft_slots_ratio {
+                    None => return ConsensusVersion::GenesisPraos,
+                    Some(ratio) => ratio.to_millis(),
+                };
+                let slot = slot as u64;
+                let ratio_one = Milli::ONE.to_millis();
+                if (slot + 1) * ratio / ratio_one > slot * ratio / ratio_one {
+                    ConsensusVersion::Bft
+                } else {
+                    ConsensusVersion::GenesisPraos
+                }
+            }
+        }
+    }
+
+    /// Check whether transactions cannot spend from or send to the given
+    /// address
+    pub fn is_address_blocked(&self, address: &Address) -> bool {
+        !self.blocked_addresses.is_empty()
+            && self
+                .blocked_addresses
+                .contains(&Hash::hash_bytes(&address.to_bytes()))
+    }
+
+    /// Iterate over the BFT leaders, in the order of their rotation
+    pub fn bft_leaders<'a>(&'a self) -> impl Iterator<Item = &'a bft::LeaderId> + 'a {
+        self.bft_leaders.iter()
+    }
+
+    /// Get the BFT leaders allowed to produce the block of the given slot,
+    /// once `slots_elapsed` slots have passed without it being produced.
+    ///
+    /// The scheduled leader comes first, followed by one more leader in
+    /// rotation for every `bft_fallback_timeout` slots elapsed.
+    pub fn eligible_leaders_for_slot(
+        &self,
+        slot: SlotId,
+        slots_elapsed: u32,
+    ) -> Vec<&bft::LeaderId> {
+        let number_of_leaders = self.bft_leaders.len();
+        if number_of_leaders == 0 {
+            return Vec::new();
+        }
+        let fallbacks = match self.bft_fallback_timeout {
+            Some(timeout) if timeout > 0 => (slots_elapsed / timeout) as usize,
+            _ => 0,
+        };
+        let scheduled = slot as usize % number_of_leaders;
+        (0..=fallbacks.min(number_of_leaders - 1))
+            .map(|offset| &self.bft_leaders[(scheduled + offset) % number_of_leaders])
+            .collect()
+    }
+
+    /// Apply the changes to these settings, in the canonical order of the
+    /// parameters whatever order they were pushed in.
     pub fn apply(&self, changes: &ConfigParams) -> Result<Self, Error> {
         let mut new_state = self.clone();
 
-        for param in changes.iter() {
+        for param in changes.iter_canonical() {
             match param {
                 ConfigParam::Block0Date(_)
                 | ConfigParam::Discrimination(_)
@@ -70,6 +214,9 @@ impl Settings {
                     new_state.consensus_version = *d;
                 }
                 ConfigParam::SlotsPerEpoch(d) => {
+                    if *d == 0 {
+                        return Err(Error::BadSlotsPerEpoch(*d));
+                    }
                     new_state.slots_per_epoch = *d;
                 }
                 ConfigParam::SlotDuration(d) => {
@@ -88,7 +235,7 @@ impl Settings {
                     if *d > Milli::ONE {
                         return Err(Error::BadBftSlotsRatio(*d));
                     }
-                    new_state.bft_slots_ratio = *d;
+                    new_state.bft_slots_ratio = Some(*d);
                 }
                 ConfigParam::AddBftLeader(d) => {
                     // FIXME: O(n)
@@ -112,6 +259,58 @@ impl Settings {
                 ConfigParam::ProposalExpiration(d) => {
                     new_state.proposal_expiration = *d;
                 }
+                ConfigParam::MaxSlotGap(d) => {
+                    new_state.max_slot_gap = Some(*d);
+                }
+                ConfigParam::AccountMinBalance(d) => {
+                    new_state.account_min_balance = *d;
+                }
+                ConfigParam::RequireSortedInputs(d) => {
+                    new_state.require_sorted_inputs = *d;
+                }
+                ConfigParam::MaxOutputValue(d) => {
+                    new_state.max_output_value = Some(*d);
+                }
+                ConfigParam::RejectZeroAccountCredit(d) => {
+                    new_state.reject_zero_account_credit = *d;
+                }
+                ConfigParam::MaxTransactionInputs(d) => {
+                    new_state.max_transaction_inputs = *d;
+                }
+                ConfigParam::MaxTransactionOutputs(d) => {
+                    new_state.max_transaction_outputs = *d;
+                }
+                ConfigParam::MaxTransactionWitnesses(d) => {
+                    new_state.max_transaction_witnesses = *d;
+                }
+                ConfigParam::PerCertificateFees(d) => {
+                    new_state.per_certificate_fees = *d;
+                }
+                ConfigParam::BftFallbackTimeout(d) => {
+                    new_state.bft_fallback_timeout = Some(*d);
+                }
+                ConfigParam::OldUtxoRedemptionDeadline(d) => {
+                    new_state.old_utxo_redemption_deadline = Some(*d);
+                }
+                ConfigParam::MinUtxoValue(d) => {
+                    new_state.min_utxo_value = *d;
+                }
+                ConfigParam::AddBlockedAddress(d) => {
+                    let mut blocked = new_state.blocked_addresses.as_ref().clone();
+                    blocked.insert(*d);
+                    new_state.blocked_addresses = Arc::new(blocked);
+                }
+                ConfigParam::RemoveBlockedAddress(d) => {
+                    let mut blocked = new_state.blocked_addresses.as_ref().clone();
+                    blocked.remove(d);
+                    new_state.blocked_addresses = Arc::new(blocked);
+                }
+                ConfigParam::RejectSelfTransfers(d) => {
+                    new_state.reject_self_transfers = *d;
+                }
+                ConfigParam::MaxSpendingCounter(d) => {
+                    new_state.max_spending_counter = *d;
+                }
             }
         }
 
@@ -131,15 +330,252 @@ impl Settings {
         params.push(ConfigParam::MaxNumberOfTransactionsPerBlock(
             self.max_number_of_transactions_per_block,
         ));
-        params.push(ConfigParam::BftSlotsRatio(self.bft_slots_ratio));
+        if let Some(bft_slots_ratio) = self.bft_slots_ratio {
+            params.push(ConfigParam::BftSlotsRatio(bft_slots_ratio));
+        }
         for bft_leader in self.bft_leaders.iter() {
             params.push(ConfigParam::AddBftLeader(bft_leader.clone()));
         }
         params.push(ConfigParam::LinearFee(*self.linear_fees));
         params.push(ConfigParam::ProposalExpiration(self.proposal_expiration));
+        if let Some(max_slot_gap) = self.max_slot_gap {
+            params.push(ConfigParam::MaxSlotGap(max_slot_gap));
+        }
+        if self.account_min_balance != Value::zero() {
+            params.push(ConfigParam::AccountMinBalance(self.account_min_balance));
+        }
+        if self.require_sorted_inputs {
+            params.push(ConfigParam::RequireSortedInputs(true));
+        }
+        if let Some(max_output_value) = self.max_output_value {
+            params.push(ConfigParam::MaxOutputValue(max_output_value));
+        }
+        if !self.reject_zero_account_credit {
+            params.push(ConfigParam::RejectZeroAccountCredit(false));
+        }
+        if self.max_transaction_inputs != MAX_TRANSACTION_INPUTS_COUNT {
+            params.push(ConfigParam::MaxTransactionInputs(
+                self.max_transaction_inputs,
+            ));
+        }
+        if self.max_transaction_outputs != MAX_TRANSACTION_OUTPUTS_COUNT {
+            params.push(ConfigParam::MaxTransactionOutputs(
+                self.max_transaction_outputs,
+            ));
+        }
+        if self.max_transaction_witnesses != MAX_TRANSACTION_WITNESSES_COUNT {
+            params.push(ConfigParam::MaxTransactionWitnesses(
+                self.max_transaction_witnesses,
+            ));
+        }
+        if self.per_certificate_fees != PerCertificateFee::zero() {
+            params.push(ConfigParam::PerCertificateFees(self.per_certificate_fees));
+        }
+        if let Some(bft_fallback_timeout) = self.bft_fallback_timeout {
+            params.push(ConfigParam::BftFallbackTimeout(bft_fallback_timeout));
+        }
+        if let Some(deadline) = self.old_utxo_redemption_deadline {
+            params.push(ConfigParam::OldUtxoRedemptionDeadline(deadline));
+        }
+        if self.min_utxo_value != Value::zero() {
+            params.push(ConfigParam::MinUtxoValue(self.min_utxo_value));
+        }
+        for blocked_address in self.blocked_addresses.iter() {
+            params.push(ConfigParam::AddBlockedAddress(*blocked_address));
+        }
+        if self.reject_self_transfers {
+            params.push(ConfigParam::RejectSelfTransfers(true));
+        }
+        if self.max_spending_counter != std::u32::MAX {
+            params.push(ConfigParam::MaxSpendingCounter(self.max_spending_counter));
+        }
 
         debug_assert_eq!(self, &Settings::new().apply(&params).unwrap());
 
         params
     }
+
+    /// Hash of the serialized parameters of these settings, committing to
+    /// the whole configuration. The serialization orders the parameters by
+    /// tag, so identical settings always give the same hash.
+    ///
+    /// The consensus nonce is not a parameter and is excluded: the hash
+    /// does not change as blocks update the nonce.
+    pub fn config_hash(&self) -> Hash {
+        use chain_core::property::Serialize;
+        let bytes = self
+            .without_nonce()
+            .to_config_params()
+            .serialize_as_vec()
+            .unwrap();
+        Hash::hash_bytes(&bytes)
+    }
+
+    /// Copy of these settings without the consensus nonce, which is not a
+    /// parameter and cannot be written as one
+    fn without_nonce(&self) -> Settings {
+        let mut settings = self.clone();
+        settings.consensus_nonce = genesis::Nonce::zero();
+        settings
+    }
+
+    /// Get the parameters changed from these settings to the new ones, if
+    /// any. The consensus nonce is not compared.
+    pub fn diff(&self, new: &Settings) -> Option<SettingsChange> {
+        let old_params = self.without_nonce().to_config_params();
+        let new_params = new.without_nonce().to_config_params();
+
+        let mut tags: Vec<Tag> = Vec::new();
+        for param in old_params.iter().chain(new_params.iter()) {
+            let tag = Tag::from(param);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        let values = |params: &ConfigParams, tag: Tag| -> Vec<ConfigParam> {
+            params
+                .iter()
+                .filter(|param| Tag::from(*param) == tag)
+                .cloned()
+                .collect()
+        };
+        let changes: Vec<SettingChange> = tags
+            .into_iter()
+            .filter_map(|tag| {
+                let old = values(&old_params, tag);
+                let new = values(&new_params, tag);
+                if old == new {
+                    None
+                } else {
+                    Some(SettingChange { tag, old, new })
+                }
+            })
+            .collect();
+
+        if changes.is_empty() {
+            None
+        } else {
+            Some(SettingsChange { changes })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_core::mempack::read_from_raw;
+    use chain_core::property::Serialize as _;
+    use chain_crypto::{Ed25519, SecretKey};
+
+    #[test]
+    pub fn half_bft_slots_ratio_alternates_slot_consensus() {
+        let mut settings = Settings::new();
+        settings.consensus_version = ConsensusVersion::GenesisPraos;
+        settings.bft_slots_ratio = Some(Milli::HALF);
+
+        for slot in 0..100 {
+            let expected = if slot % 2 == 1 {
+                ConsensusVersion::Bft
+            } else {
+                ConsensusVersion::GenesisPraos
+            };
+            assert_eq!(settings.slot_consensus_type(slot), expected);
+        }
+
+        settings.bft_slots_ratio = Some(Milli::ZERO);
+        assert!((0..100)
+            .all(|slot| settings.slot_consensus_type(slot) == ConsensusVersion::GenesisPraos));
+        settings.bft_slots_ratio = Some(Milli::ONE);
+        assert!((0..100).all(|slot| settings.slot_consensus_type(slot) == ConsensusVersion::Bft));
+    }
+
+    #[test]
+    pub fn diff_lists_exactly_the_changed_fields() {
+        let old = Settings::new();
+        let mut new = old.clone();
+        new.linear_fees = Arc::new(LinearFee::new(1, 2, 3));
+        new.epoch_stability_depth = old.epoch_stability_depth + 1;
+
+        assert_eq!(old.diff(&old), None);
+        assert_eq!(
+            old.diff(&new),
+            Some(SettingsChange {
+                changes: vec![
+                    SettingChange {
+                        tag: Tag::EpochStabilityDepth,
+                        old: vec![ConfigParam::EpochStabilityDepth(old.epoch_stability_depth)],
+                        new: vec![ConfigParam::EpochStabilityDepth(new.epoch_stability_depth)],
+                    },
+                    SettingChange {
+                        tag: Tag::LinearFee,
+                        old: vec![ConfigParam::LinearFee(old.linear_fees())],
+                        new: vec![ConfigParam::LinearFee(new.linear_fees())],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    pub fn changes_have_the_same_effect_after_a_round_trip() {
+        let sk: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+        let leader = bft::LeaderId::from(sk.to_public());
+        let mut settings = Settings::new();
+        settings.bft_leaders = Arc::new(vec![leader.clone()]);
+
+        let mut changes = ConfigParams::new();
+        changes.push(ConfigParam::RemoveBftLeader(leader.clone()));
+        changes.push(ConfigParam::AddBftLeader(leader));
+        let decoded: ConfigParams = read_from_raw(&changes.serialize_as_vec().unwrap()).unwrap();
+
+        // applied in tag order: the leader is added then removed
+        let applied = settings.apply(&changes).unwrap();
+        assert!(applied.bft_leaders.is_empty());
+        assert_eq!(applied, settings.apply(&decoded).unwrap());
+    }
+
+    #[test]
+    pub fn config_hash_commits_to_every_parameter() {
+        let settings = Settings::new();
+        assert_eq!(settings.config_hash(), Settings::new().config_hash());
+        assert_eq!(settings.config_hash(), settings.clone().config_hash());
+
+        let mut fees = settings.clone();
+        fees.linear_fees = Arc::new(LinearFee::new(1, 2, 3));
+        assert_ne!(settings.config_hash(), fees.config_hash());
+
+        let mut min_balance = settings.clone();
+        min_balance.account_min_balance = Value(1);
+        assert_ne!(settings.config_hash(), min_balance.config_hash());
+        assert_ne!(fees.config_hash(), min_balance.config_hash());
+    }
+
+    #[test]
+    pub fn fallback_leader_is_eligible_after_the_timeout() {
+        let leaders: Vec<bft::LeaderId> = std::iter::repeat_with(|| {
+            let sk: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+            bft::LeaderId::from(sk.to_public())
+        })
+        .take(3)
+        .collect();
+        let mut settings = Settings::new();
+        settings.bft_leaders = Arc::new(leaders.clone());
+
+        assert_eq!(
+            settings.eligible_leaders_for_slot(4, 100),
+            vec![&leaders[1]]
+        );
+
+        settings.bft_fallback_timeout = Some(5);
+        assert_eq!(settings.eligible_leaders_for_slot(4, 4), vec![&leaders[1]]);
+        assert_eq!(
+            settings.eligible_leaders_for_slot(4, 5),
+            vec![&leaders[1], &leaders[2]]
+        );
+        assert_eq!(
+            settings.eligible_leaders_for_slot(5, 100),
+            vec![&leaders[2], &leaders[0], &leaders[1]]
+        );
+    }
 }
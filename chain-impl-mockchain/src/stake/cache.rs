@@ -0,0 +1,346 @@
+use crate::account;
+use crate::transaction::{Input, InputEnum, Output, Witness};
+use crate::{stake::StakePoolId, utxo, value::Value};
+use chain_addr::{Address, Kind};
+use imhamt::Hamt;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::delegation::DelegationState;
+use super::distribution::{get_distribution, PoolStakeDistribution, StakeDistribution};
+
+/// Number of incremental updates after which the cached distribution is
+/// recomputed from scratch.
+const VALIDATION_PERIOD: u32 = 1024;
+
+/// Stake distribution kept up to date incrementally as the ledger changes,
+/// instead of scanning all the accounts and utxos on every query.
+///
+/// The cache is periodically validated against a full recompute.
+#[derive(Clone)]
+pub struct StakeDistributionCache {
+    distribution: Arc<StakeDistribution>,
+    /// Value of the group utxos, by the account their stake belongs to
+    group_stake: Hamt<DefaultHasher, account::Identifier, Value>,
+    /// Stake delegated to each pool which is not registered, the sum of
+    /// which is the dangling stake of the distribution
+    dangling_stake: Hamt<DefaultHasher, StakePoolId, Value>,
+    updates: u32,
+}
+
+impl PartialEq for StakeDistributionCache {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.distribution == rhs.distribution
+    }
+}
+impl Eq for StakeDistributionCache {}
+
+/// Where the value of an input or an output is accounted for in the distribution
+enum StakeTarget {
+    Unassigned,
+    Pool(StakePoolId),
+    Uncounted,
+}
+
+fn address_target(accounts: &account::Ledger, address: &Address) -> StakeTarget {
+    match address.kind() {
+        Kind::Single(_) => StakeTarget::Unassigned,
+        Kind::Group(_, account_key) => group_target(accounts, &account_key.clone().into()),
+        Kind::Account(identifier) | Kind::Delegation(identifier, _) => {
+            account_target(accounts, &identifier.clone().into())
+        }
+        Kind::Multisig(_) => StakeTarget::Uncounted,
+    }
+}
+
+fn group_target(accounts: &account::Ledger, identifier: &account::Identifier) -> StakeTarget {
+    match accounts.get_state(identifier) {
+        Ok(st) => match st.delegation() {
+            Some(pool_id) => StakeTarget::Pool(pool_id.clone()),
+            None => StakeTarget::Uncounted,
+        },
+        Err(_) => StakeTarget::Uncounted,
+    }
+}
+
+fn account_target(accounts: &account::Ledger, identifier: &account::Identifier) -> StakeTarget {
+    match accounts.get_state(identifier) {
+        Ok(st) => match st.delegation() {
+            Some(pool_id) => StakeTarget::Pool(pool_id.clone()),
+            None => StakeTarget::Unassigned,
+        },
+        Err(_) => StakeTarget::Unassigned,
+    }
+}
+
+fn group_account(address: &Address) -> Option<account::Identifier> {
+    match address.kind() {
+        Kind::Group(_, account_key) => Some(account_key.clone().into()),
+        _ => None,
+    }
+}
+
+fn credit<K: Clone + Eq + Hash>(
+    map: &Hamt<DefaultHasher, K, Value>,
+    key: &K,
+    value: Value,
+) -> Hamt<DefaultHasher, K, Value> {
+    if value == Value::zero() {
+        return map.clone();
+    }
+    map.insert_or_update(key.clone(), value, |current| {
+        Ok::<_, ()>(Some(
+            (*current + value).expect("internal error: total amount of stake overflow"),
+        ))
+    })
+    .expect("internal error: stake cannot fail to be credited")
+}
+
+fn debit<K: Clone + Eq + Hash>(
+    map: &Hamt<DefaultHasher, K, Value>,
+    key: &K,
+    value: Value,
+) -> Hamt<DefaultHasher, K, Value> {
+    if value == Value::zero() {
+        return map.clone();
+    }
+    map.update(key, |current| {
+        let left = (*current - value).expect("internal error: negative amount of stake");
+        Ok::<_, ()>(if left == Value::zero() {
+            None
+        } else {
+            Some(left)
+        })
+    })
+    .expect("internal error: debiting stake which was never credited")
+}
+
+fn get_group_stake(
+    utxos: &utxo::Ledger<Address>,
+) -> Hamt<DefaultHasher, account::Identifier, Value> {
+    utxos.values().fold(Hamt::new(), |group_stake, output| {
+        match group_account(&output.address) {
+            Some(identifier) => credit(&group_stake, &identifier, output.value),
+            None => group_stake,
+        }
+    })
+}
+
+fn get_dangling_stake(
+    accounts: &account::Ledger,
+    dstate: &DelegationState,
+    group_stake: &Hamt<DefaultHasher, account::Identifier, Value>,
+) -> Hamt<DefaultHasher, StakePoolId, Value> {
+    accounts
+        .iter()
+        .fold(Hamt::new(), |dangling_stake, (identifier, st)| {
+            match st.delegation() {
+                Some(pool_id) if !dstate.stake_pool_exists(pool_id) => {
+                    let groups = group_stake
+                        .lookup(identifier)
+                        .cloned()
+                        .unwrap_or_else(Value::zero);
+                    let value = (st.value() + groups)
+                        .expect("internal error: total amount of stake overflow");
+                    credit(&dangling_stake, pool_id, value)
+                }
+                _ => dangling_stake,
+            }
+        })
+}
+
+impl StakeDistributionCache {
+    pub fn empty() -> Self {
+        StakeDistributionCache {
+            distribution: Arc::new(StakeDistribution::empty()),
+            group_stake: Hamt::new(),
+            dangling_stake: Hamt::new(),
+            updates: 0,
+        }
+    }
+
+    /// Create the cache from a full computation of the distribution
+    pub fn new(
+        accounts: &account::Ledger,
+        dstate: &DelegationState,
+        utxos: &utxo::Ledger<Address>,
+    ) -> Self {
+        let group_stake = get_group_stake(utxos);
+        StakeDistributionCache {
+            distribution: Arc::new(get_distribution(accounts, dstate, utxos)),
+            dangling_stake: get_dangling_stake(accounts, dstate, &group_stake),
+            group_stake,
+            updates: 0,
+        }
+    }
+
+    pub fn distribution(&self) -> &StakeDistribution {
+        &self.distribution
+    }
+
+    fn update(&mut self, target: StakeTarget, value: Value, add: bool) {
+        let apply = |current: Value| {
+            if add {
+                (current + value).expect("internal error: total amount of stake overflow")
+            } else {
+                (current - value).expect("internal error: negative amount of stake")
+            }
+        };
+        let distribution = Arc::make_mut(&mut self.distribution);
+        match target {
+            StakeTarget::Uncounted => {}
+            StakeTarget::Unassigned => distribution.unassigned = apply(distribution.unassigned),
+            StakeTarget::Pool(pool_id) => match distribution.to_pools.get_mut(&pool_id) {
+                Some(pool) => pool.total_stake = apply(pool.total_stake),
+                None => {
+                    distribution.dangling = apply(distribution.dangling);
+                    self.dangling_stake = if add {
+                        credit(&self.dangling_stake, &pool_id, value)
+                    } else {
+                        debit(&self.dangling_stake, &pool_id, value)
+                    };
+                }
+            },
+        }
+    }
+
+    /// Update the distribution with the value moved by a transaction.
+    ///
+    /// The accounts and utxos are the state of the ledger *before* the
+    /// transaction is applied, and the transaction must have been applied
+    /// successfully so the inputs are known to be valid. The accounts after
+    /// the transaction tell the pool of the accounts created by delegation
+    /// outputs.
+    pub(crate) fn apply_transaction(
+        &self,
+        accounts: &account::Ledger,
+        accounts_after: &account::Ledger,
+        utxos: &utxo::Ledger<Address>,
+        inputs: &[Input],
+        witnesses: &[Witness],
+        outputs: &[Output<Address>],
+    ) -> Self {
+        let mut cache = self.clone();
+        for (input, witness) in inputs.iter().zip(witnesses.iter()) {
+            let target = match (input.to_enum(), witness) {
+                (InputEnum::UtxoInput(ptr), Witness::Utxo(_)) => {
+                    match utxos.get(&ptr.transaction_id, &ptr.output_index) {
+                        Some(entry) => {
+                            if let Some(identifier) = group_account(&entry.output.address) {
+                                cache.group_stake =
+                                    debit(&cache.group_stake, &identifier, input.value);
+                            }
+                            address_target(accounts, &entry.output.address)
+                        }
+                        None => StakeTarget::Uncounted,
+                    }
+                }
+                (InputEnum::AccountInput(account_id, _), Witness::Account(_)) => {
+                    match account_id.to_single_account() {
+                        Some(account) => account_target(accounts, &account),
+                        None => StakeTarget::Uncounted,
+                    }
+                }
+                _ => StakeTarget::Uncounted,
+            };
+            cache.update(target, input.value, false);
+        }
+        for output in outputs {
+            if let Some(identifier) = group_account(&output.address) {
+                cache.group_stake = credit(&cache.group_stake, &identifier, output.value);
+            }
+            cache.update(
+                address_target(accounts_after, &output.address),
+                output.value,
+                true,
+            );
+        }
+        cache
+    }
+
+    /// Move the stake of an account, along with the stake of the group
+    /// utxos it holds the stake key of, to the pool it now delegates to.
+    ///
+    /// The accounts are the state of the ledger before and after the
+    /// delegation of the account changed.
+    pub(crate) fn apply_delegation(
+        &self,
+        accounts: &account::Ledger,
+        accounts_after: &account::Ledger,
+        identifier: &account::Identifier,
+    ) -> Self {
+        let mut cache = self.clone();
+        let value = accounts_after
+            .get_state(identifier)
+            .map(|st| st.value())
+            .unwrap_or_else(|_| Value::zero());
+        cache.update(account_target(accounts, identifier), value, false);
+        cache.update(account_target(accounts_after, identifier), value, true);
+        if let Some(groups) = self.group_stake.lookup(identifier) {
+            cache.update(group_target(accounts, identifier), *groups, false);
+            cache.update(group_target(accounts_after, identifier), *groups, true);
+        }
+        cache
+    }
+
+    /// Count the stake delegated to a newly registered pool toward it,
+    /// instead of the dangling stake.
+    pub(crate) fn register_pool(&self, pool_id: &StakePoolId) -> Self {
+        let mut cache = self.clone();
+        let stake = self
+            .dangling_stake
+            .lookup(pool_id)
+            .cloned()
+            .unwrap_or_else(Value::zero);
+        cache.dangling_stake = debit(&self.dangling_stake, pool_id, stake);
+        let distribution = Arc::make_mut(&mut cache.distribution);
+        distribution.dangling =
+            (distribution.dangling - stake).expect("internal error: negative amount of stake");
+        distribution.to_pools.insert(
+            pool_id.clone(),
+            PoolStakeDistribution { total_stake: stake },
+        );
+        cache
+    }
+
+    /// Count the stake delegated to a retired pool as dangling
+    pub(crate) fn retire_pool(&self, pool_id: &StakePoolId) -> Self {
+        let mut cache = self.clone();
+        let distribution = Arc::make_mut(&mut cache.distribution);
+        if let Some(pool) = distribution.to_pools.remove(pool_id) {
+            distribution.dangling = (distribution.dangling + pool.total_stake)
+                .expect("internal error: total amount of stake overflow");
+            cache.dangling_stake = credit(&self.dangling_stake, pool_id, pool.total_stake);
+        }
+        cache
+    }
+
+    /// Record an incremental update, recomputing the distribution from
+    /// scratch once enough updates have been made since the last recompute.
+    ///
+    /// The accounts, delegation state and utxos are the state of the ledger
+    /// *after* the update.
+    pub(crate) fn checkpoint(
+        mut self,
+        accounts: &account::Ledger,
+        dstate: &DelegationState,
+        utxos: &utxo::Ledger<Address>,
+    ) -> Self {
+        self.updates += 1;
+        if self.updates < VALIDATION_PERIOD {
+            return self;
+        }
+        let recomputed = Self::new(accounts, dstate, utxos);
+        debug_assert_eq!(
+            self.distribution, recomputed.distribution,
+            "stake distribution cache diverged from the ledger"
+        );
+        debug_assert!(
+            self.group_stake == recomputed.group_stake
+                && self.dangling_stake == recomputed.dangling_stake,
+            "stake distribution cache diverged from the ledger"
+        );
+        recomputed
+    }
+}
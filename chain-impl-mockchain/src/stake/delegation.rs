@@ -1,7 +1,9 @@
 use imhamt::Hamt;
 use std::collections::hash_map::DefaultHasher;
 
-use super::role::{StakePoolId, StakePoolInfo};
+use super::role::{StakePoolId, StakePoolInfo, STAKE_POOL_MAX_OWNERS};
+use crate::account;
+use crate::date::Epoch;
 use crate::transaction::AccountIdentifier;
 /// All registered Stake Node
 pub type PoolTable = Hamt<DefaultHasher, StakePoolId, StakePoolInfo>;
@@ -10,6 +12,8 @@ pub type PoolTable = Hamt<DefaultHasher, StakePoolId, StakePoolInfo>;
 #[derive(Clone, PartialEq, Eq)]
 pub struct DelegationState {
     pub(crate) stake_pools: PoolTable,
+    /// The epoch at which registered stake pools are scheduled to retire
+    pub(crate) retirements: Hamt<DefaultHasher, StakePoolId, Epoch>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,9 +22,13 @@ pub enum DelegationError {
     StakeDelegationPoolKeyIsInvalid(StakePoolId),
     StakeDelegationAccountIsInvalid(AccountIdentifier),
     StakePoolRegistrationPoolSigIsInvalid,
-    StakePoolAlreadyExists(StakePoolId),
+    StakePoolAlreadyRegistered(StakePoolId),
     StakePoolRetirementSigIsInvalid,
     StakePoolDoesNotExist(StakePoolId),
+    StakePoolOwnershipTransferSigIsInvalid,
+    StakePoolOwnersInvalid(usize),
+    DuplicateVrfKey(StakePoolId),
+    BulkDelegationSigIsInvalid(account::Identifier),
 }
 
 impl std::fmt::Display for DelegationError {
@@ -44,9 +52,9 @@ impl std::fmt::Display for DelegationError {
                 f,
                 "Block has a pool registration certificate with an invalid pool signature"
             ),
-            DelegationError::StakePoolAlreadyExists(pool_id) => write!(
+            DelegationError::StakePoolAlreadyRegistered(pool_id) => write!(
                 f,
-                "Block attempts to register pool '{:?}' which already exists",
+                "Block attempts to register pool '{:?}' which is already registered",
                 pool_id
             ),
             DelegationError::StakePoolRetirementSigIsInvalid => write!(
@@ -58,6 +66,25 @@ impl std::fmt::Display for DelegationError {
                 "Block references a pool '{:?}' which does not exist",
                 pool_id
             ),
+            DelegationError::StakePoolOwnershipTransferSigIsInvalid => write!(
+                f,
+                "Block has a pool ownership transfer certificate not signed by a majority of the pool owners"
+            ),
+            DelegationError::StakePoolOwnersInvalid(owners) => write!(
+                f,
+                "Block has a pool ownership transfer certificate with {} owners, expected between 1 and {}",
+                owners, STAKE_POOL_MAX_OWNERS
+            ),
+            DelegationError::DuplicateVrfKey(pool_id) => write!(
+                f,
+                "Block attempts to register a pool with the VRF public key of pool '{:?}'",
+                pool_id
+            ),
+            DelegationError::BulkDelegationSigIsInvalid(account_id) => write!(
+                f,
+                "Block has a bulk delegation certificate not signed by the delegated account '{:?}'",
+                account_id
+            ),
         }
     }
 }
@@ -68,6 +95,7 @@ impl DelegationState {
     pub fn new() -> Self {
         DelegationState {
             stake_pools: Hamt::new(),
+            retirements: Hamt::new(),
         }
     }
 
@@ -81,14 +109,57 @@ impl DelegationState {
             .map_or_else(|| false, |_| true)
     }
 
+    /// Get the registration information of the given stake pool
+    pub fn pool_info(&self, pool_id: &StakePoolId) -> Option<&StakePoolInfo> {
+        self.stake_pools.lookup(pool_id)
+    }
+
+    /// Register a new stake pool, a pool cannot be registered twice and
+    /// its VRF public key must not be used by another registered pool
     pub fn register_stake_pool(&self, owner: StakePoolInfo) -> Result<Self, DelegationError> {
         let id = owner.to_id();
+        if self.stake_pool_exists(&id) {
+            return Err(DelegationError::StakePoolAlreadyRegistered(id));
+        }
+        // FIXME: O(n)
+        if let Some((other_id, _)) = self
+            .stake_pools
+            .iter()
+            .find(|(_, info)| info.initial_key.vrf_public_key == owner.initial_key.vrf_public_key)
+        {
+            return Err(DelegationError::DuplicateVrfKey(other_id.clone()));
+        }
         let new_pools = self
             .stake_pools
             .insert(id.clone(), owner)
-            .map_err(|_| DelegationError::StakePoolAlreadyExists(id))?;
+            .map_err(|_| DelegationError::StakePoolAlreadyRegistered(id))?;
         Ok(DelegationState {
             stake_pools: new_pools,
+            retirements: self.retirements.clone(),
+        })
+    }
+
+    /// Replace the owners of a registered stake pool
+    pub fn transfer_ownership(
+        &self,
+        pool_id: &StakePoolId,
+        new_owners: Vec<account::Identifier>,
+    ) -> Result<Self, DelegationError> {
+        if new_owners.is_empty() || new_owners.len() > STAKE_POOL_MAX_OWNERS {
+            return Err(DelegationError::StakePoolOwnersInvalid(new_owners.len()));
+        }
+        let new_pools = self
+            .stake_pools
+            .update(pool_id, |info| {
+                Ok::<_, ()>(Some(StakePoolInfo {
+                    owners: new_owners,
+                    ..info.clone()
+                }))
+            })
+            .map_err(|_| DelegationError::StakePoolDoesNotExist(pool_id.clone()))?;
+        Ok(DelegationState {
+            stake_pools: new_pools,
+            retirements: self.retirements.clone(),
         })
     }
 
@@ -98,6 +169,158 @@ impl DelegationState {
                 .stake_pools
                 .remove(pool_id)
                 .map_err(|_| DelegationError::StakePoolDoesNotExist(pool_id.clone()))?,
+            retirements: self
+                .retirements
+                .remove(pool_id)
+                .unwrap_or_else(|_| self.retirements.clone()),
+        })
+    }
+
+    /// Schedule the retirement of a registered stake pool at the given
+    /// epoch, replacing any previously scheduled retirement
+    pub fn schedule_retirement(
+        &self,
+        pool_id: &StakePoolId,
+        epoch: Epoch,
+    ) -> Result<Self, DelegationError> {
+        if !self.stake_pool_exists(pool_id) {
+            return Err(DelegationError::StakePoolDoesNotExist(pool_id.clone()));
+        }
+        Ok(DelegationState {
+            stake_pools: self.stake_pools.clone(),
+            retirements: self
+                .retirements
+                .insert_or_update(pool_id.clone(), epoch, |_| Ok::<_, ()>(Some(epoch)))
+                .expect("inserting or replacing a retirement epoch cannot fail"),
         })
     }
+
+    /// Get the epoch at which a stake pool is scheduled to retire, if any
+    pub fn retirement_epoch(&self, pool_id: &StakePoolId) -> Option<Epoch> {
+        self.retirements.lookup(pool_id).cloned()
+    }
+
+    /// Set the registration and scheduled retirement of a stake pool back
+    /// to the given ones, a pool without registration being removed
+    pub(crate) fn restore_stake_pool(
+        &self,
+        pool_id: &StakePoolId,
+        info: Option<StakePoolInfo>,
+        retirement: Option<Epoch>,
+    ) -> Self {
+        let mut stake_pools = self
+            .stake_pools
+            .remove(pool_id)
+            .unwrap_or_else(|_| self.stake_pools.clone());
+        if let Some(info) = info {
+            stake_pools = stake_pools
+                .insert(pool_id.clone(), info)
+                .expect("stake pool was removed");
+        }
+        let mut retirements = self
+            .retirements
+            .remove(pool_id)
+            .unwrap_or_else(|_| self.retirements.clone());
+        if let Some(epoch) = retirement {
+            retirements = retirements
+                .insert(pool_id.clone(), epoch)
+                .expect("retirement was removed");
+        }
+        DelegationState {
+            stake_pools,
+            retirements,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::leadership::genesis::GenesisPraosLeader;
+    use crate::testing::ledger::create_stake_pool;
+
+    #[test]
+    fn registering_a_stake_pool_twice_is_rejected() {
+        let pool = create_stake_pool(vec![]);
+        let pool_id = pool.to_id();
+
+        let state = DelegationState::new()
+            .register_stake_pool(pool.clone())
+            .unwrap();
+        assert!(state.stake_pool_exists(&pool_id));
+        assert_eq!(state.pool_info(&pool_id), Some(&pool));
+        assert_eq!(
+            state.register_stake_pool(pool).err(),
+            Some(DelegationError::StakePoolAlreadyRegistered(pool_id))
+        );
+    }
+
+    #[test]
+    fn registering_a_stake_pool_with_a_used_vrf_key_is_rejected() {
+        let pool = create_stake_pool(vec![]);
+        let pool_id = pool.to_id();
+        let other_pool = StakePoolInfo {
+            serial: 1,
+            initial_key: GenesisPraosLeader {
+                kes_public_key: create_stake_pool(vec![]).initial_key.kes_public_key,
+                vrf_public_key: pool.initial_key.vrf_public_key.clone(),
+            },
+            ..pool.clone()
+        };
+
+        let state = DelegationState::new().register_stake_pool(pool).unwrap();
+        assert_eq!(
+            state.register_stake_pool(other_pool).err(),
+            Some(DelegationError::DuplicateVrfKey(pool_id))
+        );
+        assert!(state.register_stake_pool(create_stake_pool(vec![])).is_ok());
+    }
+
+    #[test]
+    fn scheduled_retirement_epoch_is_reported() {
+        let retiring = create_stake_pool(vec![]);
+        let retiring_id = retiring.to_id();
+        let staying = create_stake_pool(vec![]);
+        let staying_id = staying.to_id();
+        let state = DelegationState::new()
+            .register_stake_pool(retiring)
+            .unwrap()
+            .register_stake_pool(staying)
+            .unwrap();
+
+        let state = state.schedule_retirement(&retiring_id, 10).unwrap();
+        assert_eq!(state.retirement_epoch(&retiring_id), Some(10));
+        assert_eq!(state.retirement_epoch(&staying_id), None);
+
+        let unknown_id = create_stake_pool(vec![]).to_id();
+        assert_eq!(
+            state.schedule_retirement(&unknown_id, 10).err(),
+            Some(DelegationError::StakePoolDoesNotExist(unknown_id))
+        );
+
+        let state = state.deregister_stake_pool(&retiring_id).unwrap();
+        assert_eq!(state.retirement_epoch(&retiring_id), None);
+    }
+
+    #[test]
+    fn transferring_ownership_replaces_the_owners() {
+        let owner = account::Identifier::from(
+            chain_crypto::SecretKey::<chain_crypto::Ed25519>::generate(
+                rand_os::OsRng::new().unwrap(),
+            )
+            .to_public(),
+        );
+        let pool = create_stake_pool(vec![]);
+        let pool_id = pool.to_id();
+        let state = DelegationState::new().register_stake_pool(pool).unwrap();
+
+        assert_eq!(
+            state.transfer_ownership(&pool_id, vec![]).err(),
+            Some(DelegationError::StakePoolOwnersInvalid(0))
+        );
+        let state = state
+            .transfer_ownership(&pool_id, vec![owner.clone()])
+            .unwrap();
+        assert_eq!(state.pool_info(&pool_id).unwrap().owners, vec![owner]);
+    }
 }
@@ -25,6 +25,15 @@ pub struct PoolStakeDistribution {
     pub total_stake: Value,
 }
 
+/// The stake of a pool along with its share of the stake of all the pools,
+/// in a flat form easy to export
+#[derive(Debug, Clone, PartialEq)]
+pub struct StakeRecord {
+    pub pool: StakePoolId,
+    pub stake: Value,
+    pub share: f64,
+}
+
 impl StakeDistribution {
     pub fn empty() -> Self {
         StakeDistribution {
@@ -51,9 +60,34 @@ impl StakeDistribution {
         self.to_pools.get(poolid).map(|psd| psd.total_stake)
     }
 
+    /// Return the total stake delegated to the given pool, zero if the pool is unknown
+    pub fn stake_of(&self, pool: &StakePoolId) -> Value {
+        self.get_stake_for(pool).unwrap_or_else(Value::zero)
+    }
+
     pub fn get_distribution(&self, stake_pool_id: &StakePoolId) -> Option<&PoolStakeDistribution> {
         self.to_pools.get(stake_pool_id)
     }
+
+    /// List the stake of every eligible pool, sorted by pool identifier
+    pub fn to_records(&self) -> Vec<StakeRecord> {
+        let total = self.total_stake().0 as f64;
+        let mut records: Vec<StakeRecord> = self
+            .to_pools
+            .iter()
+            .map(|(pool, distribution)| StakeRecord {
+                pool: pool.clone(),
+                stake: distribution.total_stake,
+                share: if total == 0.0 {
+                    0.0
+                } else {
+                    distribution.total_stake.0 as f64 / total
+                },
+            })
+            .collect();
+        records.sort_by(|a, b| a.pool.cmp(&b.pool));
+        records
+    }
 }
 
 pub fn distribution_add(p: &mut PoolStakeDistribution, v: Value) {
@@ -98,7 +132,7 @@ pub fn get_distribution(
         // We're only interested in "group" addresses
         // (i.e. containing a spending key and a stake key).
         match output.address.kind() {
-            Kind::Account(_) | Kind::Multisig(_) => {
+            Kind::Account(_) | Kind::Multisig(_) | Kind::Delegation(_, _) => {
                 // single or multisig account are not present in utxos
                 panic!("internal error: accounts in utxo")
             }
@@ -129,3 +163,69 @@ pub fn get_distribution(
         to_pools: dist,
     }
 }
+
+/// Compute the stake delegated to a single stake pool, the same as its
+/// stake in `get_distribution` but without computing the other pools'
+/// stake. Zero if the pool does not exist.
+pub fn get_pool_stake(
+    accounts: &account::Ledger,
+    dstate: &DelegationState,
+    utxos: &utxo::Ledger<Address>,
+    stake_pool_id: &StakePoolId,
+) -> Value {
+    if !dstate.stake_pool_exists(stake_pool_id) {
+        return Value::zero();
+    }
+    let delegates_to_pool = |account_key: &account::Identifier| {
+        accounts
+            .get_state(account_key)
+            .map(|st| st.delegation().as_ref() == Some(stake_pool_id))
+            .unwrap_or(false)
+    };
+
+    let accounts_stake = accounts
+        .iter()
+        .filter(|(_, st)| st.delegation().as_ref() == Some(stake_pool_id))
+        .map(|(_, st)| st.value());
+    let groups_stake = utxos
+        .values()
+        .filter(|output| match output.address.kind() {
+            Kind::Group(_spending_key, account_key) => {
+                delegates_to_pool(&account_key.clone().into())
+            }
+            _ => false,
+        })
+        .map(|output| output.value);
+    Value::sum(accounts_stake.chain(groups_stake)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::TestResult;
+
+    quickcheck! {
+        fn records_cover_all_pools_and_shares_sum_to_one(stakes: Vec<u32>) -> TestResult {
+            if stakes.is_empty() || stakes.len() > 255 {
+                return TestResult::discard();
+            }
+            let mut distribution = StakeDistribution::empty();
+            for (index, stake) in stakes.iter().enumerate() {
+                distribution.to_pools.insert(
+                    StakePoolId::from([index as u8; 32]),
+                    PoolStakeDistribution {
+                        total_stake: Value(*stake as u64 + 1),
+                    },
+                );
+            }
+
+            let records = distribution.to_records();
+            let covered = records.len() == stakes.len()
+                && records
+                    .iter()
+                    .all(|record| distribution.stake_of(&record.pool) == record.stake);
+            let total_share: f64 = records.iter().map(|record| record.share).sum();
+            TestResult::from_bool(covered && (total_share - 1.0).abs() < 1e-9)
+        }
+    }
+}
@@ -1,7 +1,9 @@
+mod cache;
 mod delegation;
 mod distribution;
 mod role;
 
+pub use cache::*;
 pub use delegation::*;
 pub use distribution::*;
 pub use role::*;
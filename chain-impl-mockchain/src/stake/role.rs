@@ -8,6 +8,9 @@ use chain_core::property;
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct StakePoolId(Hash);
 
+/// Maximum number of owners of a stake pool
+pub const STAKE_POOL_MAX_OWNERS: usize = 255;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StakePoolInfo {
     pub serial: u128,
@@ -16,15 +19,24 @@ pub struct StakePoolInfo {
 }
 
 impl StakePoolInfo {
+    /// The identifier of the pool is the hash of its serialized
+    /// representation
     pub fn to_id(&self) -> StakePoolId {
-        let mut v = Vec::new();
-        v.extend_from_slice(&self.serial.to_be_bytes());
-        for o in &self.owners {
-            v.extend_from_slice(o.as_ref().as_ref())
-        }
-        v.extend_from_slice(self.initial_key.kes_public_key.as_ref());
-        v.extend_from_slice(self.initial_key.vrf_public_key.as_ref());
-        StakePoolId(Hash::hash_bytes(&v))
+        use chain_core::property::Serialize;
+        let bytes = self.serialize_as_vec().unwrap();
+        StakePoolId(Hash::hash_bytes(&bytes))
+    }
+}
+
+impl From<[u8; 32]> for StakePoolId {
+    fn from(bytes: [u8; 32]) -> Self {
+        StakePoolId(Hash::from(bytes))
+    }
+}
+
+impl AsRef<[u8]> for StakePoolId {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
     }
 }
 
@@ -72,7 +84,7 @@ impl property::Serialize for StakePoolInfo {
         codec.put_u128(self.serial)?;
         codec.put_u8(self.owners.len() as u8)?;
         for o in &self.owners {
-            serialize_public_key(o.as_ref(), &mut codec)?;
+            o.serialize(&mut codec)?;
         }
         self.initial_key.serialize(&mut codec)?;
         Ok(())
@@ -123,6 +135,8 @@ impl std::fmt::Display for StakePoolId {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chain_core::property::Serialize;
+    use chain_crypto::{Ed25519, PublicKey};
     use quickcheck::{Arbitrary, Gen};
 
     impl Arbitrary for StakePoolId {
@@ -130,4 +144,27 @@ mod test {
             StakePoolId(Arbitrary::arbitrary(g))
         }
     }
+
+    #[test]
+    pub fn stake_pool_id_known_answer() {
+        let info = StakePoolInfo {
+            serial: 1,
+            owners: vec![account::Identifier::from(
+                PublicKey::<Ed25519>::from_binary(&[1; 32]).unwrap(),
+            )],
+            initial_key: GenesisPraosLeader {
+                kes_public_key: PublicKey::from_binary(&[2; 32]).unwrap(),
+                vrf_public_key: PublicKey::from_binary(&[0; 32]).unwrap(),
+            },
+        };
+        let expected: Hash = "b6e9aa3ed27864fe7122fbbc399a1c0b084944a595843add479df0cb93cbfa5b"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            info.to_id(),
+            StakePoolId(Hash::hash_bytes(&info.serialize_as_vec().unwrap()))
+        );
+        assert_eq!(info.to_id(), StakePoolId(expected));
+    }
 }
@@ -47,7 +47,7 @@ impl AddressData {
 
     pub fn make_input(&self, value: Value, utxo: Option<Entry<Address>>) -> Input {
         match self.address.kind() {
-            Kind::Account { .. } => {
+            Kind::Account { .. } | Kind::Delegation { .. } => {
                 Input::from_account_public_key(self.public_key.clone(), value.clone())
             }
             Kind::Single { .. } | Kind::Group { .. } | Kind::Multisig { .. } => {
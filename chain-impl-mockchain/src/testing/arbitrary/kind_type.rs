@@ -10,7 +10,8 @@ impl Arbitrary for KindTypeWithoutMultisig {
         KindTypeWithoutMultisig(
             iter::from_fn(|| Some(KindType::arbitrary(g)))
                 .filter(|x| match x {
-                    KindType::Multisig => false,
+                    // delegation addresses need a registered stake pool
+                    KindType::Multisig | KindType::Delegation => false,
                     _ => true,
                 })
                 .next()
@@ -39,7 +40,7 @@ impl Arbitrary for KindWithoutMultisig {
         KindWithoutMultisig(
             iter::from_fn(|| Some(Kind::arbitrary(g)))
                 .filter(|x| match x {
-                    Kind::Multisig { .. } => false,
+                    Kind::Multisig { .. } | Kind::Delegation { .. } => false,
                     _ => true,
                 })
                 .next()
@@ -12,7 +12,7 @@ impl Arbitrary for OutputsWithoutMultisig {
         OutputsWithoutMultisig(
             iter::from_fn(|| Some(Output::arbitrary(gen)))
                 .filter(|x| match x.address.1 {
-                    Kind::Multisig { .. } => false,
+                    Kind::Multisig { .. } | Kind::Delegation { .. } => false,
                     _ => true,
                 })
                 .take(n)
@@ -1,11 +1,15 @@
+use crate::account;
 use crate::block::ConsensusVersion;
 use crate::block::HeaderHash;
+use crate::certificate::{Certificate, CertificateContent};
 use crate::config::ConfigParam;
 use crate::ledger::Error;
 use crate::ledger::Ledger;
 use crate::fragment::config::ConfigParams;
 use crate::fragment::Fragment;
+use crate::leadership::genesis::GenesisPraosLeader;
 use crate::milli::Milli;
+use crate::stake::StakePoolInfo;
 use crate::transaction::*;
 use chain_addr::{Address, Discrimination};
 use chain_crypto::*;
@@ -18,6 +22,7 @@ pub struct ConfigBuilder {
     slots_per_epoch: u32,
     active_slots_coeff: Milli,
     discrimination: Discrimination,
+    consensus_version: ConsensusVersion,
 }
 
 impl ConfigBuilder {
@@ -27,6 +32,7 @@ impl ConfigBuilder {
             slots_per_epoch: 21600,
             active_slots_coeff: Milli::HALF,
             discrimination: Discrimination::Test,
+            consensus_version: ConsensusVersion::Bft,
         }
     }
 
@@ -35,6 +41,14 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn with_consensus_version<'a>(
+        &'a mut self,
+        consensus_version: ConsensusVersion,
+    ) -> &'a mut Self {
+        self.consensus_version = consensus_version;
+        self
+    }
+
     pub fn with_slot_duration<'a>(&'a mut self, slot_duration: u8) -> &'a mut Self {
         self.slot_duration = slot_duration;
         self
@@ -53,7 +67,7 @@ impl ConfigBuilder {
     pub fn build(&self) -> ConfigParams {
         let mut ie = ConfigParams::new();
         ie.push(ConfigParam::Discrimination(self.discrimination));
-        ie.push(ConfigParam::ConsensusVersion(ConsensusVersion::Bft));
+        ie.push(ConfigParam::ConsensusVersion(self.consensus_version));
 
         // TODO remove rng: make this creation deterministic
         let leader_prv_key: SecretKey<Ed25519Extended> =
@@ -99,3 +113,35 @@ pub fn create_initial_transactions(outputs: &Vec<Output<Address>>) -> Fragment {
     let authenticator = builder.with_outputs(outputs.to_vec()).authenticate();
     authenticator.as_message()
 }
+
+pub fn create_stake_pool(owners: Vec<account::Identifier>) -> StakePoolInfo {
+    let kes_secret_key: SecretKey<SumEd25519_12> =
+        SecretKey::generate(rand_os::OsRng::new().unwrap());
+    let vrf_secret_key: SecretKey<Curve25519_2HashDH> =
+        SecretKey::generate(rand_os::OsRng::new().unwrap());
+    StakePoolInfo {
+        serial: 0,
+        owners,
+        initial_key: GenesisPraosLeader {
+            kes_public_key: kes_secret_key.to_public(),
+            vrf_public_key: vrf_secret_key.to_public(),
+        },
+    }
+}
+
+// create a certificate fragment without inputs, outputs or witnesses, valid in block0
+// and for a ledger without fees
+pub fn create_certificate(content: CertificateContent) -> Fragment {
+    Fragment::Certificate(AuthenticatedTransaction {
+        transaction: Transaction {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            valid_until: None,
+            extra: Certificate {
+                content,
+                signatures: Vec::new(),
+            },
+        },
+        witnesses: Vec::new(),
+    })
+}
@@ -2,18 +2,24 @@ use super::address::AddressData;
 use crate::{
     account::SpendingCounter,
     block::HeaderHash,
+    date::BlockDate,
     fee::LinearFee,
     fragment::Fragment,
     key::EitherEd25519SecretKey,
     ledger::OutputAddress,
-    transaction::{AuthenticatedTransaction, Input, NoExtra, Output, Transaction, Witness},
+    transaction::{
+        AuthenticatedTransaction, Input, NoExtra, Output, Transaction, TransactionId,
+        TransactionIndex, Witness,
+    },
     txbuilder::{OutputPolicy, TransactionBuilder as Builder},
+    value::Value,
 };
 use chain_addr::{Address, Kind};
 
 pub struct TransactionBuilder {
     inputs: Vec<Input>,
     outputs: Vec<OutputAddress>,
+    valid_until: Option<BlockDate>,
 }
 
 impl TransactionBuilder {
@@ -21,6 +27,7 @@ impl TransactionBuilder {
         TransactionBuilder {
             inputs: Vec::new(),
             outputs: Vec::new(),
+            valid_until: None,
         }
     }
 
@@ -34,6 +41,20 @@ impl TransactionBuilder {
         self
     }
 
+    /// Spend the output of a transaction which is not in the ledger yet,
+    /// such as one applied earlier in the same block.
+    pub fn spend_from_pending<'a>(
+        &'a mut self,
+        prior_txid: TransactionId,
+        index: TransactionIndex,
+        value: Value,
+    ) -> &'a mut Self {
+        let mut builder = Builder::new();
+        builder.spend_from_pending(prior_txid, index, value);
+        self.inputs.extend(builder.tx.inputs);
+        self
+    }
+
     pub fn with_output<'a>(&'a mut self, output: OutputAddress) -> &'a mut Self {
         self.outputs.push(output);
         self
@@ -44,10 +65,16 @@ impl TransactionBuilder {
         self
     }
 
+    pub fn with_valid_until<'a>(&'a mut self, valid_until: BlockDate) -> &'a mut Self {
+        self.valid_until = Some(valid_until);
+        self
+    }
+
     pub fn authenticate(&self) -> TransactionAuthenticator {
         let transaction = Transaction {
             inputs: self.inputs.clone(),
             outputs: self.outputs.clone(),
+            valid_until: self.valid_until,
             extra: NoExtra,
         };
         TransactionAuthenticator::new(transaction)
@@ -60,6 +87,7 @@ impl TransactionBuilder {
         let transaction = Transaction {
             inputs: self.inputs.clone(),
             outputs: self.outputs.clone(),
+            valid_until: self.valid_until,
             extra: NoExtra,
         };
         let tx_builder = Builder::from(transaction);
@@ -120,7 +148,7 @@ impl TransactionAuthenticator {
         addres_data: &AddressData,
     ) -> &'a mut Self {
         match addres_data.address.kind() {
-            Kind::Account(_) => self.with_account_witness(
+            Kind::Account(_) | Kind::Delegation(_, _) => self.with_account_witness(
                 block0,
                 &addres_data.spending_counter.unwrap(),
                 &addres_data.private_key,
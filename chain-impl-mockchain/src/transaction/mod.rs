@@ -21,6 +21,29 @@ pub struct AuthenticatedTransaction<OutAddress, Extra> {
     pub witnesses: Vec<Witness>,
 }
 
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub WitnessesError
+        CountMismatch { expected: usize, actual: usize } = "Transaction has {expected} inputs but {actual} witnesses",
+}
+
+impl<OutAddress, Extra> AuthenticatedTransaction<OutAddress, Extra> {
+    /// Replace all the witnesses of the transaction, one per input in the
+    /// order of the inputs
+    pub fn with_witnesses(self, witnesses: Vec<Witness>) -> Result<Self, WitnessesError> {
+        if witnesses.len() != self.transaction.inputs.len() {
+            return Err(WitnessesError::CountMismatch {
+                expected: self.transaction.inputs.len(),
+                actual: witnesses.len(),
+            });
+        }
+        Ok(AuthenticatedTransaction {
+            transaction: self.transaction,
+            witnesses,
+        })
+    }
+}
+
 impl<Extra: property::Serialize> property::Serialize for AuthenticatedTransaction<Address, Extra> {
     type Error = Extra::Error;
 
@@ -51,6 +74,44 @@ impl<Extra: Readable> Readable for AuthenticatedTransaction<Address, Extra> {
     }
 }
 
+/// A transaction still waiting for its witnesses, for example while
+/// being transported to and from an offline signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedTransaction<OutAddress, Extra> {
+    pub transaction: Transaction<OutAddress, Extra>,
+}
+
+impl<OutAddress, Extra> UnsignedTransaction<OutAddress, Extra> {
+    pub fn new(transaction: Transaction<OutAddress, Extra>) -> Self {
+        UnsignedTransaction { transaction }
+    }
+
+    /// Attach the witnesses made by the signer, in the order of the inputs
+    pub fn attach_witnesses(
+        self,
+        witnesses: Vec<Witness>,
+    ) -> AuthenticatedTransaction<OutAddress, Extra> {
+        AuthenticatedTransaction {
+            transaction: self.transaction,
+            witnesses,
+        }
+    }
+}
+
+impl<Extra: property::Serialize> property::Serialize for UnsignedTransaction<Address, Extra> {
+    type Error = Extra::Error;
+
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Extra::Error> {
+        self.transaction.serialize_unsigned(writer)
+    }
+}
+
+impl<Extra: Readable> Readable for UnsignedTransaction<Address, Extra> {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        Transaction::read_with_header(buf).map(UnsignedTransaction::new)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -98,6 +159,7 @@ mod test {
                 outputs: std::iter::repeat_with(|| Arbitrary::arbitrary(g))
                     .take(num_outputs % 8)
                     .collect(),
+                valid_until: Arbitrary::arbitrary(g),
                 extra: Arbitrary::arbitrary(g),
             }
         }
@@ -1,4 +1,5 @@
 use super::transfer::*;
+use crate::date::BlockDate;
 use crate::key::Hash;
 use crate::value::{Value, ValueError};
 use chain_addr::Address;
@@ -36,6 +37,9 @@ impl Readable for NoExtra {
 pub struct Transaction<OutAddress, Extra> {
     pub inputs: Vec<Input>,
     pub outputs: Vec<Output<OutAddress>>,
+    /// The last date at which the transaction can be included in a block,
+    /// if any
+    pub valid_until: Option<BlockDate>,
     pub extra: Extra,
 }
 
@@ -57,11 +61,21 @@ impl<Extra: Readable> Transaction<Address, Extra> {
     ) -> Result<Self, ReadError> {
         let inputs = read_vec(buf, num_inputs)?;
         let outputs = read_vec(buf, num_outputs)?;
+        let valid_until = match buf.get_u8()? {
+            0 => None,
+            1 => {
+                let epoch = buf.get_u32()?;
+                let slot_id = buf.get_u32()?;
+                Some(BlockDate { epoch, slot_id })
+            }
+            tag => return Err(ReadError::UnknownTag(tag as u32)),
+        };
         let extra = Extra::read(buf)?;
 
         Ok(Transaction {
             inputs,
             outputs,
+            valid_until,
             extra,
         })
     }
@@ -93,6 +107,14 @@ impl<Extra: property::Serialize> Transaction<Address, Extra> {
             output.address.serialize(&mut codec)?;
             output.value.serialize(&mut codec)?;
         }
+        match self.valid_until {
+            None => codec.put_u8(0)?,
+            Some(date) => {
+                codec.put_u8(1)?;
+                codec.put_u32(date.epoch)?;
+                codec.put_u32(date.slot_id)?;
+            }
+        }
         self.extra.serialize(&mut codec)?;
         Ok(())
     }
@@ -112,6 +134,13 @@ impl<Extra: property::Serialize> Transaction<Address, Extra> {
         self.serialize_body(&mut codec.into_inner())
     }
 
+    /// Serialize the transaction without any witness, so it can be
+    /// transported to an offline signer and read back as an
+    /// `UnsignedTransaction`.
+    pub fn serialize_unsigned<W: std::io::Write>(&self, writer: W) -> Result<(), Extra::Error> {
+        self.serialize_with_header(writer)
+    }
+
     pub fn hash(&self) -> TransactionId {
         let mut bytes = Vec::new();
         self.serialize_body(&mut bytes).unwrap();
@@ -142,11 +171,28 @@ impl<Extra: property::Deserialize> Transaction<Address, Extra> {
             outputs.push(Output { address, value });
         }
 
+        let valid_until = match codec.get_u8()? {
+            0 => None,
+            1 => {
+                let epoch = codec.get_u32()?;
+                let slot_id = codec.get_u32()?;
+                Some(BlockDate { epoch, slot_id })
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "invalid transaction validity tag",
+                )
+                .into())
+            }
+        };
+
         let extra = Extra::deserialize(&mut codec)?;
 
         Ok(Transaction {
             inputs,
             outputs,
+            valid_until,
             extra,
         })
     }
@@ -194,6 +240,7 @@ impl<A, Extra> Transaction<A, Extra> {
         Transaction {
             inputs: self.inputs,
             outputs: self.outputs,
+            valid_until: self.valid_until,
             extra: e2,
         }
     }
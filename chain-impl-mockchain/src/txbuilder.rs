@@ -1,8 +1,13 @@
+use crate::account;
 use crate::certificate as cert;
 use crate::fee::FeeAlgorithm;
-use crate::transaction::{self as tx, Balance};
+use crate::multisig;
+use crate::transaction::{self as tx, Balance, InputType};
 use crate::value::{Value, ValueError};
 use chain_addr::Address;
+use chain_crypto::{PublicKey, Signature};
+use rand_core::RngCore;
+use std::collections::BTreeMap;
 use std::{error, fmt};
 
 /// Possible error for the builder.
@@ -11,6 +16,7 @@ pub enum Error {
     TxInvalidNoInput,
     TxInvalidNoOutput,
     TxNotEnoughTotalInput,
+    TxNotEnoughAvailableInput,
     MathErr(ValueError),
 }
 
@@ -20,6 +26,9 @@ impl fmt::Display for Error {
             Error::TxInvalidNoInput => write!(f, "transaction has no inputs"),
             Error::TxInvalidNoOutput => write!(f, "transaction has no outputs"),
             Error::TxNotEnoughTotalInput => write!(f, "not enough input for making transaction"),
+            Error::TxNotEnoughAvailableInput => {
+                write!(f, "not enough available inputs for making transaction")
+            }
             Error::MathErr(v) => write!(f, "error in arithmetics {:?}", v),
         }
     }
@@ -43,6 +52,22 @@ pub enum OutputPolicy {
     Forget,
 }
 
+/// Strategy used to select the inputs of a transaction among the
+/// available ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSelection {
+    /// Select the inputs of highest value first, using as few inputs as
+    /// possible.
+    LargestFirst,
+    /// Select the inputs of lowest value first, consolidating the small
+    /// inputs.
+    SmallestFirst,
+    /// Select random inputs until the target is covered, then keep adding
+    /// random inputs while they bring the selected value closer to twice
+    /// the target, without going over three times the target.
+    RandomImprove,
+}
+
 #[derive(Clone, Debug)]
 /// Transaction builder is an object to construct
 /// a transaction with iterative steps (inputs, outputs)
@@ -56,6 +81,7 @@ impl TransactionBuilder<Address, tx::NoExtra> {
             tx: tx::Transaction {
                 inputs: vec![],
                 outputs: vec![],
+                valid_until: None,
                 extra: tx::NoExtra,
             },
         }
@@ -87,6 +113,23 @@ impl<Extra: Clone> TransactionBuilder<Address, Extra> {
         self.tx.inputs.push(input.clone())
     }
 
+    /// Add an input spending an output of a transaction which is not in
+    /// the ledger yet, such as one applied earlier in the same block.
+    ///
+    /// Each input may extend the size of the required fee.
+    pub fn spend_from_pending(
+        &mut self,
+        prior_txid: tx::TransactionId,
+        index: tx::TransactionIndex,
+        value: Value,
+    ) {
+        self.tx.inputs.push(tx::Input::from_utxo(tx::UtxoPointer {
+            transaction_id: prior_txid,
+            output_index: index,
+            value,
+        }))
+    }
+
     /// Add additional output.
     ///
     /// Each output may extend the size of the required fee.
@@ -103,6 +146,31 @@ impl<Extra: Clone> TransactionBuilder<Address, Extra> {
             .ok_or(ValueError::Overflow)
     }
 
+    /// Estimate the fee of the transaction as `finalize` would make it
+    /// with the given output policy, including the change output it may
+    /// add.
+    pub fn estimate_fee_with_policy<F: FeeAlgorithm<tx::Transaction<Address, Extra>>>(
+        &self,
+        fee_algorithm: F,
+        policy: &OutputPolicy,
+    ) -> Result<Value, ValueError> {
+        let fee = self.estimate_fee(&fee_algorithm)?;
+        let address = match (self.tx.balance(fee)?, policy) {
+            (Balance::Positive(_), OutputPolicy::One(address)) => address,
+            _ => return Ok(fee),
+        };
+        let mut tx = self.tx.clone();
+        tx.outputs.push(tx::Output {
+            address: address.clone(),
+            value: Value(0),
+        });
+        let fee_with_change = fee_algorithm.calculate(&tx).ok_or(ValueError::Overflow)?;
+        match tx.balance(fee_with_change)? {
+            Balance::Positive(_) => Ok(fee_with_change),
+            _ => Ok(fee),
+        }
+    }
+
     /// Get balance including current feee.
     pub fn get_balance<F: FeeAlgorithm<tx::Transaction<Address, Extra>>>(
         &self,
@@ -114,6 +182,153 @@ impl<Extra: Clone> TransactionBuilder<Address, Extra> {
         self.tx.balance(fee)
     }
 
+    /// Select among the candidates the inputs paying for the outputs and
+    /// the fee at the lowest fee, and add them to the transaction.
+    ///
+    /// The fee grows with the number of inputs, so the largest inputs are
+    /// selected first, account inputs before utxo inputs of the same value.
+    /// Since an account can be partially spent, the last selected account
+    /// input is reduced to the value actually needed.
+    pub fn select_inputs_min_fee<F: FeeAlgorithm<tx::Transaction<Address, Extra>>>(
+        &mut self,
+        fee_algorithm: F,
+        candidates: &[tx::Input],
+    ) -> Result<Vec<tx::Input>, Error> {
+        let is_account = |input: &tx::Input| match input.get_type() {
+            InputType::Account => true,
+            InputType::Utxo => false,
+        };
+        let mut candidates: Vec<&tx::Input> = candidates.iter().collect();
+        candidates.sort_by(|a, b| {
+            b.value
+                .cmp(&a.value)
+                .then_with(|| is_account(b).cmp(&is_account(a)))
+        });
+
+        let mut builder = self.clone();
+        let mut selected: Vec<tx::Input> = Vec::new();
+        for input in candidates {
+            match builder.get_balance(&fee_algorithm) {
+                Ok(Balance::Negative(_)) => (),
+                Ok(_) => break,
+                Err(err) => return Err(Error::MathErr(err)),
+            }
+            builder.add_input(input);
+            selected.push(input.clone());
+        }
+
+        let excess = match builder.get_balance(&fee_algorithm) {
+            Ok(Balance::Negative(_)) => return Err(Error::TxNotEnoughTotalInput),
+            Ok(Balance::Zero) => Value::zero(),
+            Ok(Balance::Positive(value)) => value,
+            Err(err) => return Err(Error::MathErr(err)),
+        };
+        let first_selected = builder.tx.inputs.len() - selected.len();
+        if let Some(index) = selected.iter().rposition(|input| is_account(input)) {
+            if excess < selected[index].value {
+                let value = (selected[index].value - excess).map_err(Error::MathErr)?;
+                selected[index].value = value;
+                builder.tx.inputs[first_selected + index].value = value;
+            }
+        }
+
+        *self = builder;
+        Ok(selected)
+    }
+
+    /// Select among the available inputs, following the given strategy,
+    /// enough inputs to pay for `target` and the fee, and add them to the
+    /// transaction. `target` is usually the total value of the outputs.
+    ///
+    /// Returns the selected inputs along with the change left once the
+    /// target and the fee are paid, to be returned with the `OutputPolicy`
+    /// given to `finalize`. The change does not account for the fee of
+    /// the change output itself.
+    pub fn add_inputs_from<F, R>(
+        &mut self,
+        fee_algorithm: F,
+        available: &[tx::Input],
+        target: Value,
+        strategy: InputSelection,
+        rng: &mut R,
+    ) -> Result<(Vec<tx::Input>, Value), Error>
+    where
+        F: FeeAlgorithm<tx::Transaction<Address, Extra>>,
+        R: RngCore,
+    {
+        let mut candidates: Vec<&tx::Input> = available.iter().collect();
+        match strategy {
+            InputSelection::LargestFirst => candidates.sort_by(|a, b| b.value.cmp(&a.value)),
+            InputSelection::SmallestFirst => candidates.sort_by(|a, b| a.value.cmp(&b.value)),
+            InputSelection::RandomImprove => {
+                for i in (1..candidates.len()).rev() {
+                    let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+                    candidates.swap(i, j);
+                }
+            }
+        }
+        let mut candidates = candidates.into_iter();
+
+        let mut builder = self.clone();
+        let mut selected: Vec<tx::Input> = Vec::new();
+        let mut change = loop {
+            if let Some(change) = builder.change_over(&fee_algorithm, target)? {
+                break change;
+            }
+            match candidates.next() {
+                Some(input) => {
+                    builder.add_input(input);
+                    selected.push(input.clone());
+                }
+                None => return Err(Error::TxNotEnoughAvailableInput),
+            }
+        };
+
+        if strategy == InputSelection::RandomImprove {
+            let ideal = 2 * target.0 as u128;
+            let maximum = 3 * target.0 as u128;
+            let distance = |value: u128| (value as i128 - ideal as i128).abs();
+            let mut total: u128 = selected.iter().map(|input| input.value.0 as u128).sum();
+            for input in candidates {
+                let improved = total + input.value.0 as u128;
+                if improved > maximum || distance(improved) >= distance(total) {
+                    break;
+                }
+                let mut improved_builder = builder.clone();
+                improved_builder.add_input(input);
+                match improved_builder.change_over(&fee_algorithm, target)? {
+                    Some(improved_change) => {
+                        builder = improved_builder;
+                        selected.push(input.clone());
+                        change = improved_change;
+                        total = improved;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        *self = builder;
+        Ok((selected, change))
+    }
+
+    /// Get the value of the inputs left once `target` and the fee are
+    /// paid, or `None` if the inputs do not cover them.
+    fn change_over<F: FeeAlgorithm<tx::Transaction<Address, Extra>>>(
+        &self,
+        fee_algorithm: F,
+        target: Value,
+    ) -> Result<Option<Value>, Error> {
+        let fee = self.estimate_fee(fee_algorithm).map_err(Error::MathErr)?;
+        let needed = (target + fee).map_err(Error::MathErr)?;
+        let inputs =
+            Value::sum(self.tx.inputs.iter().map(|input| input.value)).map_err(Error::MathErr)?;
+        if inputs < needed {
+            return Ok(None);
+        }
+        (inputs - needed).map(Some).map_err(Error::MathErr)
+    }
+
     /// Get transaction balance without fee included.
     pub fn get_balance_without_fee(&self) -> Result<Balance, ValueError> {
         self.tx.balance(Value::zero())
@@ -184,14 +399,21 @@ impl<Extra: Clone> TransactionBuilder<Address, Extra> {
     }
 }
 
+/// Transaction finalizer collecting the witness of each input.
+///
+/// The witness of a multisig input can be assembled from the signatures of
+/// its participants, which are kept by input index until the threshold of
+/// the declaration is met.
 pub enum TransactionFinalizer {
     Type1(
         tx::Transaction<Address, tx::NoExtra>,
         Vec<Option<tx::Witness>>,
+        BTreeMap<usize, multisig::WitnessBuilder>,
     ),
     Type2(
         tx::Transaction<Address, cert::Certificate>,
         Vec<Option<tx::Witness>>,
+        BTreeMap<usize, multisig::WitnessBuilder>,
     ),
 }
 
@@ -200,10 +422,15 @@ pub enum GeneratedTransaction {
     Type2(tx::AuthenticatedTransaction<Address, cert::Certificate>),
 }
 
-custom_error! {pub BuildError
-    WitnessOutOfBound { index: usize, max: usize } = "Witness index {index} out of bound (max {max})",
-    WitnessMismatch { index: usize } = "Invalid witness type at index {index}",
-    MissingWitnessAt { index: usize } = "Missing a witness for input at index {index}",
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub BuildError
+        WitnessOutOfBound { index: usize, max: usize } = "Witness index {index} out of bound (max {max})",
+        WitnessMismatch { index: usize } = "Invalid witness type at index {index}",
+        MissingWitnessAt { index: usize } = "Missing a witness for input at index {index}",
+        MultisigNotParticipant { index: usize, participant: usize } = "Key of participant {participant} is not part of the multisig declaration of the input at index {index}",
+        MultisigAlreadySigned { index: usize, participant: usize } = "Participant {participant} already signed the input at index {index}",
+        MultisigNestedSignature { index: usize } = "Signature of a nested multisig declaration for the input at index {index} is not supported",
 }
 
 fn set_witness<Address, Extra>(
@@ -223,6 +450,7 @@ fn set_witness<Address, Extra>(
         (tx::InputType::Utxo, tx::Witness::OldUtxo(_, _)) => (),
         (tx::InputType::Utxo, tx::Witness::Utxo(_)) => (),
         (tx::InputType::Account, tx::Witness::Account(_)) => (),
+        (tx::InputType::Account, tx::Witness::Multisig(_)) => (),
         (_, _) => return Err(BuildError::WitnessMismatch { index }),
     };
 
@@ -230,6 +458,50 @@ fn set_witness<Address, Extra>(
     Ok(())
 }
 
+fn add_multisig_signature<Address, Extra>(
+    transaction: &tx::Transaction<Address, Extra>,
+    witnesses: &mut Vec<Option<tx::Witness>>,
+    signatures: &mut BTreeMap<usize, multisig::WitnessBuilder>,
+    index: usize,
+    declaration: &multisig::Declaration,
+    participant: usize,
+    public_key: PublicKey<account::AccountAlg>,
+    signature: Signature<multisig::WitnessMultisigData, account::AccountAlg>,
+) -> Result<bool, BuildError> {
+    if index >= witnesses.len() {
+        return Err(BuildError::WitnessOutOfBound {
+            index,
+            max: witnesses.len(),
+        });
+    }
+    match transaction.inputs[index].get_type() {
+        tx::InputType::Account => (),
+        tx::InputType::Utxo => return Err(BuildError::WitnessMismatch { index }),
+    };
+
+    let owner = multisig::DeclElement::from_publickey(&public_key);
+    let tree_index = match multisig::Index::from_u8(participant as u8) {
+        Some(i) if declaration.owners.get(participant) == Some(&owner) => {
+            multisig::TreeIndex::D1(i)
+        }
+        _ => return Err(BuildError::MultisigNotParticipant { index, participant }),
+    };
+
+    let builder = signatures
+        .entry(index)
+        .or_insert_with(multisig::WitnessBuilder::new);
+    if builder.contains(&tree_index) {
+        return Err(BuildError::MultisigAlreadySigned { index, participant });
+    }
+    builder.append(tree_index, public_key, signature);
+
+    if builder.len() < declaration.threshold() {
+        return Ok(false);
+    }
+    witnesses[index] = Some(tx::Witness::Multisig(builder.finalize()));
+    Ok(true)
+}
+
 fn get_full_witnesses(witnesses: Vec<Option<tx::Witness>>) -> Result<Vec<tx::Witness>, BuildError> {
     let mut v = Vec::new();
     for (i, w) in witnesses.iter().enumerate() {
@@ -244,37 +516,114 @@ fn get_full_witnesses(witnesses: Vec<Option<tx::Witness>>) -> Result<Vec<tx::Wit
 impl TransactionFinalizer {
     pub fn new_trans(transaction: tx::Transaction<Address, tx::NoExtra>) -> Self {
         let nb_inputs = transaction.inputs.len();
-        TransactionFinalizer::Type1(transaction, vec![None; nb_inputs])
+        TransactionFinalizer::Type1(transaction, vec![None; nb_inputs], BTreeMap::new())
     }
 
     pub fn new_cert(transaction: tx::Transaction<Address, cert::Certificate>) -> Self {
         let nb_inputs = transaction.inputs.len();
-        TransactionFinalizer::Type2(transaction, vec![None; nb_inputs])
+        TransactionFinalizer::Type2(transaction, vec![None; nb_inputs], BTreeMap::new())
     }
 
     pub fn set_witness(&mut self, index: usize, witness: tx::Witness) -> Result<(), BuildError> {
         match self {
-            TransactionFinalizer::Type1(ref t, ref mut w) => set_witness(t, w, index, witness),
-            TransactionFinalizer::Type2(ref t, ref mut w) => set_witness(t, w, index, witness),
+            TransactionFinalizer::Type1(ref t, ref mut w, _) => set_witness(t, w, index, witness),
+            TransactionFinalizer::Type2(ref t, ref mut w, _) => set_witness(t, w, index, witness),
+        }
+    }
+
+    /// Add the signature of one participant of the multisig declaration
+    /// of the input at the given index.
+    ///
+    /// Returns whether the threshold of the declaration is met, in which
+    /// case the witness of the input is assembled from the signatures.
+    pub fn add_multisig_signature(
+        &mut self,
+        index: usize,
+        declaration: &multisig::Declaration,
+        participant: usize,
+        public_key: PublicKey<account::AccountAlg>,
+        signature: Signature<multisig::WitnessMultisigData, account::AccountAlg>,
+    ) -> Result<bool, BuildError> {
+        match self {
+            TransactionFinalizer::Type1(ref t, ref mut w, ref mut s) => add_multisig_signature(
+                t,
+                w,
+                s,
+                index,
+                declaration,
+                participant,
+                public_key,
+                signature,
+            ),
+            TransactionFinalizer::Type2(ref t, ref mut w, ref mut s) => add_multisig_signature(
+                t,
+                w,
+                s,
+                index,
+                declaration,
+                participant,
+                public_key,
+                signature,
+            ),
+        }
+    }
+
+    /// Add the multisig signatures collected by another participant for the
+    /// input at the given index, as obtained with `multisig_signatures`.
+    ///
+    /// Each signature goes through the same checks as when added with
+    /// `add_multisig_signature`. Returns whether the threshold of the
+    /// declaration is met.
+    pub fn import_multisig_signatures(
+        &mut self,
+        index: usize,
+        declaration: &multisig::Declaration,
+        signatures: &multisig::WitnessBuilder,
+    ) -> Result<bool, BuildError> {
+        let mut complete = false;
+        for (tree_index, public_key, signature) in signatures.iter() {
+            let participant = match tree_index {
+                multisig::TreeIndex::D1(i) => i.to_usize(),
+                multisig::TreeIndex::D2(_, _) => {
+                    return Err(BuildError::MultisigNestedSignature { index })
+                }
+            };
+            complete = self.add_multisig_signature(
+                index,
+                declaration,
+                participant,
+                public_key.clone(),
+                signature.clone(),
+            )?;
+        }
+        Ok(complete)
+    }
+
+    /// Get the multisig signatures collected so far for the input at the
+    /// given index, to be passed to the other participants.
+    pub fn multisig_signatures(&self, index: usize) -> Option<&multisig::WitnessBuilder> {
+        match self {
+            TransactionFinalizer::Type1(_, _, s) => s.get(&index),
+            TransactionFinalizer::Type2(_, _, s) => s.get(&index),
         }
     }
 
     pub fn get_txid(&self) -> tx::TransactionId {
         match self {
-            TransactionFinalizer::Type1(t, _) => t.hash(),
-            TransactionFinalizer::Type2(t, _) => t.hash(),
+            TransactionFinalizer::Type1(t, _, _) => t.hash(),
+            TransactionFinalizer::Type2(t, _, _) => t.hash(),
         }
     }
 
     pub fn build(self) -> Result<GeneratedTransaction, BuildError> {
         match self {
-            TransactionFinalizer::Type1(t, witnesses) => {
+            TransactionFinalizer::Type1(t, witnesses, _) => {
                 Ok(GeneratedTransaction::Type1(tx::AuthenticatedTransaction {
                     transaction: t,
                     witnesses: get_full_witnesses(witnesses)?,
                 }))
             }
-            TransactionFinalizer::Type2(t, witnesses) => {
+            TransactionFinalizer::Type2(t, witnesses, _) => {
                 Ok(GeneratedTransaction::Type2(tx::AuthenticatedTransaction {
                     transaction: t,
                     witnesses: get_full_witnesses(witnesses)?,
@@ -320,6 +669,264 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn estimated_fee_is_the_fee_of_the_finalized_tx(
+        inputs: ArbitraryInputs,
+        outputs: ArbitraryOutputs,
+        fee: LinearFee,
+        change_address: Option<Address>,
+    ) -> TestResult {
+        let builder = build_builder(&inputs, &outputs);
+        let policy = match change_address {
+            Some(address) => OutputPolicy::One(address),
+            None => OutputPolicy::Forget,
+        };
+        let estimate = match builder.estimate_fee_with_policy(fee, &policy) {
+            Ok(estimate) => estimate,
+            Err(_) => return TestResult::discard(),
+        };
+        match builder.finalize(fee, policy) {
+            Ok((_, tx)) => TestResult::from_bool(fee.calculate(&tx) == Some(estimate)),
+            Err(_) => TestResult::discard(),
+        }
+    }
+
+    #[quickcheck]
+    fn single_account_input_is_selected_over_many_utxo_inputs(address: Address) -> TestResult {
+        let fee = LinearFee::new(10, 5, 0);
+        let mut builder = TransactionBuilder::new();
+        builder.add_output(address, Value(100));
+        let utxo_input = Input {
+            index_or_account: 0,
+            value: Value(60),
+            input_ptr: [1; INPUT_PTR_SIZE],
+        };
+        let account_input = Input {
+            index_or_account: 0xff,
+            value: Value(200),
+            input_ptr: [2; INPUT_PTR_SIZE],
+        };
+        let candidates = vec![utxo_input.clone(), utxo_input, account_input.clone()];
+
+        let selected = builder.select_inputs_min_fee(fee, &candidates).unwrap();
+
+        // 100 for the output and 10 + 5 * 2 for the fee of 1 input and 1 output
+        let expected = Input {
+            value: Value(120),
+            ..account_input
+        };
+        TestResult::from_bool(
+            selected == vec![expected.clone()]
+                && builder.tx.inputs == vec![expected]
+                && match builder.get_balance(fee) {
+                    Ok(Balance::Zero) => true,
+                    _ => false,
+                },
+        )
+    }
+
+    fn utxo_inputs(values: &[u64]) -> Vec<Input> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Input {
+                index_or_account: 0,
+                value: Value(*value),
+                input_ptr: [i as u8; INPUT_PTR_SIZE],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inputs_are_selected_following_the_strategy() {
+        use rand_core::SeedableRng;
+        let mut rng = rand_chacha::ChaChaRng::from_seed([0; 32]);
+        let fee = LinearFee::new(1, 0, 0);
+        let available = utxo_inputs(&[10, 40, 20, 30]);
+
+        let mut builder = TransactionBuilder::new();
+        let (selected, change) = builder
+            .add_inputs_from(
+                fee,
+                &available,
+                Value(45),
+                InputSelection::LargestFirst,
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(selected, vec![available[1].clone(), available[3].clone()]);
+        assert_eq!(builder.tx.inputs, selected);
+        assert_eq!(change, Value(24));
+
+        let mut builder = TransactionBuilder::new();
+        let (selected, change) = builder
+            .add_inputs_from(
+                fee,
+                &available,
+                Value(45),
+                InputSelection::SmallestFirst,
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(
+            selected,
+            vec![
+                available[0].clone(),
+                available[2].clone(),
+                available[3].clone()
+            ]
+        );
+        assert_eq!(change, Value(14));
+
+        let mut builder = TransactionBuilder::new();
+        match builder.add_inputs_from(
+            fee,
+            &available,
+            Value(100),
+            InputSelection::LargestFirst,
+            &mut rng,
+        ) {
+            Err(Error::TxNotEnoughAvailableInput) => (),
+            result => panic!("unexpected selection result {:?}", result),
+        }
+        assert!(builder.tx.inputs.is_empty());
+    }
+
+    #[quickcheck]
+    fn random_improve_selection_covers_the_target_and_the_fee(
+        values: Vec<u32>,
+        target: u32,
+        seed: u64,
+    ) -> TestResult {
+        use rand_core::SeedableRng;
+        let mut rng_seed = [0; 32];
+        rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+        let mut rng = rand_chacha::ChaChaRng::from_seed(rng_seed);
+        let fee = LinearFee::new(3, 1, 0);
+        let available = utxo_inputs(&values.iter().map(|v| *v as u64).collect::<Vec<_>>());
+
+        let mut builder = TransactionBuilder::new();
+        let (selected, change) = match builder.add_inputs_from(
+            fee,
+            &available,
+            Value(target as u64),
+            InputSelection::RandomImprove,
+            &mut rng,
+        ) {
+            Ok(selection) => selection,
+            Err(Error::TxNotEnoughAvailableInput) => return TestResult::discard(),
+            Err(err) => return TestResult::error(format!("selection failed: {}", err)),
+        };
+
+        let total = Value::sum(selected.iter().map(|input| input.value)).unwrap();
+        let paid = (Value(target as u64) + builder.estimate_fee(fee).unwrap()).unwrap();
+        TestResult::from_bool(builder.tx.inputs == selected && (total - paid) == Ok(change))
+    }
+
+    #[test]
+    fn multisig_witness_is_assembled_once_the_threshold_is_met() {
+        use crate::accounting::account::SpendingCounter;
+        use crate::key::Hash;
+        use chain_core::mempack::{ReadBuf, Readable};
+        use chain_core::property::Serialize;
+        use chain_crypto::SecretKey;
+
+        let keys: Vec<SecretKey<account::AccountAlg>> =
+            iter::repeat_with(|| SecretKey::generate(rand_os::OsRng::new().unwrap()))
+                .take(4)
+                .collect();
+        let declaration = multisig::Declaration {
+            threshold: 2,
+            owners: keys[..3]
+                .iter()
+                .map(|key| multisig::DeclElement::from_publickey(&key.to_public()))
+                .collect(),
+        };
+        let mut builder = TransactionBuilder::new();
+        builder.add_input(&Input {
+            index_or_account: 0xff,
+            value: Value(100),
+            input_ptr: [1; INPUT_PTR_SIZE],
+        });
+        let transaction = builder.unchecked_finalize();
+        let mut finalizer = TransactionFinalizer::new_trans(transaction.clone());
+        let msg = multisig::WitnessMultisigData::new(
+            &Hash::hash_bytes(b"block0"),
+            &finalizer.get_txid(),
+            &SpendingCounter::zero(),
+        );
+        let sign = |finalizer: &mut TransactionFinalizer, participant: usize, key: usize| {
+            finalizer.add_multisig_signature(
+                0,
+                &declaration,
+                participant,
+                keys[key].to_public(),
+                keys[key].sign(&msg),
+            )
+        };
+
+        assert_eq!(sign(&mut finalizer, 0, 0), Ok(false));
+        assert_eq!(
+            sign(&mut finalizer, 0, 0),
+            Err(BuildError::MultisigAlreadySigned {
+                index: 0,
+                participant: 0
+            })
+        );
+        assert_eq!(
+            sign(&mut finalizer, 1, 3),
+            Err(BuildError::MultisigNotParticipant {
+                index: 0,
+                participant: 1
+            })
+        );
+
+        // the signatures collected so far are passed to the next signer
+        let bytes = finalizer
+            .multisig_signatures(0)
+            .unwrap()
+            .serialize_as_vec()
+            .unwrap();
+        let collected = multisig::WitnessBuilder::read(&mut ReadBuf::from(&bytes)).unwrap();
+        assert_eq!(collected.serialize_as_vec().unwrap(), bytes);
+
+        let mut cosigner = TransactionFinalizer::new_trans(transaction);
+        let other_declaration = multisig::Declaration {
+            threshold: 2,
+            owners: keys[1..]
+                .iter()
+                .map(|key| multisig::DeclElement::from_publickey(&key.to_public()))
+                .collect(),
+        };
+        assert_eq!(
+            cosigner.import_multisig_signatures(0, &other_declaration, &collected),
+            Err(BuildError::MultisigNotParticipant {
+                index: 0,
+                participant: 0
+            })
+        );
+        assert_eq!(
+            cosigner.import_multisig_signatures(0, &declaration, &collected),
+            Ok(false)
+        );
+        assert_eq!(
+            cosigner.import_multisig_signatures(0, &declaration, &collected),
+            Err(BuildError::MultisigAlreadySigned {
+                index: 0,
+                participant: 0
+            })
+        );
+
+        assert_eq!(sign(&mut cosigner, 2, 2), Ok(true));
+        match cosigner.build() {
+            Ok(GeneratedTransaction::Type1(signed_tx)) => match &signed_tx.witnesses[..] {
+                [tx::Witness::Multisig(witness)] => assert!(witness.verify(&declaration, &msg)),
+                _ => panic!("expected a single multisig witness"),
+            },
+            _ => panic!("the multisig witness should be complete"),
+        }
+    }
+
     fn build_builder(
         inputs: &ArbitraryInputs,
         outputs: &ArbitraryOutputs,
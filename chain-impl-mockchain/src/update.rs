@@ -1,4 +1,5 @@
 use crate::certificate::{verify_certificate, HasPublicKeys, SignatureRaw};
+use crate::config::Tag;
 use crate::date::BlockDate;
 use crate::fragment::config::ConfigParams;
 use crate::leadership::{bft, genesis::ActiveSlotsCoeffError};
@@ -14,12 +15,24 @@ pub struct UpdateState {
     // Note: we use a BTreeMap to ensure that proposals are processed
     // in a well-defined (sorted) order.
     pub proposals: BTreeMap<UpdateProposalId, UpdateProposalState>,
+    /// Accepted proposals that were not applied during the last enactment,
+    /// with the reason why.
+    pub rejected: BTreeMap<UpdateProposalId, RejectionReason>,
+}
+
+/// Reason why an accepted proposal was not applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// Another proposal enacted at the same time, with more votes or the same
+    /// number of votes but a higher proposal id, changes the same parameter.
+    Conflict { winner: UpdateProposalId, tag: Tag },
 }
 
 impl UpdateState {
     pub fn new() -> Self {
         UpdateState {
             proposals: BTreeMap::new(),
+            rejected: BTreeMap::new(),
         }
     }
 
@@ -108,23 +121,60 @@ impl UpdateState {
         // If we entered a new epoch, then delete expired update
         // proposals and apply accepted update proposals.
         if prev_date.epoch < new_date.epoch {
+            let mut accepted = vec![];
             for (proposal_id, proposal_state) in &self.proposals {
                 // If a majority of BFT leaders voted for the
-                // proposal, then apply it. FIXME: multiple proposals
-                // might become accepted at the same time, in which
-                // case they're currently applied in order of proposal
-                // ID. FIXME: delay the effectuation of the proposal
-                // for some number of epochs.
+                // proposal, then apply it. FIXME: delay the effectuation
+                // of the proposal for some number of epochs.
                 if proposal_state.votes.len() > settings.bft_leaders.len() / 2 {
-                    settings = settings.apply(&proposal_state.proposal.changes)?;
+                    accepted.push((proposal_id, proposal_state));
                     expired_ids.push(proposal_id.clone());
                 } else if proposal_state.proposal_date.epoch + settings.proposal_expiration
-                    > new_date.epoch
+                    < new_date.epoch
                 {
                     expired_ids.push(proposal_id.clone());
                 }
             }
 
+            // Multiple proposals might become accepted at the same time. When
+            // they change the same parameter, the one with the most votes wins,
+            // the highest proposal id breaking ties, and the others are rejected.
+            // Parameters which can be repeated, like adding a BFT leader, do
+            // not conflict.
+            accepted.sort_by(|(id_a, state_a), (id_b, state_b)| {
+                state_b
+                    .votes
+                    .len()
+                    .cmp(&state_a.votes.len())
+                    .then_with(|| id_b.cmp(id_a))
+            });
+
+            let mut changed: Vec<(Tag, UpdateProposalId)> = vec![];
+            let mut rejected = BTreeMap::new();
+            for (proposal_id, proposal_state) in accepted {
+                let tags: Vec<Tag> = proposal_state
+                    .proposal
+                    .changes
+                    .iter()
+                    .map(Tag::from)
+                    .filter(|tag| !tag.is_repeatable())
+                    .collect();
+                let conflict = changed.iter().find(|(tag, _)| tags.contains(tag)).cloned();
+                match conflict {
+                    Some((tag, winner)) => {
+                        rejected.insert(
+                            proposal_id.clone(),
+                            RejectionReason::Conflict { winner, tag },
+                        );
+                    }
+                    None => {
+                        settings = settings.apply(&proposal_state.proposal.changes)?;
+                        changed.extend(tags.into_iter().map(|tag| (tag, proposal_id.clone())));
+                    }
+                }
+            }
+            self.rejected = rejected;
+
             for proposal_id in expired_ids {
                 self.proposals.remove(&proposal_id);
             }
@@ -132,6 +182,41 @@ impl UpdateState {
 
         Ok((self, settings))
     }
+
+    /// Get the proposals that can no longer gather a majority of BFT
+    /// leaders' votes before they expire.
+    ///
+    /// A proposal is stalled when the leaders who did not vote for it yet
+    /// are too few to reach the majority. In the last epoch before its
+    /// expiration, at most one new vote per remaining block is assumed, as
+    /// given by `remaining_blocks_estimate`.
+    pub fn stalled_proposals(
+        &self,
+        settings: &Settings,
+        cur_date: BlockDate,
+        remaining_blocks_estimate: u32,
+    ) -> Vec<UpdateProposalId> {
+        let required = settings.bft_leaders.len() / 2 + 1;
+        self.proposals
+            .iter()
+            .filter(|(_, proposal_state)| {
+                let votes = proposal_state.votes.len();
+                let missing = required.saturating_sub(votes);
+                let mut possible = settings
+                    .bft_leaders
+                    .iter()
+                    .filter(|leader| !proposal_state.votes.contains(*leader))
+                    .count();
+                if proposal_state.proposal_date.epoch + settings.proposal_expiration
+                    <= cur_date.epoch
+                {
+                    possible = possible.min(remaining_blocks_estimate as usize);
+                }
+                missing > possible
+            })
+            .map(|(proposal_id, _)| proposal_id.clone())
+            .collect()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -156,6 +241,7 @@ pub enum Error {
     DuplicateVote(UpdateProposalId, UpdateVoterId),
     ReadOnlySetting,
     BadBftSlotsRatio(crate::milli::Milli),
+    BadSlotsPerEpoch(u32),
     BadConsensusGenesisPraosActiveSlotsCoeff(ActiveSlotsCoeffError),
 }
 impl std::fmt::Display for Error {
@@ -210,6 +296,9 @@ impl std::fmt::Display for Error {
             Error::BadBftSlotsRatio(m) => {
                 write!(f, "Cannot set BFT slots ratio to invalid value {}", m)
             }
+            Error::BadSlotsPerEpoch(n) => {
+                write!(f, "Cannot set the number of slots per epoch to {}", n)
+            }
             Error::BadConsensusGenesisPraosActiveSlotsCoeff(err) => write!(
                 f,
                 "Cannot set consensus genesis praos active slots coefficient: {}",
@@ -414,7 +503,105 @@ impl Readable for SignedUpdateVote {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::config::ConfigParam;
+    use crate::fee::LinearFee;
+    use crate::key::Hash;
     use quickcheck::{Arbitrary, Gen};
+    use std::sync::Arc;
+
+    /// Generate `count` leaders, the first `bft_leaders` of them being the
+    /// BFT leaders of the returned settings
+    fn leaders_settings(count: usize, bft_leaders: usize) -> (Vec<UpdateVoterId>, Settings) {
+        let leaders: Vec<UpdateVoterId> = std::iter::repeat_with(|| {
+            let sk: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
+            bft::LeaderId::from(sk.to_public())
+        })
+        .take(count)
+        .collect();
+        let mut settings = Settings::new();
+        settings.bft_leaders = Arc::new(leaders[..bft_leaders].to_vec());
+        (leaders, settings)
+    }
+
+    fn proposal(change: ConfigParam, voters: &[UpdateVoterId]) -> UpdateProposalState {
+        let mut changes = ConfigParams::new();
+        changes.push(change);
+        UpdateProposalState {
+            proposal: UpdateProposal { changes },
+            proposal_date: BlockDate::first(),
+            votes: voters.iter().cloned().collect(),
+        }
+    }
+
+    fn fee_proposal(fee: LinearFee, voters: &[UpdateVoterId]) -> UpdateProposalState {
+        proposal(ConfigParam::LinearFee(fee), voters)
+    }
+
+    fn add_leader_proposal(
+        leader: &UpdateVoterId,
+        voters: &[UpdateVoterId],
+    ) -> UpdateProposalState {
+        proposal(ConfigParam::AddBftLeader(leader.clone()), voters)
+    }
+
+    #[test]
+    pub fn conflicting_proposals_apply_the_most_voted() {
+        let (leaders, settings) = leaders_settings(5, 5);
+
+        let less_voted_id = Hash::hash_bytes(&[1]);
+        let most_voted_id = Hash::hash_bytes(&[2]);
+        let less_voted_fee = LinearFee::new(1, 1, 1);
+        let most_voted_fee = LinearFee::new(2, 2, 2);
+
+        let mut state = UpdateState::new();
+        state.proposals.insert(
+            less_voted_id.clone(),
+            fee_proposal(less_voted_fee, &leaders[0..3]),
+        );
+        state.proposals.insert(
+            most_voted_id.clone(),
+            fee_proposal(most_voted_fee, &leaders[0..4]),
+        );
+
+        let next_epoch = BlockDate::first().next_epoch();
+        let (state, settings) = state
+            .process_proposals(settings, BlockDate::first(), next_epoch)
+            .unwrap();
+
+        assert_eq!(settings.linear_fees(), most_voted_fee);
+        assert_eq!(
+            state.rejected.get(&less_voted_id),
+            Some(&RejectionReason::Conflict {
+                winner: most_voted_id,
+                tag: Tag::LinearFee,
+            })
+        );
+        assert!(state.proposals.is_empty());
+    }
+
+    #[test]
+    pub fn simultaneous_proposals_of_repeatable_params_do_not_conflict() {
+        let (leaders, settings) = leaders_settings(7, 5);
+
+        let mut state = UpdateState::new();
+        state.proposals.insert(
+            Hash::hash_bytes(&[1]),
+            add_leader_proposal(&leaders[5], &leaders[0..3]),
+        );
+        state.proposals.insert(
+            Hash::hash_bytes(&[2]),
+            add_leader_proposal(&leaders[6], &leaders[0..4]),
+        );
+
+        let next_epoch = BlockDate::first().next_epoch();
+        let (state, settings) = state
+            .process_proposals(settings, BlockDate::first(), next_epoch)
+            .unwrap();
+
+        assert!(state.rejected.is_empty());
+        assert!(settings.bft_leaders.contains(&leaders[5]));
+        assert!(settings.bft_leaders.contains(&leaders[6]));
+    }
 
     impl Arbitrary for UpdateProposal {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -461,4 +648,32 @@ mod test {
             }
         }
     }
+
+    #[test]
+    pub fn proposal_short_of_votes_near_expiry_is_stalled() {
+        let (leaders, mut settings) = leaders_settings(5, 5);
+        settings.proposal_expiration = 1;
+
+        let stalled_id = Hash::hash_bytes(&[1]);
+        let viable_id = Hash::hash_bytes(&[2]);
+        let mut state = UpdateState::new();
+        state.proposals.insert(
+            stalled_id.clone(),
+            fee_proposal(LinearFee::new(1, 1, 1), &leaders[0..1]),
+        );
+        state.proposals.insert(
+            viable_id.clone(),
+            fee_proposal(LinearFee::new(2, 2, 2), &leaders[0..2]),
+        );
+
+        let cur_date = BlockDate::first();
+        assert!(state.stalled_proposals(&settings, cur_date, 1).is_empty());
+
+        let last_epoch = cur_date.next_epoch();
+        assert_eq!(
+            state.stalled_proposals(&settings, last_epoch, 1),
+            vec![stalled_id]
+        );
+        assert!(state.stalled_proposals(&settings, last_epoch, 2).is_empty());
+    }
 }
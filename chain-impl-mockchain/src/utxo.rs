@@ -4,7 +4,9 @@
 //! and each demonination get permanantly consumed by the system once spent.
 //!
 
-use crate::transaction::{Output, TransactionId, TransactionIndex};
+use crate::block::ChainLength;
+use crate::transaction::{Output, TransactionId, TransactionIndex, UtxoPointer};
+use crate::value::{Value, ValueError};
 use std::collections::btree_map;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
@@ -43,12 +45,16 @@ impl From<RemoveError> for Error {
     }
 }
 
-/// Hold all the individual outputs that remain unspent
+/// Hold all the individual outputs that remain unspent, along with the
+/// chain length at which their transaction was added
 #[derive(Clone, PartialEq, Eq)]
-struct TransactionUnspents<OutAddress>(BTreeMap<TransactionIndex, Output<OutAddress>>);
+struct TransactionUnspents<OutAddress>(BTreeMap<TransactionIndex, Output<OutAddress>>, ChainLength);
 
 impl<OutAddress: Clone> TransactionUnspents<OutAddress> {
-    pub fn from_outputs(outs: &[(TransactionIndex, Output<OutAddress>)]) -> Self {
+    pub fn from_outputs(
+        outs: &[(TransactionIndex, Output<OutAddress>)],
+        creation_height: ChainLength,
+    ) -> Self {
         assert!(outs.len() < 255);
         let mut b = BTreeMap::new();
         for (index, output) in outs.iter() {
@@ -56,7 +62,11 @@ impl<OutAddress: Clone> TransactionUnspents<OutAddress> {
             // duplicated index
             if r.is_some() {}
         }
-        TransactionUnspents(b)
+        TransactionUnspents(b, creation_height)
+    }
+
+    fn total_value(&self) -> u128 {
+        self.0.values().map(|output| output.value.0 as u128).sum()
     }
 
     pub fn remove_input(
@@ -67,19 +77,27 @@ impl<OutAddress: Clone> TransactionUnspents<OutAddress> {
         let mut t = self.0.clone();
         match t.remove(&index) {
             None => Err(Error::IndexNotFound),
-            Some(o) => Ok((TransactionUnspents(t), o)),
+            Some(o) => Ok((TransactionUnspents(t, self.1), o)),
         }
     }
 }
 
 /// Ledger of UTXO
+///
+/// The number of unspent outputs and their total value are maintained
+/// along every update, so they can be queried without walking the ledger.
 #[derive(Clone, PartialEq, Eq)]
-pub struct Ledger<OutAddress>(Hamt<DefaultHasher, TransactionId, TransactionUnspents<OutAddress>>);
+pub struct Ledger<OutAddress> {
+    unspents: Hamt<DefaultHasher, TransactionId, TransactionUnspents<OutAddress>>,
+    count: usize,
+    total_value: u128,
+}
 
 pub struct Iter<'a, V> {
     hamt_iter: HamtIter<'a, TransactionId, TransactionUnspents<V>>,
     unspents_iter: Option<(
         &'a TransactionId,
+        ChainLength,
         btree_map::Iter<'a, TransactionIndex, Output<V>>,
     )>,
 }
@@ -95,19 +113,34 @@ pub struct Entry<'a, OutputAddress> {
     pub transaction_id: TransactionId,
     pub output_index: u8,
     pub output: &'a Output<OutputAddress>,
+    pub creation_height: ChainLength,
 }
 
 impl<OutAddress> Ledger<OutAddress> {
+    /// Number of unspent outputs in the ledger
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Total value held by the unspent outputs of the ledger
+    pub fn total_value(&self) -> Result<Value, ValueError> {
+        if self.total_value > u64::max_value() as u128 {
+            Err(ValueError::Overflow)
+        } else {
+            Ok(Value(self.total_value as u64))
+        }
+    }
+
     pub fn iter<'a>(&'a self) -> Iter<'a, OutAddress> {
         Iter {
-            hamt_iter: self.0.iter(),
+            hamt_iter: self.unspents.iter(),
             unspents_iter: None,
         }
     }
 
     pub fn values<'a>(&'a self) -> Values<'a, OutAddress> {
         Values {
-            hamt_iter: self.0.iter(),
+            hamt_iter: self.unspents.iter(),
             unspents_iter: None,
         }
     }
@@ -117,13 +150,37 @@ impl<OutAddress> Ledger<OutAddress> {
         tid: &TransactionId,
         index: &TransactionIndex,
     ) -> Option<Entry<'a, OutAddress>> {
-        self.0
-            .lookup(tid)
-            .and_then(|unspent| unspent.0.get(index))
-            .map(|output| Entry {
+        self.unspents.lookup(tid).and_then(|unspent| {
+            unspent.0.get(index).map(|output| Entry {
                 transaction_id: tid.clone(),
                 output_index: *index,
                 output: output,
+                creation_height: unspent.1,
+            })
+        })
+    }
+
+    /// Get the chain length at which the transaction holding the given
+    /// unspent output was added to the ledger
+    pub fn creation_height(
+        &self,
+        tid: &TransactionId,
+        index: &TransactionIndex,
+    ) -> Option<ChainLength> {
+        self.get(tid, index).map(|entry| entry.creation_height)
+    }
+
+    /// Iterate over the unspent outputs holding less than the given value
+    pub fn dust_utxos<'a>(
+        &'a self,
+        threshold: Value,
+    ) -> impl Iterator<Item = (UtxoPointer, &'a Output<OutAddress>)> + 'a {
+        self.iter()
+            .filter(move |entry| entry.output.value < threshold)
+            .map(|entry| {
+                let pointer =
+                    UtxoPointer::new(entry.transaction_id, entry.output_index, entry.output.value);
+                (pointer, entry.output)
             })
     }
 }
@@ -159,11 +216,11 @@ impl<'a, V> Iterator for Iter<'a, V> {
             None => match self.hamt_iter.next() {
                 None => None,
                 Some(unspent) => {
-                    self.unspents_iter = Some((unspent.0, (unspent.1).0.iter()));
+                    self.unspents_iter = Some((unspent.0, (unspent.1).1, (unspent.1).0.iter()));
                     self.next()
                 }
             },
-            Some((id, o)) => match o.next() {
+            Some((id, creation_height, o)) => match o.next() {
                 None => {
                     self.unspents_iter = None;
                     self.next()
@@ -172,6 +229,7 @@ impl<'a, V> Iterator for Iter<'a, V> {
                     transaction_id: id.clone(),
                     output_index: *x.0,
                     output: x.1,
+                    creation_height: *creation_height,
                 }),
             },
         }
@@ -181,21 +239,33 @@ impl<'a, V> Iterator for Iter<'a, V> {
 impl<OutAddress: Clone> Ledger<OutAddress> {
     /// Create a new empty UTXO Ledger
     pub fn new() -> Self {
-        Ledger(Hamt::new())
+        Ledger {
+            unspents: Hamt::new(),
+            count: 0,
+            total_value: 0,
+        }
     }
 
-    /// Add new outputs associated with a specific transaction
+    /// Add new outputs associated with a specific transaction, added to
+    /// the chain at the given chain length
     ///
     /// Error if the transaction already exist
     pub fn add(
         &self,
         tid: &TransactionId,
         outs: &[(TransactionIndex, Output<OutAddress>)],
+        creation_height: ChainLength,
     ) -> Result<Self, Error> {
         assert!(outs.len() < 255);
-        let b = TransactionUnspents::from_outputs(outs);
-        let next = self.0.insert(tid.clone(), b)?;
-        Ok(Ledger(next))
+        let b = TransactionUnspents::from_outputs(outs, creation_height);
+        let count = self.count + b.0.len();
+        let total_value = self.total_value + b.total_value();
+        let unspents = self.unspents.insert(tid.clone(), b)?;
+        Ok(Ledger {
+            unspents,
+            count,
+            total_value,
+        })
     }
 
     /// Spend a specific index from the transaction
@@ -205,16 +275,13 @@ impl<OutAddress: Clone> Ledger<OutAddress> {
         tid: &TransactionId,
         index: TransactionIndex,
     ) -> Result<(Self, Output<OutAddress>), Error> {
-        let (treemap, output) = match self.0.lookup(tid) {
+        let (treemap, output) = match self.unspents.lookup(tid) {
             None => Err(Error::TransactionNotFound),
             Some(out) => out.remove_input(index),
         }?;
+        let spent_value = output.value.0 as u128;
 
-        if treemap.0.len() == 0 {
-            Ok((Ledger(self.0.remove(tid)?), output))
-        } else {
-            Ok((Ledger(self.0.replace(tid, treemap)?.0), output))
-        }
+        Ok((self.replace_unspents(tid, treemap, 1, spent_value)?, output))
     }
 
     pub fn remove_multiple(
@@ -222,7 +289,7 @@ impl<OutAddress: Clone> Ledger<OutAddress> {
         tid: &TransactionId,
         indices: &[TransactionIndex],
     ) -> Result<(Self, Vec<Output<OutAddress>>), Error> {
-        let (treemap, outputs) = match self.0.lookup(tid) {
+        let (treemap, outputs) = match self.unspents.lookup(tid) {
             None => Err(Error::TransactionNotFound),
             Some(out) => {
                 let mut treemap = out.clone();
@@ -235,28 +302,180 @@ impl<OutAddress: Clone> Ledger<OutAddress> {
                 Ok((treemap, outputs))
             }
         }?;
+        let spent_value = outputs.iter().map(|output| output.value.0 as u128).sum();
 
-        if treemap.0.len() == 0 {
-            Ok((Ledger(self.0.remove(tid)?), outputs))
+        Ok((
+            self.replace_unspents(tid, treemap, outputs.len(), spent_value)?,
+            outputs,
+        ))
+    }
+
+    /// Replace the unspent outputs of a known transaction by the remaining
+    /// ones, after `spent_count` outputs worth `spent_value` were spent
+    fn replace_unspents(
+        &self,
+        tid: &TransactionId,
+        treemap: TransactionUnspents<OutAddress>,
+        spent_count: usize,
+        spent_value: u128,
+    ) -> Result<Self, Error> {
+        let unspents = if treemap.0.len() == 0 {
+            self.unspents.remove(tid)?
         } else {
-            Ok((Ledger(self.0.replace(tid, treemap)?.0), outputs))
+            self.unspents.replace(tid, treemap)?.0
+        };
+        Ok(Ledger {
+            unspents,
+            count: self.count - spent_count,
+            total_value: self.total_value - spent_value,
+        })
+    }
+
+    /// Get the unspent outputs of a transaction, along with the chain
+    /// length at which it was added, if the transaction is known
+    pub(crate) fn unspents(
+        &self,
+        tid: &TransactionId,
+    ) -> Option<(ChainLength, Vec<(TransactionIndex, Output<OutAddress>)>)> {
+        self.unspents.lookup(tid).map(|unspents| {
+            let outputs = unspents
+                .0
+                .iter()
+                .map(|(index, output)| (*index, output.clone()))
+                .collect();
+            (unspents.1, outputs)
+        })
+    }
+
+    /// Set the unspent outputs of a transaction back to the given ones, or
+    /// forget the transaction if there are none
+    pub(crate) fn restore(
+        &self,
+        tid: &TransactionId,
+        unspents: Option<(ChainLength, Vec<(TransactionIndex, Output<OutAddress>)>)>,
+    ) -> Self {
+        let mut ledger = match self.unspents.lookup(tid) {
+            None => self.clone(),
+            Some(current) => Ledger {
+                unspents: self.unspents.remove(tid).expect("transaction is known"),
+                count: self.count - current.0.len(),
+                total_value: self.total_value - current.total_value(),
+            },
+        };
+        if let Some((creation_height, outputs)) = unspents {
+            ledger = ledger
+                .add(tid, &outputs, creation_height)
+                .expect("transaction was removed");
         }
+        ledger
     }
 }
 
 impl<OutAddress: Clone>
-    std::iter::FromIterator<(TransactionId, Vec<(TransactionIndex, Output<OutAddress>)>)>
-    for Ledger<OutAddress>
+    std::iter::FromIterator<(
+        TransactionId,
+        ChainLength,
+        Vec<(TransactionIndex, Output<OutAddress>)>,
+    )> for Ledger<OutAddress>
 {
     fn from_iter<
-        I: IntoIterator<Item = (TransactionId, Vec<(TransactionIndex, Output<OutAddress>)>)>,
+        I: IntoIterator<
+            Item = (
+                TransactionId,
+                ChainLength,
+                Vec<(TransactionIndex, Output<OutAddress>)>,
+            ),
+        >,
     >(
         iter: I,
     ) -> Self {
         let mut ledger = Ledger::new();
-        for (tid, outputs) in iter {
-            ledger = ledger.add(&tid, &outputs).unwrap();
+        for (tid, creation_height, outputs) in iter {
+            ledger = ledger.add(&tid, &outputs, creation_height).unwrap();
         }
         ledger
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::Hash;
+    use crate::testing::address::AddressData;
+    use chain_addr::{Address, Discrimination};
+
+    #[test]
+    pub fn dust_utxos_are_the_outputs_below_the_threshold() {
+        let address = AddressData::utxo(Discrimination::Test).address;
+        let output = |value| Output::from_address(address.clone(), Value(value));
+        let first = Hash::hash_bytes(&[1]);
+        let second = Hash::hash_bytes(&[2]);
+        let ledger: Ledger<Address> = Ledger::new()
+            .add(&first, &[(0, output(5)), (1, output(100))], ChainLength(0))
+            .unwrap()
+            .add(&second, &[(0, output(10)), (3, output(9))], ChainLength(1))
+            .unwrap();
+
+        let mut dust: Vec<UtxoPointer> = ledger
+            .dust_utxos(Value(10))
+            .map(|(pointer, output)| {
+                assert_eq!(pointer.value, output.value);
+                pointer
+            })
+            .collect();
+        dust.sort();
+        let mut expected = vec![
+            UtxoPointer::new(first, 0, Value(5)),
+            UtxoPointer::new(second, 3, Value(9)),
+        ];
+        expected.sort();
+        assert_eq!(dust, expected);
+    }
+
+    #[test]
+    pub fn cached_count_and_total_value_match_the_unspent_outputs() {
+        fn assert_cache_matches(ledger: &Ledger<Address>) {
+            assert_eq!(ledger.count(), ledger.iter().count());
+            assert_eq!(
+                ledger.total_value().unwrap(),
+                Value::sum(ledger.values().map(|output| output.value)).unwrap()
+            );
+        }
+
+        let address = AddressData::utxo(Discrimination::Test).address;
+        let output = |value| Output::from_address(address.clone(), Value(value));
+        let first = Hash::hash_bytes(&[1]);
+        let second = Hash::hash_bytes(&[2]);
+        let third = Hash::hash_bytes(&[3]);
+
+        let ledger: Ledger<Address> = Ledger::new();
+        assert_cache_matches(&ledger);
+        let ledger = ledger
+            .add(&first, &[(0, output(5)), (1, output(100))], ChainLength(0))
+            .unwrap()
+            .add(&second, &[(0, output(10)), (3, output(9))], ChainLength(1))
+            .unwrap()
+            .add(&third, &[(2, output(42))], ChainLength(1))
+            .unwrap();
+        assert_cache_matches(&ledger);
+        assert_eq!(ledger.count(), 5);
+        assert_eq!(ledger.total_value().unwrap(), Value(166));
+
+        let (ledger, _) = ledger.remove(&first, 1).unwrap();
+        assert_cache_matches(&ledger);
+        let (ledger, _) = ledger.remove(&third, 2).unwrap();
+        assert_cache_matches(&ledger);
+        let previous = ledger.unspents(&second);
+        let (ledger, _) = ledger.remove_multiple(&second, &[0, 3]).unwrap();
+        assert_cache_matches(&ledger);
+        assert_eq!(ledger.count(), 1);
+        assert_eq!(ledger.total_value().unwrap(), Value(5));
+
+        let ledger = ledger.restore(&second, previous);
+        assert_cache_matches(&ledger);
+        let ledger = ledger.restore(&first, None);
+        assert_cache_matches(&ledger);
+        assert_eq!(ledger.count(), 2);
+        assert_eq!(ledger.total_value().unwrap(), Value(19));
+    }
+}
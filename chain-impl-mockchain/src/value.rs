@@ -34,6 +34,23 @@ impl Value {
             .map(Value)
             .ok_or(ValueError::NegativeAmount)
     }
+
+    /// Split the value into one share per weight, each proportional to its
+    /// weight and rounded down. What remains from the rounding goes to the
+    /// first share, so the shares always sum back to the value.
+    pub fn split_proportionally(self, weights: &[u64]) -> Result<Vec<Value>, ValueError> {
+        let total_weight: u128 = weights.iter().map(|weight| *weight as u128).sum();
+        if total_weight == 0 {
+            return Err(ValueError::SplitWithoutWeight);
+        }
+        let mut shares: Vec<Value> = weights
+            .iter()
+            .map(|weight| Value((self.0 as u128 * *weight as u128 / total_weight) as u64))
+            .collect();
+        let remainder = (self - Value::sum(shares.iter().cloned())?)?;
+        shares[0] = (shares[0] + remainder)?;
+        Ok(shares)
+    }
 }
 
 custom_error! {
@@ -41,6 +58,7 @@ custom_error! {
     pub ValueError
         NegativeAmount = "Value cannot be negative",
         Overflow = "Value overflowed its maximum value",
+        SplitWithoutWeight = "Value cannot be split over weights summing to zero",
 }
 
 impl ops::Add for Value {
@@ -94,3 +112,39 @@ impl std::fmt::Display for Value {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::TestResult;
+
+    quickcheck! {
+        fn split_proportionally_sums_back_to_the_value(value: Value, weights: Vec<u64>) -> TestResult {
+            match value.split_proportionally(&weights) {
+                Err(ValueError::SplitWithoutWeight) => {
+                    TestResult::from_bool(weights.iter().all(|weight| *weight == 0))
+                }
+                Err(error) => TestResult::error(format!("unexpected error: {:?}", error)),
+                Ok(shares) => TestResult::from_bool(
+                    shares.len() == weights.len() && Value::sum(shares.into_iter()) == Ok(value),
+                ),
+            }
+        }
+    }
+
+    #[test]
+    pub fn split_proportionally_gives_the_remainder_to_the_first_share() {
+        assert_eq!(
+            Value(10).split_proportionally(&[1, 1, 1]),
+            Ok(vec![Value(4), Value(3), Value(3)])
+        );
+        assert_eq!(
+            Value(100).split_proportionally(&[0, 3, 1]),
+            Ok(vec![Value(0), Value(75), Value(25)])
+        );
+        assert_eq!(
+            Value(100).split_proportionally(&[0, 0]),
+            Err(ValueError::SplitWithoutWeight)
+        );
+    }
+}
@@ -19,8 +19,18 @@ pub struct EpochPosition {
 
 /// Describe a new era, which start at epoch_start and is associated
 /// to a specific slot. Each epoch have a constant number of slots on a given time era.
+///
+/// The era can be extended with segments changing the number of slots per
+/// epoch from a given epoch onward, the earlier epochs keeping their number
+/// of slots.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TimeEra {
+    segments: Vec<EraSegment>,
+}
+
+/// Part of an era with a constant number of slots per epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EraSegment {
     epoch_start: Epoch,
     slot_start: Slot,
     slots_per_epoch: u32,
@@ -30,15 +40,71 @@ impl TimeEra {
     /// Set a new era to start on slot_start at epoch_start for a given slots per epoch.
     pub fn new(slot_start: Slot, epoch_start: Epoch, slots_per_epoch: u32) -> Self {
         TimeEra {
+            segments: vec![EraSegment {
+                epoch_start,
+                slot_start,
+                slots_per_epoch,
+            }],
+        }
+    }
+
+    fn last_segment(&self) -> &EraSegment {
+        self.segments.last().expect("era has at least one segment")
+    }
+
+    /// Get the segment an epoch belongs to, or the first one if the epoch
+    /// is before the beginning of this era
+    fn segment_of_epoch(&self, epoch: Epoch) -> &EraSegment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.epoch_start <= epoch)
+            .unwrap_or(&self.segments[0])
+    }
+
+    /// retrieve the number of slots in an epoch of the latest segment of
+    /// this era
+    pub fn slots_per_epoch(&self) -> u32 {
+        self.last_segment().slots_per_epoch
+    }
+
+    /// retrieve the number of slots in the given epoch
+    pub fn slots_per_epoch_at(&self, epoch: Epoch) -> u32 {
+        self.segment_of_epoch(epoch).slots_per_epoch
+    }
+
+    /// Change the number of slots per epoch, starting from the given epoch
+    ///
+    /// # Panics
+    ///
+    /// If the epoch is not after the start of the latest segment of this era
+    pub fn add_segment(&self, epoch_start: Epoch, slots_per_epoch: u32) -> Self {
+        assert!(epoch_start > self.last_segment().epoch_start);
+        let slot_start = self.from_era_to_slot(EpochPosition {
+            epoch: epoch_start,
+            slot: EpochSlotOffset(0),
+        });
+        let mut segments = self.segments.clone();
+        segments.push(EraSegment {
             epoch_start,
             slot_start,
             slots_per_epoch,
-        }
+        });
+        TimeEra { segments }
     }
 
-    /// retrieve the number of slots in an epoch during a given Epoch
-    pub fn slots_per_epoch(&self) -> u32 {
-        self.slots_per_epoch
+    /// Remove the segments starting after the given epoch
+    pub fn truncate(&self, epoch: Epoch) -> Self {
+        let mut segments: Vec<_> = self
+            .segments
+            .iter()
+            .filter(|segment| segment.epoch_start <= epoch)
+            .cloned()
+            .collect();
+        if segments.is_empty() {
+            segments.push(self.segments[0].clone());
+        }
+        TimeEra { segments }
     }
 
     /// Try to return the epoch/inner-epoch-slot associated.
@@ -46,26 +112,30 @@ impl TimeEra {
     /// If the slot in parameter is before the beginning of this era, then
     /// None is returned.
     pub fn from_slot_to_era(&self, slot: Slot) -> Option<EpochPosition> {
-        if slot < self.slot_start {
-            return None;
-        }
-        let slot_era_offset = slot.0 - self.slot_start.0;
-        let spe = self.slots_per_epoch as u64;
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.slot_start <= slot)?;
+        let slot_era_offset = slot.0 - segment.slot_start.0;
+        let spe = segment.slots_per_epoch as u64;
         let epoch_offset = (slot_era_offset / spe) as u32;
         let slot_offset = (slot_era_offset % spe) as u32;
         Some(EpochPosition {
-            epoch: Epoch(self.epoch_start.0 + epoch_offset),
+            epoch: Epoch(segment.epoch_start.0 + epoch_offset),
             slot: EpochSlotOffset(slot_offset),
         })
     }
 
     /// Convert an epoch position into a flat slot
     pub fn from_era_to_slot(&self, pos: EpochPosition) -> Slot {
-        assert!(pos.epoch >= self.epoch_start);
-        assert!(pos.slot.0 < self.slots_per_epoch);
+        let segment = self.segment_of_epoch(pos.epoch);
+        assert!(pos.epoch >= segment.epoch_start);
+        assert!(pos.slot.0 < segment.slots_per_epoch);
 
-        let slot_offset = (pos.epoch.0 as u64) * (self.slots_per_epoch as u64) + pos.slot.0 as u64;
-        Slot(self.slot_start.0 + slot_offset)
+        let epoch_offset = (pos.epoch.0 - segment.epoch_start.0) as u64;
+        let slot_offset = epoch_offset * (segment.slots_per_epoch as u64) + pos.slot.0 as u64;
+        Slot(segment.slot_start.0 + slot_offset)
     }
 }
 
@@ -126,4 +196,36 @@ mod test {
             }
         );
     }
+
+    #[test]
+    pub fn added_segment_keeps_earlier_epochs() {
+        let era = TimeEra::new(Slot(10), Epoch(0), 4).add_segment(Epoch(2), 10);
+
+        assert_eq!(era.slots_per_epoch(), 10);
+        assert_eq!(era.slots_per_epoch_at(Epoch(1)), 4);
+        assert_eq!(era.slots_per_epoch_at(Epoch(2)), 10);
+
+        // epochs 0 and 1 take 4 slots each, so epoch 2 starts at slot 18
+        let before = EpochPosition {
+            epoch: Epoch(1),
+            slot: EpochSlotOffset(3),
+        };
+        let boundary = EpochPosition {
+            epoch: Epoch(2),
+            slot: EpochSlotOffset(0),
+        };
+        let after = EpochPosition {
+            epoch: Epoch(3),
+            slot: EpochSlotOffset(5),
+        };
+        assert_eq!(era.from_era_to_slot(before), Slot(17));
+        assert_eq!(era.from_era_to_slot(boundary), Slot(18));
+        assert_eq!(era.from_era_to_slot(after), Slot(33));
+        for pos in vec![before, boundary, after] {
+            assert_eq!(era.from_slot_to_era(era.from_era_to_slot(pos)), Some(pos));
+        }
+        assert_eq!(era.from_slot_to_era(Slot(9)), None);
+
+        assert_eq!(era.truncate(Epoch(1)), TimeEra::new(Slot(10), Epoch(0), 4));
+    }
 }
@@ -8,6 +8,12 @@ use std::time::{Duration, SystemTime};
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Slot(pub(crate) u64);
 
+impl From<u64> for Slot {
+    fn from(s: u64) -> Slot {
+        Slot(s)
+    }
+}
+
 impl From<Slot> for u64 {
     fn from(s: Slot) -> u64 {
         s.0